@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+DROP TRIGGER products_variants_fts_after_delete;
+DROP TRIGGER products_variants_fts_after_update;
+DROP TRIGGER products_variants_fts_after_insert;
+DROP TRIGGER products_fts_after_delete;
+DROP TRIGGER products_fts_after_update;
+DROP TRIGGER products_fts_after_insert;
+DROP TABLE products_fts;
@@ -0,0 +1,55 @@
+-- Full-text index over product names and their variant values.
+-- The rowid of each entry is the id of the product it indexes.
+CREATE VIRTUAL TABLE products_fts USING fts5(
+   name,
+   variant_values,
+   tokenize = 'porter unicode61 remove_diacritics 2',
+   prefix = '2 3'
+);
+
+-- Matches in the name weigh ten times more than matches in variant values
+INSERT INTO products_fts(products_fts, rank) VALUES('rank', 'bm25(10.0, 1.0)');
+
+INSERT INTO products_fts(rowid, name, variant_values)
+SELECT products.id, products.name, COALESCE(
+   (SELECT group_concat(value, ' ') FROM products_variants WHERE products_variants.product_id = products.id),
+   ''
+)
+FROM products;
+
+CREATE TRIGGER products_fts_after_insert AFTER INSERT ON products BEGIN
+   INSERT INTO products_fts(rowid, name, variant_values) VALUES (new.id, new.name, '');
+END;
+
+CREATE TRIGGER products_fts_after_update AFTER UPDATE OF name ON products BEGIN
+   UPDATE products_fts SET name = new.name WHERE rowid = new.id;
+END;
+
+CREATE TRIGGER products_fts_after_delete AFTER DELETE ON products BEGIN
+   DELETE FROM products_fts WHERE rowid = old.id;
+END;
+
+CREATE TRIGGER products_variants_fts_after_insert AFTER INSERT ON products_variants BEGIN
+   UPDATE products_fts SET variant_values = COALESCE(
+      (SELECT group_concat(value, ' ') FROM products_variants WHERE product_id = new.product_id),
+      ''
+   ) WHERE rowid = new.product_id;
+END;
+
+CREATE TRIGGER products_variants_fts_after_update AFTER UPDATE ON products_variants BEGIN
+   UPDATE products_fts SET variant_values = COALESCE(
+      (SELECT group_concat(value, ' ') FROM products_variants WHERE product_id = old.product_id),
+      ''
+   ) WHERE rowid = old.product_id;
+   UPDATE products_fts SET variant_values = COALESCE(
+      (SELECT group_concat(value, ' ') FROM products_variants WHERE product_id = new.product_id),
+      ''
+   ) WHERE rowid = new.product_id;
+END;
+
+CREATE TRIGGER products_variants_fts_after_delete AFTER DELETE ON products_variants BEGIN
+   UPDATE products_fts SET variant_values = COALESCE(
+      (SELECT group_concat(value, ' ') FROM products_variants WHERE product_id = old.product_id),
+      ''
+   ) WHERE rowid = old.product_id;
+END;
@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+DROP TRIGGER products_variants_words_after_delete;
+DROP TRIGGER products_variants_words_after_update;
+DROP TRIGGER products_variants_words_after_insert;
+DROP TRIGGER products_words_after_delete;
+DROP TRIGGER products_words_after_update;
+DROP TRIGGER products_words_after_insert;
+DROP TABLE products_words_vocab;
+DROP TABLE products_words;
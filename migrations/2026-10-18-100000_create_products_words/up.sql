@@ -0,0 +1,49 @@
+-- Unstemmed copy of the full-text index. Its vocabulary holds the words as
+-- written in the catalog, which typos in searches are corrected against.
+CREATE VIRTUAL TABLE products_words USING fts5(
+   name,
+   variant_values,
+   tokenize = 'unicode61 remove_diacritics 2'
+);
+
+CREATE VIRTUAL TABLE products_words_vocab USING fts5vocab(products_words, 'row');
+
+INSERT INTO products_words(rowid, name, variant_values)
+SELECT rowid, name, variant_values FROM products_fts;
+
+CREATE TRIGGER products_words_after_insert AFTER INSERT ON products BEGIN
+   INSERT INTO products_words(rowid, name, variant_values) VALUES (new.id, new.name, '');
+END;
+
+CREATE TRIGGER products_words_after_update AFTER UPDATE OF name ON products BEGIN
+   UPDATE products_words SET name = new.name WHERE rowid = new.id;
+END;
+
+CREATE TRIGGER products_words_after_delete AFTER DELETE ON products BEGIN
+   DELETE FROM products_words WHERE rowid = old.id;
+END;
+
+CREATE TRIGGER products_variants_words_after_insert AFTER INSERT ON products_variants BEGIN
+   UPDATE products_words SET variant_values = COALESCE(
+      (SELECT group_concat(value, ' ') FROM products_variants WHERE product_id = new.product_id),
+      ''
+   ) WHERE rowid = new.product_id;
+END;
+
+CREATE TRIGGER products_variants_words_after_update AFTER UPDATE ON products_variants BEGIN
+   UPDATE products_words SET variant_values = COALESCE(
+      (SELECT group_concat(value, ' ') FROM products_variants WHERE product_id = old.product_id),
+      ''
+   ) WHERE rowid = old.product_id;
+   UPDATE products_words SET variant_values = COALESCE(
+      (SELECT group_concat(value, ' ') FROM products_variants WHERE product_id = new.product_id),
+      ''
+   ) WHERE rowid = new.product_id;
+END;
+
+CREATE TRIGGER products_variants_words_after_delete AFTER DELETE ON products_variants BEGIN
+   UPDATE products_words SET variant_values = COALESCE(
+      (SELECT group_concat(value, ' ') FROM products_variants WHERE product_id = old.product_id),
+      ''
+   ) WHERE rowid = old.product_id;
+END;
@@ -0,0 +1,11 @@
+CREATE TABLE synonym_groups (
+   id INTEGER PRIMARY KEY NOT NULL
+);
+
+-- A term belongs to at most one group; terms are stored lowercase
+CREATE TABLE synonyms (
+   id INTEGER PRIMARY KEY NOT NULL,
+   synonym_group_id INTEGER NOT NULL,
+   term VARCHAR NOT NULL UNIQUE,
+   FOREIGN KEY(synonym_group_id) REFERENCES synonym_groups(id) ON DELETE CASCADE
+);
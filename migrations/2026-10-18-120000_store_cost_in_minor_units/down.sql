@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE products DROP COLUMN currency;
+
+ALTER TABLE products ADD COLUMN cost_major DOUBLE NOT NULL DEFAULT 0;
+UPDATE products SET cost_major = cost / 100.0;
+ALTER TABLE products DROP COLUMN cost;
+ALTER TABLE products RENAME COLUMN cost_major TO cost;
@@ -0,0 +1,9 @@
+-- Costs are stored as an integer amount of minor units (cents) of their
+-- currency, so that they never suffer from floating point error.
+ALTER TABLE products ADD COLUMN cost_minor BIGINT NOT NULL DEFAULT 0;
+UPDATE products SET cost_minor = CAST(ROUND(cost * 100) AS INTEGER);
+ALTER TABLE products DROP COLUMN cost;
+ALTER TABLE products RENAME COLUMN cost_minor TO cost;
+
+-- ISO 4217 code of the currency of `cost`
+ALTER TABLE products ADD COLUMN currency VARCHAR(3) NOT NULL DEFAULT 'EUR';
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE exchange_rates;
+DROP TABLE product_prices;
@@ -0,0 +1,20 @@
+-- Explicit prices of a product in currencies other than the one of its cost,
+-- in minor units of `currency`
+CREATE TABLE product_prices (
+   id INTEGER PRIMARY KEY NOT NULL,
+   product_id INTEGER NOT NULL,
+   currency VARCHAR(3) NOT NULL,
+   amount BIGINT NOT NULL,
+   UNIQUE(product_id, currency),
+   FOREIGN KEY(product_id) REFERENCES products(id) ON DELETE CASCADE
+);
+
+-- How much of `quote_currency` one unit of `base_currency` buys, as a fixed
+-- point number with 6 decimals
+CREATE TABLE exchange_rates (
+   id INTEGER PRIMARY KEY NOT NULL,
+   base_currency VARCHAR(3) NOT NULL,
+   quote_currency VARCHAR(3) NOT NULL,
+   rate BIGINT NOT NULL,
+   UNIQUE(base_currency, quote_currency)
+);
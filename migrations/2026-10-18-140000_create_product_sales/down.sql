@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE product_sales;
+ALTER TABLE products DROP COLUMN compare_at_cost;
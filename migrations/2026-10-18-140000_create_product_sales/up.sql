@@ -0,0 +1,16 @@
+-- Original price shown struck through next to the cost, in minor units
+ALTER TABLE products ADD COLUMN compare_at_cost BIGINT;
+
+-- A sale prices a product at `cost` from `starts_at` (included) until
+-- `ends_at` (excluded), both in UTC
+CREATE TABLE product_sales (
+   id INTEGER PRIMARY KEY NOT NULL,
+   product_id INTEGER NOT NULL,
+   cost BIGINT NOT NULL,
+   starts_at TIMESTAMP NOT NULL,
+   ends_at TIMESTAMP NOT NULL,
+   CHECK(starts_at < ends_at),
+   FOREIGN KEY(product_id) REFERENCES products(id) ON DELETE CASCADE
+);
+
+CREATE INDEX product_sales_product_id ON product_sales(product_id, starts_at);
@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+DROP TRIGGER product_price_changes_after_price_delete;
+DROP TRIGGER product_price_changes_after_price_update;
+DROP TRIGGER product_price_changes_after_price_insert;
+DROP TRIGGER product_price_changes_after_update;
+DROP TRIGGER product_price_changes_after_insert;
+DROP TABLE product_price_changes;
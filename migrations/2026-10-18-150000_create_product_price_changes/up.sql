@@ -0,0 +1,48 @@
+-- Every cost a product ever had, from `changed_at` (UTC) until the next change
+-- in the same currency. Rows in another currency than the one of the product
+-- are its explicit prices, `cost` being null from when one was removed
+CREATE TABLE product_price_changes (
+   id INTEGER PRIMARY KEY NOT NULL,
+   product_id INTEGER NOT NULL,
+   cost BIGINT,
+   currency VARCHAR(3) NOT NULL,
+   changed_at TIMESTAMP NOT NULL,
+   FOREIGN KEY(product_id) REFERENCES products(id) ON DELETE CASCADE
+);
+
+CREATE INDEX product_price_changes_product_id ON product_price_changes(product_id, changed_at);
+
+INSERT INTO product_price_changes(product_id, cost, currency, changed_at)
+SELECT id, cost, currency, strftime('%Y-%m-%d %H:%M:%f', 'now') FROM products;
+
+INSERT INTO product_price_changes(product_id, cost, currency, changed_at)
+SELECT product_id, amount, currency, strftime('%Y-%m-%d %H:%M:%f', 'now') FROM product_prices;
+
+CREATE TRIGGER product_price_changes_after_insert AFTER INSERT ON products BEGIN
+   INSERT INTO product_price_changes(product_id, cost, currency, changed_at)
+   VALUES (new.id, new.cost, new.currency, strftime('%Y-%m-%d %H:%M:%f', 'now'));
+END;
+
+CREATE TRIGGER product_price_changes_after_update AFTER UPDATE OF cost, currency ON products
+WHEN old.cost IS NOT new.cost OR old.currency IS NOT new.currency BEGIN
+   INSERT INTO product_price_changes(product_id, cost, currency, changed_at)
+   VALUES (new.id, new.cost, new.currency, strftime('%Y-%m-%d %H:%M:%f', 'now'));
+END;
+
+CREATE TRIGGER product_price_changes_after_price_insert AFTER INSERT ON product_prices BEGIN
+   INSERT INTO product_price_changes(product_id, cost, currency, changed_at)
+   VALUES (new.product_id, new.amount, new.currency, strftime('%Y-%m-%d %H:%M:%f', 'now'));
+END;
+
+CREATE TRIGGER product_price_changes_after_price_update AFTER UPDATE OF amount ON product_prices
+WHEN old.amount IS NOT new.amount BEGIN
+   INSERT INTO product_price_changes(product_id, cost, currency, changed_at)
+   VALUES (new.product_id, new.amount, new.currency, strftime('%Y-%m-%d %H:%M:%f', 'now'));
+END;
+
+-- Not when the price goes along with its product
+CREATE TRIGGER product_price_changes_after_price_delete AFTER DELETE ON product_prices
+WHEN EXISTS (SELECT 1 FROM products WHERE id = old.product_id) BEGIN
+   INSERT INTO product_price_changes(product_id, cost, currency, changed_at)
+   VALUES (old.product_id, NULL, old.currency, strftime('%Y-%m-%d %H:%M:%f', 'now'));
+END;
@@ -0,0 +1,20 @@
+-- A concrete combination of variant values of a product, e.g. size 42 in
+-- black, which is what actually gets stocked and sold
+CREATE TABLE skus (
+   id INTEGER PRIMARY KEY NOT NULL,
+   product_id INTEGER NOT NULL,
+   code VARCHAR NOT NULL UNIQUE,
+   stock INTEGER NOT NULL DEFAULT 0 CHECK(stock >= 0),
+   -- Overrides the cost of the product, in minor units of its currency
+   price BIGINT,
+   FOREIGN KEY(product_id) REFERENCES products(id) ON DELETE CASCADE
+);
+
+-- The variant values making up a SKU, at most one per variant
+CREATE TABLE sku_values (
+   sku_id INTEGER NOT NULL,
+   product_variant_id INTEGER NOT NULL,
+   PRIMARY KEY(sku_id, product_variant_id),
+   FOREIGN KEY(sku_id) REFERENCES skus(id) ON DELETE CASCADE,
+   FOREIGN KEY(product_variant_id) REFERENCES products_variants(id) ON DELETE CASCADE
+);
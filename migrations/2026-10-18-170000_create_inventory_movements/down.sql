@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE skus DROP COLUMN allow_backorder;
+ALTER TABLE skus DROP COLUMN stock;
+ALTER TABLE skus ADD COLUMN stock INTEGER NOT NULL DEFAULT 0 CHECK(stock >= 0);
+UPDATE skus SET stock = MAX(0, COALESCE(
+   (SELECT balance FROM inventory_movements WHERE sku_id = skus.id ORDER BY id DESC LIMIT 1),
+   0
+));
+DROP TABLE inventory_movements;
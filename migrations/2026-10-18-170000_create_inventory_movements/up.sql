@@ -0,0 +1,30 @@
+-- Every change to the stock of a SKU. Rows are only ever appended, so a SKU
+-- with movements can't be deleted. `balance` is the stock on hand right after
+-- the movement
+CREATE TABLE inventory_movements (
+   id INTEGER PRIMARY KEY NOT NULL,
+   sku_id INTEGER NOT NULL,
+   reason VARCHAR NOT NULL CHECK(reason IN ('receipt', 'sale', 'return', 'damage', 'correction')),
+   quantity INTEGER NOT NULL CHECK(quantity <> 0),
+   balance INTEGER NOT NULL,
+   note VARCHAR,
+   created_at TIMESTAMP NOT NULL,
+   FOREIGN KEY(sku_id) REFERENCES skus(id) ON DELETE RESTRICT
+);
+
+CREATE INDEX inventory_movements_sku_id ON inventory_movements(sku_id);
+
+INSERT INTO inventory_movements(sku_id, reason, quantity, balance, note, created_at)
+SELECT id, 'correction', stock, stock, 'opening balance', strftime('%Y-%m-%d %H:%M:%f', 'now')
+FROM skus WHERE stock <> 0;
+
+-- `stock` is now the balance of the last movement, kept next to the SKU so
+-- that it can be checked and updated in one statement. It goes below zero
+-- only for SKUs sold on backorder
+ALTER TABLE skus DROP COLUMN stock;
+ALTER TABLE skus ADD COLUMN stock INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE skus ADD COLUMN allow_backorder BOOLEAN NOT NULL DEFAULT 0;
+UPDATE skus SET stock = COALESCE(
+   (SELECT balance FROM inventory_movements WHERE sku_id = skus.id ORDER BY id DESC LIMIT 1),
+   0
+);
@@ -0,0 +1,28 @@
+-- This file should undo anything in `up.sql`
+CREATE TABLE old_inventory_movements (
+   id INTEGER PRIMARY KEY NOT NULL,
+   sku_id INTEGER NOT NULL,
+   reason VARCHAR NOT NULL CHECK(reason IN ('receipt', 'sale', 'return', 'damage', 'correction')),
+   quantity INTEGER NOT NULL CHECK(quantity <> 0),
+   balance INTEGER NOT NULL,
+   note VARCHAR,
+   created_at TIMESTAMP NOT NULL,
+   FOREIGN KEY(sku_id) REFERENCES skus(id) ON DELETE RESTRICT
+);
+
+-- Transfers leave the stock of a SKU unchanged once both sides are dropped,
+-- the balances become running sums over every location again
+INSERT INTO old_inventory_movements(id, sku_id, reason, quantity, balance, note, created_at)
+SELECT id, sku_id, reason, quantity,
+   (SELECT SUM(quantity) FROM inventory_movements AS earlier
+    WHERE earlier.sku_id = inventory_movements.sku_id AND earlier.id <= inventory_movements.id),
+   note, created_at
+FROM inventory_movements WHERE reason <> 'transfer';
+
+DROP TABLE inventory_movements;
+ALTER TABLE old_inventory_movements RENAME TO inventory_movements;
+CREATE INDEX inventory_movements_sku_id ON inventory_movements(sku_id);
+
+DROP TABLE transfers;
+DROP TABLE location_stocks;
+DROP TABLE locations;
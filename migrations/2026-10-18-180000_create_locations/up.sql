@@ -0,0 +1,64 @@
+-- Places holding stock, the warehouse we ship from or one of the shops
+CREATE TABLE locations (
+   id INTEGER PRIMARY KEY NOT NULL,
+   name VARCHAR NOT NULL UNIQUE,
+   kind VARCHAR NOT NULL CHECK(kind IN ('warehouse', 'store'))
+);
+
+-- Receives the movements not given a location, including every movement
+-- recorded before there were locations
+INSERT INTO locations(id, name, kind) VALUES (1, 'Central warehouse', 'warehouse');
+
+-- Stock of a SKU at one location, `skus.stock` being the sum over locations
+CREATE TABLE location_stocks (
+   location_id INTEGER NOT NULL,
+   sku_id INTEGER NOT NULL,
+   stock INTEGER NOT NULL DEFAULT 0,
+   PRIMARY KEY(location_id, sku_id),
+   FOREIGN KEY(location_id) REFERENCES locations(id),
+   FOREIGN KEY(sku_id) REFERENCES skus(id) ON DELETE CASCADE
+);
+
+INSERT INTO location_stocks(location_id, sku_id, stock)
+SELECT 1, id, stock FROM skus WHERE stock <> 0;
+
+-- Stock of a SKU moved between two locations, recorded as a movement out of
+-- one and a movement into the other
+CREATE TABLE transfers (
+   id INTEGER PRIMARY KEY NOT NULL,
+   sku_id INTEGER NOT NULL,
+   from_location_id INTEGER NOT NULL,
+   to_location_id INTEGER NOT NULL CHECK(to_location_id <> from_location_id),
+   quantity INTEGER NOT NULL CHECK(quantity > 0),
+   note VARCHAR,
+   created_at TIMESTAMP NOT NULL,
+   FOREIGN KEY(sku_id) REFERENCES skus(id) ON DELETE RESTRICT,
+   FOREIGN KEY(from_location_id) REFERENCES locations(id),
+   FOREIGN KEY(to_location_id) REFERENCES locations(id)
+);
+
+CREATE INDEX transfers_sku_id ON transfers(sku_id);
+
+-- Rebuilt to add the location and the transfer of each movement, as well as
+-- the 'transfer' reason. `balance` is now the stock at the location
+CREATE TABLE new_inventory_movements (
+   id INTEGER PRIMARY KEY NOT NULL,
+   sku_id INTEGER NOT NULL,
+   location_id INTEGER NOT NULL,
+   transfer_id INTEGER,
+   reason VARCHAR NOT NULL CHECK(reason IN ('receipt', 'sale', 'return', 'damage', 'correction', 'transfer')),
+   quantity INTEGER NOT NULL CHECK(quantity <> 0),
+   balance INTEGER NOT NULL,
+   note VARCHAR,
+   created_at TIMESTAMP NOT NULL,
+   FOREIGN KEY(sku_id) REFERENCES skus(id) ON DELETE RESTRICT,
+   FOREIGN KEY(location_id) REFERENCES locations(id),
+   FOREIGN KEY(transfer_id) REFERENCES transfers(id) ON DELETE RESTRICT
+);
+
+INSERT INTO new_inventory_movements(id, sku_id, location_id, reason, quantity, balance, note, created_at)
+SELECT id, sku_id, 1, reason, quantity, balance, note, created_at FROM inventory_movements;
+
+DROP TABLE inventory_movements;
+ALTER TABLE new_inventory_movements RENAME TO inventory_movements;
+CREATE INDEX inventory_movements_sku_id ON inventory_movements(sku_id);
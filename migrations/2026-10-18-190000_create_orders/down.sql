@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE order_line_options;
+DROP TABLE order_lines;
+DROP TABLE orders;
@@ -0,0 +1,37 @@
+-- An order placed at checkout, in the currency its cart was priced in
+CREATE TABLE orders (
+   id INTEGER PRIMARY KEY NOT NULL,
+   currency VARCHAR(3) NOT NULL,
+   item_count INTEGER NOT NULL,
+   -- In minor units of `currency`
+   total BIGINT NOT NULL,
+   created_at TIMESTAMP NOT NULL
+);
+
+-- A line of an order, keeping the name and price of the product as they were
+-- when the order was placed. The product and SKU themselves may go away
+CREATE TABLE order_lines (
+   id INTEGER PRIMARY KEY NOT NULL,
+   order_id INTEGER NOT NULL,
+   product_id INTEGER,
+   sku_id INTEGER,
+   product_name VARCHAR NOT NULL,
+   quantity INTEGER NOT NULL CHECK(quantity > 0),
+   unit_price BIGINT NOT NULL,
+   total BIGINT NOT NULL,
+   FOREIGN KEY(order_id) REFERENCES orders(id) ON DELETE CASCADE,
+   FOREIGN KEY(product_id) REFERENCES products(id) ON DELETE SET NULL,
+   FOREIGN KEY(sku_id) REFERENCES skus(id) ON DELETE SET NULL
+);
+
+CREATE INDEX order_lines_order_id ON order_lines(order_id);
+
+-- The variant values of an order line, e.g. size 42, copied as text
+CREATE TABLE order_line_options (
+   order_line_id INTEGER NOT NULL,
+   position INTEGER NOT NULL,
+   name VARCHAR NOT NULL,
+   value VARCHAR,
+   PRIMARY KEY(order_line_id, position),
+   FOREIGN KEY(order_line_id) REFERENCES order_lines(id) ON DELETE CASCADE
+);
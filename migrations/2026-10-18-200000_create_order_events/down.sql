@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE order_events;
+ALTER TABLE orders DROP COLUMN status;
@@ -0,0 +1,19 @@
+ALTER TABLE orders ADD COLUMN status VARCHAR NOT NULL DEFAULT 'pending'
+   CHECK(status IN ('pending', 'paid', 'packed', 'shipped', 'delivered', 'cancelled', 'refunded'));
+
+-- Every status an order went through, `from_status` being NULL when it was
+-- placed
+CREATE TABLE order_events (
+   id INTEGER PRIMARY KEY NOT NULL,
+   order_id INTEGER NOT NULL,
+   from_status VARCHAR,
+   to_status VARCHAR NOT NULL,
+   note VARCHAR,
+   created_at TIMESTAMP NOT NULL,
+   FOREIGN KEY(order_id) REFERENCES orders(id) ON DELETE CASCADE
+);
+
+CREATE INDEX order_events_order_id ON order_events(order_id);
+
+INSERT INTO order_events(order_id, from_status, to_status, created_at)
+SELECT id, NULL, 'pending', created_at FROM orders;
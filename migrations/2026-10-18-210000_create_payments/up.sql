@@ -0,0 +1,37 @@
+-- A payment of an order through a payment gateway, which knows it by
+-- `reference`. Payments are marked capturing while the gateway is asked to
+-- capture them, and those whose order couldn't be paid anymore once
+-- authorized have their authorization voided.
+CREATE TABLE payments (
+   id INTEGER PRIMARY KEY NOT NULL,
+   order_id INTEGER NOT NULL,
+   gateway VARCHAR NOT NULL,
+   reference VARCHAR NOT NULL,
+   status VARCHAR NOT NULL
+      CHECK(status IN ('requires_action', 'authorized', 'capturing', 'captured', 'declined', 'voided', 'refunded')),
+   -- In minor units of `currency`
+   amount BIGINT NOT NULL,
+   refunded BIGINT NOT NULL DEFAULT 0,
+   currency VARCHAR(3) NOT NULL,
+   created_at TIMESTAMP NOT NULL,
+   updated_at TIMESTAMP NOT NULL,
+   UNIQUE(gateway, reference),
+   FOREIGN KEY(order_id) REFERENCES orders(id) ON DELETE CASCADE
+);
+
+CREATE INDEX payments_order_id ON payments(order_id);
+
+-- A refund of part of a payment. It is recorded as pending before the
+-- gateway is asked for it, so that a refund is never made twice nor lost.
+CREATE TABLE refunds (
+   id INTEGER PRIMARY KEY NOT NULL,
+   payment_id INTEGER NOT NULL,
+   -- In minor units of the currency of the payment
+   amount BIGINT NOT NULL,
+   status VARCHAR NOT NULL DEFAULT 'pending' CHECK(status IN ('pending', 'succeeded', 'failed')),
+   created_at TIMESTAMP NOT NULL,
+   updated_at TIMESTAMP NOT NULL,
+   FOREIGN KEY(payment_id) REFERENCES payments(id) ON DELETE CASCADE
+);
+
+CREATE INDEX refunds_payment_id ON refunds(payment_id);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE return_lines;
+DROP TABLE return_requests;
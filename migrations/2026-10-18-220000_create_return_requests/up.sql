@@ -0,0 +1,29 @@
+-- A request to return lines of an order. It is approved or rejected, then
+-- its items are received back and `refund` is paid back
+CREATE TABLE return_requests (
+   id INTEGER PRIMARY KEY NOT NULL,
+   order_id INTEGER NOT NULL,
+   status VARCHAR NOT NULL DEFAULT 'requested'
+      CHECK(status IN ('requested', 'approved', 'rejected', 'received', 'refunded')),
+   -- In minor units of the currency of the order, from the prices of its lines
+   refund BIGINT NOT NULL,
+   note VARCHAR,
+   created_at TIMESTAMP NOT NULL,
+   updated_at TIMESTAMP NOT NULL,
+   FOREIGN KEY(order_id) REFERENCES orders(id) ON DELETE CASCADE
+);
+
+CREATE INDEX return_requests_order_id ON return_requests(order_id);
+
+CREATE TABLE return_lines (
+   id INTEGER PRIMARY KEY NOT NULL,
+   return_request_id INTEGER NOT NULL,
+   order_line_id INTEGER NOT NULL,
+   quantity INTEGER NOT NULL CHECK(quantity > 0),
+   reason VARCHAR NOT NULL CHECK(reason IN ('too_small', 'too_large', 'defective')),
+   FOREIGN KEY(return_request_id) REFERENCES return_requests(id) ON DELETE CASCADE,
+   FOREIGN KEY(order_line_id) REFERENCES order_lines(id) ON DELETE CASCADE
+);
+
+CREATE INDEX return_lines_return_request_id ON return_lines(return_request_id);
+CREATE INDEX return_lines_order_line_id ON return_lines(order_line_id);
@@ -0,0 +1,10 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX orders_promo_code_id;
+ALTER TABLE order_lines DROP COLUMN discount;
+ALTER TABLE orders DROP COLUMN customer_email;
+ALTER TABLE orders DROP COLUMN discount;
+ALTER TABLE orders DROP COLUMN promo_code;
+ALTER TABLE orders DROP COLUMN promo_code_id;
+DROP TABLE promo_code_variant_rules;
+DROP TABLE promo_code_products;
+DROP TABLE promo_codes;
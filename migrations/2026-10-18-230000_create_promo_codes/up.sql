@@ -0,0 +1,54 @@
+-- A discount code, taking either `percent_off` or `amount_off` off the cart
+-- lines it applies to
+CREATE TABLE promo_codes (
+   id INTEGER PRIMARY KEY NOT NULL,
+   -- Uppercase, codes are entered regardless of case
+   code VARCHAR NOT NULL UNIQUE,
+   percent_off INTEGER CHECK(percent_off BETWEEN 1 AND 100),
+   -- In minor units of `currency`, as is `min_cart_total`
+   amount_off BIGINT CHECK(amount_off > 0),
+   currency VARCHAR(3) NOT NULL,
+   min_cart_total BIGINT,
+   -- Orders the code may be used on, overall and by a single customer
+   usage_limit INTEGER,
+   per_customer_limit INTEGER,
+   starts_at TIMESTAMP,
+   ends_at TIMESTAMP,
+   active BOOLEAN NOT NULL DEFAULT 1,
+   CHECK((percent_off IS NULL) != (amount_off IS NULL))
+);
+
+-- Products a code applies to, every product when it has none
+CREATE TABLE promo_code_products (
+   promo_code_id INTEGER NOT NULL,
+   product_id INTEGER NOT NULL,
+   PRIMARY KEY(promo_code_id, product_id),
+   FOREIGN KEY(promo_code_id) REFERENCES promo_codes(id) ON DELETE CASCADE,
+   FOREIGN KEY(product_id) REFERENCES products(id) ON DELETE CASCADE
+);
+
+-- Variant values a code applies to. A line matches when, for every variant
+-- named by the rules of the code, one of the rules of that variant matches
+-- its value: exactly `value`, or a number between `min_value` and `max_value`
+CREATE TABLE promo_code_variant_rules (
+   id INTEGER PRIMARY KEY NOT NULL,
+   promo_code_id INTEGER NOT NULL,
+   variant_name VARCHAR NOT NULL,
+   value VARCHAR,
+   min_value DOUBLE,
+   max_value DOUBLE,
+   CHECK(value IS NOT NULL OR min_value IS NOT NULL OR max_value IS NOT NULL),
+   FOREIGN KEY(promo_code_id) REFERENCES promo_codes(id) ON DELETE CASCADE
+);
+
+CREATE INDEX promo_code_variant_rules_promo_code_id ON promo_code_variant_rules(promo_code_id);
+
+-- `total` is what is left once `discount` is taken off the lines, the code
+-- itself is copied in case it goes away
+ALTER TABLE orders ADD COLUMN promo_code_id INTEGER REFERENCES promo_codes(id) ON DELETE SET NULL;
+ALTER TABLE orders ADD COLUMN promo_code VARCHAR;
+ALTER TABLE orders ADD COLUMN discount BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE orders ADD COLUMN customer_email VARCHAR;
+ALTER TABLE order_lines ADD COLUMN discount BIGINT NOT NULL DEFAULT 0;
+
+CREATE INDEX orders_promo_code_id ON orders(promo_code_id);
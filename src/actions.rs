@@ -1,107 +1,235 @@
-use actix_web::{get, post, web, Responder, HttpResponse, put, delete};
-use serde::{Serialize, Deserialize};
-use crate::db::dal::{update_product, delete_product};
+use actix_web::{delete, get, http::header, post, put, web, HttpResponse};
+use serde::{Deserialize, Serialize};
 
 use super::db::{
     connect::DbPool,
-    dal::{create_product, search_products, list_products, show_product},
-    models::{FormProduct, NewCompleteProduct}
+    dal::{
+        create_product, delete_product, facets, list_products,
+        locations::{add_availability, AvailabilityView},
+        prices::{parse_currency, price_products},
+        search, search_products, show_product, sku_matrix::preview_new_product, update_product,
+    },
+    filters::{ProductFilter, ProductSort},
+    models::{FormProduct, NewCompleteProduct},
+    money::Money,
+    pagination::PageParams,
 };
+use super::errors::ApiError;
+use super::validation::validate;
 
+pub mod carts;
+pub mod inventory;
+pub mod locations;
+pub mod orders;
+pub mod payments;
+pub mod prices;
+pub mod promotions;
+pub mod returns;
+pub mod skus;
+pub mod synonyms;
+
+#[derive(Serialize, Deserialize)]
+struct ProductCreateQueryParams {
+    preview: Option<bool>,
+}
+
+/// With `?preview=true`, responds with the SKUs the `sku_matrix` of the
+/// product would generate instead of creating anything.
 #[post("/products")]
-async fn product_create(product: web::Json<NewCompleteProduct>, pool: web::Data<DbPool>) -> impl Responder {
-    let connection = pool.get().unwrap();
-	let product = product.into_inner();
-	let _create_product_result = web::block(move || create_product(product, &connection).unwrap())
-	.await
-	.map_err(|e| {
-            log::error!("{}", e);
-            HttpResponse::InternalServerError().finish()
-        })
-	.unwrap();
-	HttpResponse::Created()
+async fn product_create(
+    product: web::Json<NewCompleteProduct>,
+    query_params: web::Query<ProductCreateQueryParams>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    validate(&*product)?;
+    let product = product.into_inner();
+    if query_params.preview.unwrap_or(false) {
+        let plans = preview_new_product(&product)?;
+        return Ok(HttpResponse::Ok().json(plans));
+    }
+
+    let connection = pool.get()?;
+    let product = web::block(move || {
+        let product_id = create_product(product, &connection)?;
+        show_product(product_id, &connection)
+    })
+    .await??;
+    Ok(HttpResponse::Created()
+        .insert_header((header::LOCATION, format!("/products/{}", product.id)))
+        .json(product))
 }
 
 #[derive(Serialize, Deserialize)]
 struct ProductListQueryParams {
-	limit: Option<u16>,
+    limit: Option<u16>,
+    offset: Option<u32>,
+    cursor: Option<String>,
+    search: Option<String>,
+    active: Option<bool>,
+    min_cost: Option<Money>,
+    max_cost: Option<Money>,
+    name_prefix: Option<String>,
+    sort: Option<String>,
+    order: Option<String>,
+    /// Currency to return the prices in; `min_cost`, `max_cost` and sorting
+    /// by cost always apply to the cost of the products as stored.
+    currency: Option<String>,
+    /// `total` or `location` to add the stock of each product, overall or
+    /// per location.
+    availability: Option<String>,
 }
 
-#[get("/products")]
-async fn product_list(query_params: web::Query<ProductListQueryParams>, pool: web::Data<DbPool>) -> impl Responder {
-	let connection = pool.get().unwrap();
-	let ProductListQueryParams { limit } = query_params.into_inner();
-	let products = web::block(move || list_products(limit, &connection).unwrap())
-	.await
-	.map_err(|e| {
-            log::error!("{}", e);
-            HttpResponse::InternalServerError().finish()
+impl ProductListQueryParams {
+    /// Builds the filter from these params plus the `variant.<name>=<value>`
+    /// pairs found among the raw query parameters.
+    fn filter(&self, raw_params: &[(String, String)]) -> Result<ProductFilter, ApiError> {
+        Ok(ProductFilter {
+            search: self.search.clone(),
+            active: self.active,
+            min_cost: self.min_cost,
+            max_cost: self.max_cost,
+            name_prefix: self.name_prefix.clone(),
+            variants: ProductFilter::variants_from_params(raw_params)?,
         })
-	.unwrap();
-	HttpResponse::Ok().json(products)
+    }
 }
 
+/// Besides the fields of `ProductListQueryParams`, accepts any number of
+/// `variant.<name>=<value>` parameters to filter on variant values.
+#[get("/products")]
+async fn product_list(
+    query_params: web::Query<ProductListQueryParams>,
+    raw_params: web::Query<Vec<(String, String)>>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let filter = query_params.filter(&raw_params)?;
+    let ProductListQueryParams { limit, offset, cursor, sort, order, currency, availability, .. } =
+        query_params.into_inner();
+    let sort = ProductSort::parse(sort.as_deref(), order.as_deref())?;
+    let page = PageParams { limit, offset, cursor };
+    let currency = currency.as_deref().map(parse_currency).transpose()?;
+    let availability = availability.as_deref().map(str::parse::<AvailabilityView>).transpose()?;
+    let products = web::block(move || {
+        let mut products = list_products(filter, sort, page, &connection)?;
+        if let Some(currency) = currency {
+            price_products(&mut products.items, &currency, &connection)?;
+        }
+        if let Some(availability) = availability {
+            add_availability(&mut products.items, availability, &connection)?;
+        }
+        Ok::<_, ApiError>(products)
+    })
+    .await??;
+    Ok(HttpResponse::Ok().json(products))
+}
+
+/// Takes the same filters as `product_list`; paging and sorting are ignored.
+#[get("/products/facets")]
+async fn product_facets(
+    query_params: web::Query<ProductListQueryParams>,
+    raw_params: web::Query<Vec<(String, String)>>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let filter = query_params.filter(&raw_params)?;
+    let facets = web::block(move || facets::product_facets(filter, &connection)).await??;
+    Ok(HttpResponse::Ok().json(facets))
+}
 
 #[derive(Serialize, Deserialize)]
 struct ProductSearchQueryParams {
-	search: String
+    search: String,
+    fuzzy: Option<bool>,
+    limit: Option<u16>,
+    offset: Option<u32>,
+    cursor: Option<String>,
+    currency: Option<String>,
+    availability: Option<String>,
 }
 
 #[get("/products/search")]
-async fn product_search(query: web::Query<ProductSearchQueryParams>, pool: web::Data<DbPool>) -> impl Responder {
-	let connection = pool.get().unwrap();
-	let ProductSearchQueryParams { search } = query.into_inner();
-	let products = web::block(move || search_products(search, &connection).unwrap())
-	.await
-	.map_err(|e| {
-            log::error!("{}", e);
-            HttpResponse::InternalServerError().finish()
-        })
-	.unwrap();
-	HttpResponse::Ok().json(products)
+async fn product_search(query: web::Query<ProductSearchQueryParams>, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let ProductSearchQueryParams { search, fuzzy, limit, offset, cursor, currency, availability } = query.into_inner();
+    let page = PageParams { limit, offset, cursor };
+    let fuzzy = fuzzy.unwrap_or(false);
+    let currency = currency.as_deref().map(parse_currency).transpose()?;
+    let availability = availability.as_deref().map(str::parse::<AvailabilityView>).transpose()?;
+    let products = web::block(move || {
+        let mut hits = search_products(search, fuzzy, page, &connection)?;
+        if let Some(currency) = currency {
+            let products = hits.items.iter_mut().map(|hit| &mut hit.product);
+            price_products(products, &currency, &connection)?;
+        }
+        if let Some(availability) = availability {
+            let products = hits.items.iter_mut().map(|hit| &mut hit.product);
+            add_availability(products, availability, &connection)?;
+        }
+        Ok::<_, ApiError>(hits)
+    })
+    .await??;
+    Ok(HttpResponse::Ok().json(products))
 }
 
-#[get("/products/{id}")]
-async fn product_show(id: web::Path<i32>, pool: web::Data<DbPool>) -> impl Responder {
-	let id = id.into_inner();
-	let connection = pool.get().unwrap();
-	let product = web::block(move || show_product(id, &connection).unwrap())
-	.await
-	.map_err(|e| {
-            log::error!("{}", e);
-            HttpResponse::InternalServerError().finish()
-        })
-	.unwrap();
-	HttpResponse::Ok().json(product)
+#[derive(Serialize, Deserialize)]
+struct ProductSuggestQueryParams {
+    q: String,
+    limit: Option<u16>,
 }
 
+#[get("/products/suggest")]
+async fn product_suggest(query: web::Query<ProductSuggestQueryParams>, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let ProductSuggestQueryParams { q, limit } = query.into_inner();
+    let suggestions = web::block(move || search::suggest_products(q, limit, &connection)).await??;
+    Ok(HttpResponse::Ok().json(suggestions))
+}
 
-#[put("/products/{id}")]
-async fn product_update(id: web::Path<i32>, product: web::Json<FormProduct>, pool: web::Data<DbPool>) -> impl Responder {
-    let connection = pool.get().unwrap();
-	let id = id.into_inner();
-	let product = product.into_inner();
-	let _update_product_result = web::block(move || update_product(id, product, &connection).unwrap())
-	.await
-	.map_err(|e| {
-            log::error!("{}", e);
-            HttpResponse::InternalServerError().finish()
-        })
-	.unwrap();
-	HttpResponse::Ok()
+#[derive(Serialize, Deserialize)]
+struct ProductShowQueryParams {
+    currency: Option<String>,
+    availability: Option<String>,
 }
 
+#[get("/products/{id}")]
+async fn product_show(id: web::Path<i32>, query: web::Query<ProductShowQueryParams>, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let id = id.into_inner();
+    let currency = query.currency.as_deref().map(parse_currency).transpose()?;
+    let availability = query.availability.as_deref().map(str::parse::<AvailabilityView>).transpose()?;
+    let connection = pool.get()?;
+    let product = web::block(move || {
+        let mut product = show_product(id, &connection)?;
+        if let Some(currency) = currency {
+            price_products(Some(&mut product), &currency, &connection)?;
+        }
+        if let Some(availability) = availability {
+            add_availability(Some(&mut product), availability, &connection)?;
+        }
+        Ok::<_, ApiError>(product)
+    })
+    .await??;
+    Ok(HttpResponse::Ok().json(product))
+}
+
+#[put("/products/{id}")]
+async fn product_update(id: web::Path<i32>, product: web::Json<FormProduct>, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    validate(&*product)?;
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let product = product.into_inner();
+    let product = web::block(move || {
+        let product_id = update_product(id, product, &connection)?;
+        show_product(product_id, &connection)
+    })
+    .await??;
+    Ok(HttpResponse::Ok().json(product))
+}
 
 #[delete("/products/{id}")]
-async fn product_delete(id: web::Path<i32>, pool: web::Data<DbPool>) -> impl Responder {
-	let connection = pool.get().unwrap();
-	let id = id.into_inner();
-	let _delete_product_result = web::block(move || delete_product(id, &connection).unwrap())
-	.await
-	.map_err(|e| {
-            log::error!("{}", e);
-            HttpResponse::InternalServerError().finish()
-        })
-	.unwrap();
-	HttpResponse::Ok()
-}
\ No newline at end of file
+async fn product_delete(id: web::Path<i32>, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let _deleted_product_id = web::block(move || delete_product(id, &connection)).await??;
+    Ok(HttpResponse::Ok().finish())
+}
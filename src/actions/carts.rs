@@ -0,0 +1,173 @@
+use actix_session::Session;
+use actix_web::{delete, get, post, put, web, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+use crate::db::{
+    connect::DbPool,
+    dal::{carts, prices::parse_currency},
+    models::{Cart, FormCartLine, FormCartPromoCode, FormCartQuantity},
+    money::DEFAULT_CURRENCY,
+};
+use crate::errors::ApiError;
+use crate::validation::validate;
+
+/// Session key of the cart.
+const CART_KEY: &str = "cart";
+
+#[derive(Serialize, Deserialize)]
+pub(super) struct CartQueryParams {
+    /// Currency to price the cart in, the default currency otherwise.
+    currency: Option<String>,
+}
+
+impl CartQueryParams {
+    pub(super) fn currency(&self) -> Result<String, ApiError> {
+        parse_currency(self.currency.as_deref().unwrap_or(DEFAULT_CURRENCY))
+    }
+}
+
+/// The cart of the session. A cart that can't be read back, e.g. one saved by
+/// an older version of the store, starts over empty.
+pub(super) fn load_cart(session: &Session) -> Cart {
+    session.get::<Cart>(CART_KEY).ok().flatten().unwrap_or_default()
+}
+
+pub(super) fn save_cart(session: &Session, cart: &Cart) -> Result<(), ApiError> {
+    session
+        .insert(CART_KEY, cart)
+        .map_err(|error| ApiError::Internal(error.to_string()))
+}
+
+#[get("/cart")]
+async fn cart_show(
+    query: web::Query<CartQueryParams>,
+    session: Session,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    let currency = query.currency()?;
+    let connection = pool.get()?;
+    let mut cart = load_cart(&session);
+    let (cart, priced) = web::block(move || {
+        let priced = carts::price_cart(&mut cart, &currency, &connection)?;
+        Ok::<_, ApiError>((cart, priced))
+    })
+    .await??;
+    save_cart(&session, &cart)?;
+    Ok(HttpResponse::Ok().json(priced))
+}
+
+/// Adds a product, with the variant values chosen for it, to the cart.
+#[post("/cart/lines")]
+async fn cart_line_add(
+    line: web::Json<FormCartLine>,
+    query: web::Query<CartQueryParams>,
+    session: Session,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    validate(&*line)?;
+    let currency = query.currency()?;
+    let connection = pool.get()?;
+    let line = line.into_inner();
+    let mut cart = load_cart(&session);
+    let (cart, priced) = web::block(move || {
+        carts::add_cart_line(&mut cart, line, &connection)?;
+        let priced = carts::price_cart(&mut cart, &currency, &connection)?;
+        Ok::<_, ApiError>((cart, priced))
+    })
+    .await??;
+    save_cart(&session, &cart)?;
+    Ok(HttpResponse::Created().json(priced))
+}
+
+#[put("/cart/lines/{id}")]
+async fn cart_line_update(
+    id: web::Path<u32>,
+    quantity: web::Json<FormCartQuantity>,
+    query: web::Query<CartQueryParams>,
+    session: Session,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    validate(&*quantity)?;
+    let currency = query.currency()?;
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let quantity = quantity.quantity;
+    let mut cart = load_cart(&session);
+    let (cart, priced) = web::block(move || {
+        carts::update_cart_line(&mut cart, id, quantity)?;
+        let priced = carts::price_cart(&mut cart, &currency, &connection)?;
+        Ok::<_, ApiError>((cart, priced))
+    })
+    .await??;
+    save_cart(&session, &cart)?;
+    Ok(HttpResponse::Ok().json(priced))
+}
+
+#[delete("/cart/lines/{id}")]
+async fn cart_line_remove(
+    id: web::Path<u32>,
+    query: web::Query<CartQueryParams>,
+    session: Session,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    let currency = query.currency()?;
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let mut cart = load_cart(&session);
+    let (cart, priced) = web::block(move || {
+        carts::remove_cart_line(&mut cart, id)?;
+        let priced = carts::price_cart(&mut cart, &currency, &connection)?;
+        Ok::<_, ApiError>((cart, priced))
+    })
+    .await??;
+    save_cart(&session, &cart)?;
+    Ok(HttpResponse::Ok().json(priced))
+}
+
+/// Enters a promo code on the cart, responding with why when it doesn't
+/// apply.
+#[put("/cart/promo-code")]
+async fn cart_promo_code_apply(
+    promo_code: web::Json<FormCartPromoCode>,
+    query: web::Query<CartQueryParams>,
+    session: Session,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    validate(&*promo_code)?;
+    let currency = query.currency()?;
+    let connection = pool.get()?;
+    let code = promo_code.into_inner().code;
+    let mut cart = load_cart(&session);
+    let (cart, priced) = web::block(move || {
+        let priced = carts::apply_promo_code(&mut cart, &code, &currency, &connection)?;
+        Ok::<_, ApiError>((cart, priced))
+    })
+    .await??;
+    save_cart(&session, &cart)?;
+    Ok(HttpResponse::Ok().json(priced))
+}
+
+#[delete("/cart/promo-code")]
+async fn cart_promo_code_remove(
+    query: web::Query<CartQueryParams>,
+    session: Session,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    let currency = query.currency()?;
+    let connection = pool.get()?;
+    let mut cart = load_cart(&session);
+    let (cart, priced) = web::block(move || {
+        carts::remove_promo_code(&mut cart)?;
+        let priced = carts::price_cart(&mut cart, &currency, &connection)?;
+        Ok::<_, ApiError>((cart, priced))
+    })
+    .await??;
+    save_cart(&session, &cart)?;
+    Ok(HttpResponse::Ok().json(priced))
+}
+
+#[delete("/cart")]
+async fn cart_clear(session: Session) -> Result<HttpResponse, ApiError> {
+    session.remove(CART_KEY);
+    Ok(HttpResponse::Ok().finish())
+}
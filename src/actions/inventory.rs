@@ -0,0 +1,56 @@
+use actix_web::{get, post, web, HttpResponse};
+
+use crate::db::{
+    connect::DbPool,
+    dal::inventory,
+    models::{FormInventoryAdjustment, FormTransfer},
+};
+use crate::errors::ApiError;
+use crate::validation::validate;
+
+/// Posts a receipt, sale, return, damage or correction to the stock of a SKU
+/// at a location, the central warehouse unless `location_id` says otherwise.
+#[post("/skus/{id}/inventory")]
+async fn inventory_adjust(
+    id: web::Path<i32>,
+    adjustment: web::Json<FormInventoryAdjustment>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    validate(&*adjustment)?;
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let adjustment = adjustment.into_inner();
+    let movement = web::block(move || inventory::adjust_stock(id, adjustment, &connection)).await??;
+    Ok(HttpResponse::Created().json(movement))
+}
+
+#[get("/skus/{id}/inventory")]
+async fn inventory_list(id: web::Path<i32>, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let movements = web::block(move || inventory::list_movements(id, &connection)).await??;
+    Ok(HttpResponse::Ok().json(movements))
+}
+
+/// Moves stock of a SKU from one location to another.
+#[post("/skus/{id}/transfers")]
+async fn transfer_create(
+    id: web::Path<i32>,
+    transfer: web::Json<FormTransfer>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    validate(&*transfer)?;
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let transfer = transfer.into_inner();
+    let transfer = web::block(move || inventory::transfer_stock(id, transfer, &connection)).await??;
+    Ok(HttpResponse::Created().json(transfer))
+}
+
+#[get("/skus/{id}/transfers")]
+async fn transfer_list(id: web::Path<i32>, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let transfers = web::block(move || inventory::list_transfers(id, &connection)).await??;
+    Ok(HttpResponse::Ok().json(transfers))
+}
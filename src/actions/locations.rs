@@ -0,0 +1,60 @@
+use actix_web::{delete, get, post, put, web, HttpResponse};
+
+use crate::db::{connect::DbPool, dal::locations, models::FormLocation};
+use crate::errors::ApiError;
+use crate::validation::validate;
+
+#[post("/locations")]
+async fn location_create(location: web::Json<FormLocation>, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    validate(&*location)?;
+    let connection = pool.get()?;
+    let location = location.into_inner();
+    let location = web::block(move || locations::create_location(location, &connection)).await??;
+    Ok(HttpResponse::Created().json(location))
+}
+
+#[get("/locations")]
+async fn location_list(pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let locations = web::block(move || locations::list_locations(&connection)).await??;
+    Ok(HttpResponse::Ok().json(locations))
+}
+
+#[get("/locations/{id}")]
+async fn location_show(id: web::Path<i32>, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let location = web::block(move || locations::show_location(id, &connection)).await??;
+    Ok(HttpResponse::Ok().json(location))
+}
+
+#[put("/locations/{id}")]
+async fn location_update(
+    id: web::Path<i32>,
+    location: web::Json<FormLocation>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    validate(&*location)?;
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let location = location.into_inner();
+    let location = web::block(move || locations::update_location(id, location, &connection)).await??;
+    Ok(HttpResponse::Ok().json(location))
+}
+
+#[delete("/locations/{id}")]
+async fn location_delete(id: web::Path<i32>, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let _deleted_location_id = web::block(move || locations::delete_location(id, &connection)).await??;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Stock of a SKU at each location.
+#[get("/skus/{id}/stock")]
+async fn sku_stock(id: web::Path<i32>, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let stock = web::block(move || locations::sku_stock(id, &connection)).await??;
+    Ok(HttpResponse::Ok().json(stock))
+}
@@ -0,0 +1,122 @@
+use actix_session::Session;
+use actix_web::{get, http::header, post, web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use super::carts::{load_cart, save_cart, CartQueryParams};
+use crate::db::{connect::DbPool, dal::orders, models::FormOrderTransition};
+use crate::errors::ApiError;
+use crate::payments::PaymentGateway;
+use crate::validation::validate;
+
+/// Session key of the ids of the orders placed from the session.
+const ORDERS_KEY: &str = "orders";
+
+/// Most orders a session remembers, so that it still fits in a cookie. The
+/// oldest are forgotten first.
+const MAX_SESSION_ORDERS: usize = 20;
+
+/// Ids of the orders placed from the session, oldest first.
+pub(super) fn placed_orders(session: &Session) -> Vec<i32> {
+    session.get::<Vec<i32>>(ORDERS_KEY).ok().flatten().unwrap_or_default()
+}
+
+/// Fails as if the order didn't exist when it isn't one of `placed`. Until
+/// there are customer accounts, an order is only shown to the session that
+/// placed it.
+pub(super) fn ensure_placed(placed: &[i32], order_id: i32) -> Result<(), ApiError> {
+    if !placed.contains(&order_id) {
+        return Err(ApiError::NotFound(format!("order {}", order_id)));
+    }
+    Ok(())
+}
+
+fn remember_order(session: &Session, order_id: i32) -> Result<(), ApiError> {
+    let mut placed = placed_orders(session);
+    placed.push(order_id);
+    let forgotten = placed.len().saturating_sub(MAX_SESSION_ORDERS);
+    placed.drain(..forgotten);
+    session
+        .insert(ORDERS_KEY, placed)
+        .map_err(|error| ApiError::Internal(error.to_string()))
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+struct CheckoutQueryParams {
+    /// Customer placing the order, needed by promo codes limited per
+    /// customer. Compared lowercase and trimmed.
+    #[validate(email)]
+    email: Option<String>,
+}
+
+/// Turns the cart of the session into an order, priced in `currency`. The
+/// order can then be read from the same session.
+#[post("/checkout")]
+async fn checkout(
+    query: web::Query<CartQueryParams>,
+    customer: web::Query<CheckoutQueryParams>,
+    session: Session,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    validate(&*customer)?;
+    let currency = query.currency()?;
+    let connection = pool.get()?;
+    let email = customer.into_inner().email;
+    let mut cart = load_cart(&session);
+    let (cart, order) = web::block(move || {
+        let order = orders::checkout(&mut cart, &currency, email.as_deref(), &connection)?;
+        Ok::<_, ApiError>((cart, order))
+    })
+    .await??;
+    save_cart(&session, &cart)?;
+    remember_order(&session, order.id)?;
+    Ok(HttpResponse::Created()
+        .insert_header((header::LOCATION, format!("/orders/{}", order.id)))
+        .json(order))
+}
+
+#[get("/orders/{id}")]
+async fn order_show(id: web::Path<i32>, session: Session, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let id = id.into_inner();
+    ensure_placed(&placed_orders(&session), id)?;
+    let connection = pool.get()?;
+    let order = web::block(move || orders::show_order(id, &connection)).await??;
+    Ok(HttpResponse::Ok().json(order))
+}
+
+/// Packs, ships, delivers, cancels or refunds an order, when its current
+/// status allows it. Orders are paid through `/orders/{id}/payments`.
+///
+/// Responds with 202 Accepted, listing the `failed_refunds`, when the order
+/// was cancelled or refunded but some of its payments couldn't be refunded.
+#[post("/orders/{id}/transitions")]
+async fn order_transition(
+    id: web::Path<i32>,
+    transition: web::Json<FormOrderTransition>,
+    gateway: web::Data<dyn PaymentGateway>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let transition = transition.into_inner();
+    let transitioned =
+        web::block(move || orders::transition_order(id, transition, &**gateway, &connection)).await??;
+    if transitioned.failed_refunds.is_empty() {
+        Ok(HttpResponse::Ok().json(transitioned))
+    } else {
+        Ok(HttpResponse::Accepted().json(transitioned))
+    }
+}
+
+#[get("/orders/{id}/events")]
+async fn order_event_list(
+    id: web::Path<i32>,
+    session: Session,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    let id = id.into_inner();
+    ensure_placed(&placed_orders(&session), id)?;
+    let connection = pool.get()?;
+    let events = web::block(move || orders::list_order_events(id, &connection)).await??;
+    Ok(HttpResponse::Ok().json(events))
+}
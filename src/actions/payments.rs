@@ -0,0 +1,119 @@
+use actix_session::Session;
+use actix_web::{get, http::header, post, web, HttpRequest, HttpResponse};
+use diesel::sqlite::SqliteConnection;
+
+use super::orders::{ensure_placed, placed_orders};
+use crate::db::{
+    connect::DbPool,
+    dal::payments,
+    models::{FormPayment, FormRefund, Payment},
+};
+use crate::errors::ApiError;
+use crate::payments::PaymentGateway;
+use crate::validation::validate;
+
+/// Header carrying the signature of a gateway webhook.
+pub const SIGNATURE_HEADER: &str = "X-Payment-Signature";
+
+/// Pays an order of the session through the configured gateway. Responds
+/// with the payment whatever its outcome, a declined payment being a success
+/// of this request.
+#[post("/orders/{id}/payments")]
+async fn payment_create(
+    id: web::Path<i32>,
+    payment: web::Json<FormPayment>,
+    session: Session,
+    gateway: web::Data<dyn PaymentGateway>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    validate(&*payment)?;
+    let id = id.into_inner();
+    ensure_placed(&placed_orders(&session), id)?;
+    let connection = pool.get()?;
+    let payment = payment.into_inner();
+    let payment = web::block(move || payments::create_payment(id, payment, &**gateway, &connection)).await??;
+    Ok(HttpResponse::Created()
+        .insert_header((header::LOCATION, format!("/payments/{}", payment.id)))
+        .json(payment))
+}
+
+#[get("/orders/{id}/payments")]
+async fn payment_list(id: web::Path<i32>, session: Session, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let id = id.into_inner();
+    ensure_placed(&placed_orders(&session), id)?;
+    let connection = pool.get()?;
+    let payments = web::block(move || payments::list_payments(id, &connection)).await??;
+    Ok(HttpResponse::Ok().json(payments))
+}
+
+#[get("/payments/{id}")]
+async fn payment_show(id: web::Path<i32>, session: Session, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let placed = placed_orders(&session);
+    let payment = web::block(move || placed_payment(id, &placed, &connection)).await??;
+    Ok(HttpResponse::Ok().json(payment))
+}
+
+/// Refunds part of a captured payment, or all that is left of it when the
+/// payload has no `amount`.
+#[post("/payments/{id}/refunds")]
+async fn payment_refund(
+    id: web::Path<i32>,
+    refund: web::Json<FormRefund>,
+    gateway: web::Data<dyn PaymentGateway>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    validate(&*refund)?;
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let refund = refund.into_inner();
+    let payment = web::block(move || payments::refund_payment(id, refund, &**gateway, &connection)).await??;
+    Ok(HttpResponse::Ok().json(payment))
+}
+
+/// Every refund of a payment, failed ones included.
+#[get("/payments/{id}/refunds")]
+async fn payment_refund_list(
+    id: web::Path<i32>,
+    session: Session,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let placed = placed_orders(&session);
+    let refunds = web::block(move || {
+        placed_payment(id, &placed, &connection)?;
+        payments::list_refunds(id, &connection)
+    })
+    .await??;
+    Ok(HttpResponse::Ok().json(refunds))
+}
+
+/// A payment of one of the `placed` orders, as if it didn't exist otherwise.
+fn placed_payment(id: i32, placed: &[i32], conn: &SqliteConnection) -> Result<Payment, ApiError> {
+    let payment = payments::show_payment(id, conn)?;
+    ensure_placed(placed, payment.order_id).map_err(|_| ApiError::NotFound(format!("payment {}", id)))?;
+    Ok(payment)
+}
+
+/// Receives the outcome of payments that required action from the gateway,
+/// signed in the [`SIGNATURE_HEADER`] header.
+#[post("/payments/webhook")]
+async fn payment_webhook(
+    request: HttpRequest,
+    payload: web::Bytes,
+    gateway: web::Data<dyn PaymentGateway>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    let signature = request
+        .headers()
+        .get(SIGNATURE_HEADER)
+        .and_then(|signature| signature.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let connection = pool.get()?;
+    let payment =
+        web::block(move || payments::handle_webhook(&payload, &signature, &**gateway, &connection)).await??;
+    Ok(HttpResponse::Ok().json(payment))
+}
@@ -0,0 +1,106 @@
+use actix_web::{delete, get, post, put, web, HttpResponse};
+
+use crate::db::{
+    connect::DbPool,
+    dal::{price_history, prices, sales},
+    models::{FormExchangeRate, FormProductPrice, FormProductSale},
+};
+use crate::errors::ApiError;
+use crate::validation::validate;
+
+#[get("/products/{id}/prices")]
+async fn product_price_list(id: web::Path<i32>, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let prices = web::block(move || prices::list_product_prices(id, &connection)).await??;
+    Ok(HttpResponse::Ok().json(prices))
+}
+
+#[put("/products/{id}/prices/{currency}")]
+async fn product_price_set(
+    path: web::Path<(i32, String)>,
+    price: web::Json<FormProductPrice>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    validate(&*price)?;
+    let connection = pool.get()?;
+    let (id, currency) = path.into_inner();
+    let price = price.into_inner();
+    let price = web::block(move || prices::set_product_price(id, &currency, price, &connection)).await??;
+    Ok(HttpResponse::Ok().json(price))
+}
+
+#[delete("/products/{id}/prices/{currency}")]
+async fn product_price_delete(path: web::Path<(i32, String)>, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let (id, currency) = path.into_inner();
+    let _product_id = web::block(move || prices::delete_product_price(id, &currency, &connection)).await??;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[get("/products/{id}/price-history")]
+async fn product_price_history(id: web::Path<i32>, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let changes = web::block(move || price_history::list_price_history(id, &connection)).await??;
+    Ok(HttpResponse::Ok().json(changes))
+}
+
+#[get("/products/{id}/sales")]
+async fn product_sale_list(id: web::Path<i32>, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let sales = web::block(move || sales::list_sales(id, &connection)).await??;
+    Ok(HttpResponse::Ok().json(sales))
+}
+
+#[post("/products/{id}/sales")]
+async fn product_sale_create(
+    id: web::Path<i32>,
+    sale: web::Json<FormProductSale>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    validate(&*sale)?;
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let sale = sale.into_inner();
+    let sale = web::block(move || sales::create_sale(id, sale, &connection)).await??;
+    Ok(HttpResponse::Created().json(sale))
+}
+
+#[delete("/products/{id}/sales/{sale_id}")]
+async fn product_sale_delete(path: web::Path<(i32, i32)>, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let (id, sale_id) = path.into_inner();
+    let _sale_id = web::block(move || sales::delete_sale(id, sale_id, &connection)).await??;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[get("/exchange-rates")]
+async fn exchange_rate_list(pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let rates = web::block(move || prices::list_exchange_rates(&connection)).await??;
+    Ok(HttpResponse::Ok().json(rates))
+}
+
+#[put("/exchange-rates/{base}/{quote}")]
+async fn exchange_rate_set(
+    path: web::Path<(String, String)>,
+    rate: web::Json<FormExchangeRate>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    validate(&*rate)?;
+    let connection = pool.get()?;
+    let (base, quote) = path.into_inner();
+    let rate = rate.into_inner();
+    let rate = web::block(move || prices::set_exchange_rate(&base, &quote, rate, &connection)).await??;
+    Ok(HttpResponse::Ok().json(rate))
+}
+
+#[delete("/exchange-rates/{base}/{quote}")]
+async fn exchange_rate_delete(path: web::Path<(String, String)>, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let (base, quote) = path.into_inner();
+    web::block(move || prices::delete_exchange_rate(&base, &quote, &connection)).await??;
+    Ok(HttpResponse::Ok().finish())
+}
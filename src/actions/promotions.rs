@@ -0,0 +1,56 @@
+use actix_web::{delete, get, http::header, post, put, web, HttpResponse};
+
+use crate::db::{connect::DbPool, dal::promotions, models::FormPromoCode};
+use crate::errors::ApiError;
+use crate::validation::validate;
+
+#[post("/promo-codes")]
+async fn promo_code_create(
+    promo_code: web::Json<FormPromoCode>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    validate(&*promo_code)?;
+    let connection = pool.get()?;
+    let promo_code = promo_code.into_inner();
+    let promo_code = web::block(move || promotions::create_promo_code(promo_code, &connection)).await??;
+    Ok(HttpResponse::Created()
+        .insert_header((header::LOCATION, format!("/promo-codes/{}", promo_code.id)))
+        .json(promo_code))
+}
+
+#[get("/promo-codes")]
+async fn promo_code_list(pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let promo_codes = web::block(move || promotions::list_promo_codes(&connection)).await??;
+    Ok(HttpResponse::Ok().json(promo_codes))
+}
+
+#[get("/promo-codes/{id}")]
+async fn promo_code_show(id: web::Path<i32>, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let promo_code = web::block(move || promotions::show_promo_code(id, &connection)).await??;
+    Ok(HttpResponse::Ok().json(promo_code))
+}
+
+#[put("/promo-codes/{id}")]
+async fn promo_code_update(
+    id: web::Path<i32>,
+    promo_code: web::Json<FormPromoCode>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    validate(&*promo_code)?;
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let promo_code = promo_code.into_inner();
+    let promo_code = web::block(move || promotions::update_promo_code(id, promo_code, &connection)).await??;
+    Ok(HttpResponse::Ok().json(promo_code))
+}
+
+#[delete("/promo-codes/{id}")]
+async fn promo_code_delete(id: web::Path<i32>, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let _deleted_id = web::block(move || promotions::delete_promo_code(id, &connection)).await??;
+    Ok(HttpResponse::Ok().finish())
+}
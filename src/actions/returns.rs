@@ -0,0 +1,95 @@
+use actix_session::Session;
+use actix_web::{get, http::header, post, web, HttpResponse};
+
+use super::orders::{ensure_placed, placed_orders};
+use crate::db::{
+    connect::DbPool,
+    dal::returns,
+    models::{FormReturn, FormReturnReceipt},
+};
+use crate::errors::ApiError;
+use crate::payments::PaymentGateway;
+use crate::validation::validate;
+
+/// Requests the return of items of an order of the session, each with a
+/// reason.
+#[post("/orders/{id}/returns")]
+async fn return_create(
+    id: web::Path<i32>,
+    request: web::Json<FormReturn>,
+    session: Session,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    validate(&*request)?;
+    let id = id.into_inner();
+    ensure_placed(&placed_orders(&session), id)?;
+    let connection = pool.get()?;
+    let request = request.into_inner();
+    let request = web::block(move || returns::create_return(id, request, &connection)).await??;
+    Ok(HttpResponse::Created()
+        .insert_header((header::LOCATION, format!("/returns/{}", request.id)))
+        .json(request))
+}
+
+#[get("/orders/{id}/returns")]
+async fn return_list(id: web::Path<i32>, session: Session, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let id = id.into_inner();
+    ensure_placed(&placed_orders(&session), id)?;
+    let connection = pool.get()?;
+    let requests = web::block(move || returns::list_returns(id, &connection)).await??;
+    Ok(HttpResponse::Ok().json(requests))
+}
+
+#[get("/returns/{id}")]
+async fn return_show(id: web::Path<i32>, session: Session, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let request = web::block(move || returns::show_return(id, &connection)).await??;
+    ensure_placed(&placed_orders(&session), request.order_id)
+        .map_err(|_| ApiError::NotFound(format!("return {}", id)))?;
+    Ok(HttpResponse::Ok().json(request))
+}
+
+#[post("/returns/{id}/approve")]
+async fn return_approve(id: web::Path<i32>, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let request = web::block(move || returns::approve_return(id, &connection)).await??;
+    Ok(HttpResponse::Ok().json(request))
+}
+
+#[post("/returns/{id}/reject")]
+async fn return_reject(id: web::Path<i32>, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let request = web::block(move || returns::reject_return(id, &connection)).await??;
+    Ok(HttpResponse::Ok().json(request))
+}
+
+/// Puts the items of an approved return back into stock, at the central
+/// warehouse unless `location_id` says otherwise.
+#[post("/returns/{id}/receive")]
+async fn return_receive(
+    id: web::Path<i32>,
+    receipt: web::Json<FormReturnReceipt>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let receipt = receipt.into_inner();
+    let request = web::block(move || returns::receive_return(id, receipt, &connection)).await??;
+    Ok(HttpResponse::Ok().json(request))
+}
+
+/// Pays the refund of a received return back through the payment gateway.
+#[post("/returns/{id}/refund")]
+async fn return_refund(
+    id: web::Path<i32>,
+    gateway: web::Data<dyn PaymentGateway>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let request = web::block(move || returns::refund_return(id, &**gateway, &connection)).await??;
+    Ok(HttpResponse::Ok().json(request))
+}
@@ -0,0 +1,81 @@
+use actix_web::{delete, get, post, put, web, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+use crate::db::{
+    connect::DbPool,
+    dal::{sku_matrix, skus},
+    models::{FormSku, SkuMatrix},
+};
+use crate::errors::ApiError;
+use crate::validation::validate;
+
+#[post("/products/{id}/skus")]
+async fn sku_create(id: web::Path<i32>, sku: web::Json<FormSku>, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    validate(&*sku)?;
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let sku = sku.into_inner();
+    let sku = web::block(move || skus::create_sku(id, sku, &connection)).await??;
+    Ok(HttpResponse::Created().json(sku))
+}
+
+#[derive(Serialize, Deserialize)]
+struct SkuMatrixQueryParams {
+    preview: Option<bool>,
+}
+
+/// Creates the SKUs of every combination of the variant values of a product
+/// that isn't one yet, or only lists them with `?preview=true`.
+#[post("/products/{id}/skus/matrix")]
+async fn sku_matrix_generate(
+    id: web::Path<i32>,
+    matrix: web::Json<SkuMatrix>,
+    query_params: web::Query<SkuMatrixQueryParams>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    validate(&*matrix)?;
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let matrix = matrix.into_inner();
+    if query_params.preview.unwrap_or(false) {
+        let plans = web::block(move || sku_matrix::preview_sku_matrix(id, &matrix, &connection)).await??;
+        return Ok(HttpResponse::Ok().json(plans));
+    }
+
+    let skus = web::block(move || sku_matrix::generate_sku_matrix(id, &matrix, &connection)).await??;
+    Ok(HttpResponse::Created().json(skus))
+}
+
+#[get("/products/{id}/skus")]
+async fn sku_list(id: web::Path<i32>, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let skus = web::block(move || skus::list_skus(id, &connection)).await??;
+    Ok(HttpResponse::Ok().json(skus))
+}
+
+#[get("/skus/{id}")]
+async fn sku_show(id: web::Path<i32>, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let sku = web::block(move || skus::show_sku(id, &connection)).await??;
+    Ok(HttpResponse::Ok().json(sku))
+}
+
+#[put("/skus/{id}")]
+async fn sku_update(id: web::Path<i32>, sku: web::Json<FormSku>, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    validate(&*sku)?;
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let sku = sku.into_inner();
+    let sku = web::block(move || skus::update_sku(id, sku, &connection)).await??;
+    Ok(HttpResponse::Ok().json(sku))
+}
+
+#[delete("/skus/{id}")]
+async fn sku_delete(id: web::Path<i32>, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let _deleted_sku_id = web::block(move || skus::delete_sku(id, &connection)).await??;
+    Ok(HttpResponse::Ok().finish())
+}
@@ -0,0 +1,48 @@
+use actix_web::{delete, get, post, put, web, HttpResponse};
+
+use crate::db::{connect::DbPool, dal::synonyms, models::FormSynonymGroup};
+use crate::errors::ApiError;
+
+#[post("/synonyms")]
+async fn synonym_group_create(group: web::Json<FormSynonymGroup>, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let group = group.into_inner();
+    let group = web::block(move || synonyms::create_synonym_group(group, &connection)).await??;
+    Ok(HttpResponse::Created().json(group))
+}
+
+#[get("/synonyms")]
+async fn synonym_group_list(pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let groups = web::block(move || synonyms::list_synonym_groups(&connection)).await??;
+    Ok(HttpResponse::Ok().json(groups))
+}
+
+#[get("/synonyms/{id}")]
+async fn synonym_group_show(id: web::Path<i32>, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let group = web::block(move || synonyms::show_synonym_group(id, &connection)).await??;
+    Ok(HttpResponse::Ok().json(group))
+}
+
+#[put("/synonyms/{id}")]
+async fn synonym_group_update(
+    id: web::Path<i32>,
+    group: web::Json<FormSynonymGroup>,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let group = group.into_inner();
+    let group = web::block(move || synonyms::update_synonym_group(id, group, &connection)).await??;
+    Ok(HttpResponse::Ok().json(group))
+}
+
+#[delete("/synonyms/{id}")]
+async fn synonym_group_delete(id: web::Path<i32>, pool: web::Data<DbPool>) -> Result<HttpResponse, ApiError> {
+    let connection = pool.get()?;
+    let id = id.into_inner();
+    let _deleted_group_id = web::block(move || synonyms::delete_synonym_group(id, &connection)).await??;
+    Ok(HttpResponse::Ok().finish())
+}
@@ -1,4 +1,9 @@
 pub mod connect;
 pub mod dal;
+pub mod filters;
 pub mod models;
+pub mod money;
+pub mod order_status;
+pub mod pagination;
+pub mod responses;
 mod schema;
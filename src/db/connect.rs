@@ -1,15 +1,29 @@
 use diesel::prelude::*;
-use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::r2d2::{self, ConnectionManager, CustomizeConnection, Pool};
 use diesel::sqlite::SqliteConnection;
 
 pub type DbPool = Pool<ConnectionManager<SqliteConnection>>;
 
+/// Turns foreign key constraints on for each connection of the pool, the
+/// schema relies on them to cascade deletions.
+#[derive(Debug)]
+struct ForeignKeys;
+
+impl CustomizeConnection<SqliteConnection, r2d2::Error> for ForeignKeys {
+    fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<(), r2d2::Error> {
+        // Foreign key constraint is not enabled by default in SQLite, and the
+        // setting is per connection
+        // https://www.sqlite.org/foreignkeys.html
+        conn.execute("PRAGMA foreign_keys = ON")
+            .map(|_| ())
+            .map_err(r2d2::Error::QueryError)
+    }
+}
+
 pub fn establish_connection(database_url: &str) -> Pool<ConnectionManager<SqliteConnection>> {
     let manager = ConnectionManager::<SqliteConnection>::new(database_url);
-    let pool = Pool::builder().build(manager).expect("Error creating database pool");
-    let conn = pool.get().expect("Error getting connection from pool");
-    // Foreign key constraint is not enabled by default in SQLite
-    // https://www.sqlite.org/foreignkeys.html
-    conn.execute("PRAGMA foreign_keys = ON").unwrap();
-    pool
+    Pool::builder()
+        .connection_customizer(Box::new(ForeignKeys))
+        .build(manager)
+        .expect("Error creating database pool")
 }
@@ -1,14 +1,37 @@
-use super::models::{NewCompleteProduct, Product, ProductVariant, Variant, FormProduct};
+use super::models::{FormProduct, NewCompleteProduct, Product, ProductVariant, Variant};
+use super::filters::{ProductFilter, ProductSort, SortDirection, SortField};
+use super::pagination::{Cursor, CursorKey, Page, PageParams};
+use super::responses::{CurrentPrices, ProductResponse};
 use super::schema::{products, products_variants, variants};
-use anyhow::Result;
+use crate::errors::ApiError;
+use chrono::Utc;
 use diesel::{
-    sqlite::SqliteConnection, BelongingToDsl, Connection, ExpressionMethods, GroupedBy, QueryDsl,
+    sqlite::{Sqlite, SqliteConnection}, BelongingToDsl, BoolExpressionMethods, Connection,
+    EscapeExpressionMethods, ExpressionMethods, GroupedBy, OptionalExtension, QueryDsl,
     RunQueryDsl, TextExpressionMethods,
 };
 
-no_arg_sql_function!(last_insert_rowid, diesel::sql_types::Integer);
+pub type Result<T> = std::result::Result<T, ApiError>;
+
+pub mod carts;
+pub mod facets;
+pub mod inventory;
+pub mod locations;
+pub mod orders;
+pub mod payments;
+pub mod price_history;
+pub mod prices;
+pub mod promotions;
+pub mod returns;
+pub mod sales;
+pub mod search;
+pub mod sku_matrix;
+pub mod skus;
+pub mod synonyms;
+
+pub use search::search_products;
 
-const PRODUCT_DEFAULT_LIMIT: u16 = 50;
+no_arg_sql_function!(last_insert_rowid, diesel::sql_types::Integer);
 
 pub fn create_product(new_product: NewCompleteProduct, conn: &SqliteConnection) -> Result<i32> {
     conn.transaction(|| {
@@ -17,6 +40,7 @@ pub fn create_product(new_product: NewCompleteProduct, conn: &SqliteConnection)
             .execute(conn)?;
 
         let last_product_id = diesel::select(last_insert_rowid).first(conn)?;
+        let sku_matrix = new_product.sku_matrix;
 
         for new_variant in new_product.variants {
             let variants_result = variants::table
@@ -45,59 +69,208 @@ pub fn create_product(new_product: NewCompleteProduct, conn: &SqliteConnection)
                     .execute(conn)?;
             }
         }
+
+        if let Some(sku_matrix) = sku_matrix {
+            sku_matrix::generate_sku_matrix(last_product_id, &sku_matrix, conn)?;
+        }
         Ok(last_product_id)
     })
 }
 
-pub fn show_product(id: i32, conn: &SqliteConnection) -> Result<(Product, Vec<(ProductVariant, Variant)>)> {
-    let product_result = products::table.find(id).get_result::<Product>(conn)?;
+pub fn show_product(id: i32, conn: &SqliteConnection) -> Result<ProductResponse> {
+    let product_result = products::table
+        .find(id)
+        .get_result::<Product>(conn)
+        .optional()?
+        .ok_or_else(|| ApiError::NotFound(format!("product {}", id)))?;
 
-    let variants_result = ProductVariant::belonging_to(&product_result)
-        .inner_join(variants::table)
-        .load::<(ProductVariant, Variant)>(conn)?;
+    let mut responses = product_responses(vec![product_result], conn)?;
+    Ok(responses.remove(0))
+}
 
-    Ok((product_result, variants_result))
+pub fn list_products(
+    filter: ProductFilter,
+    sort: ProductSort,
+    page: PageParams,
+    conn: &SqliteConnection,
+) -> Result<Page<ProductResponse>> {
+    filter.validate()?;
+    let search = search_query(&filter, conn)?;
+    load_page(|| filtered_products(&filter, search.as_deref()), sort, &page, conn)
 }
 
-pub fn list_products(limit: Option<u16>, conn: &SqliteConnection) -> Result<Vec<(Product, Vec<(ProductVariant, Variant)>)>> {
-    let limit: i64 = limit.unwrap_or(PRODUCT_DEFAULT_LIMIT).into();
-    let products_result = products::table.limit(limit).load::<Product>(conn)?;
-    let variants_result = ProductVariant::belonging_to(&products_result)
-        .inner_join(variants::table)
-        .load::<(ProductVariant, Variant)>(conn)?
-        .grouped_by(&products_result);
-    let data = products_result
-        .into_iter()
-        .zip(variants_result)
-        .collect::<Vec<_>>();
+/// FTS5 query for the `search` of `filter`, expanded through the synonyms.
+fn search_query(filter: &ProductFilter, conn: &SqliteConnection) -> Result<Option<String>> {
+    Ok(match &filter.search {
+        Some(search) => search::expanded_fts_query(search, false, conn)?,
+        None => None,
+    })
+}
+
+/// Products matching `filter`, where `search` is the FTS5 query built out of
+/// `filter.search` by [`search_query`].
+fn filtered_products<'a>(filter: &'a ProductFilter, search: Option<&str>) -> products::BoxedQuery<'a, Sqlite> {
+    let mut query = products::table.into_boxed();
+    if let Some(search) = search {
+        query = query.filter(search::matching_products(search.to_string()));
+    }
+    if let Some(active) = filter.active {
+        query = query.filter(products::active.eq(active));
+    }
+    if let Some(min_cost) = filter.min_cost {
+        query = query.filter(products::cost.ge(min_cost));
+    }
+    if let Some(max_cost) = filter.max_cost {
+        query = query.filter(products::cost.le(max_cost));
+    }
+    if let Some(name_prefix) = &filter.name_prefix {
+        query = query.filter(products::name.like(format!("{}%", escape_like(name_prefix))).escape('\\'));
+    }
+    for (name, values) in &filter.variants {
+        let matching_products = products_variants::table
+            .inner_join(variants::table)
+            .filter(variants::name.eq(name))
+            .filter(products_variants::value.eq_any(values))
+            .select(products_variants::product_id);
+        query = query.filter(products::id.eq_any(matching_products));
+    }
+    query
+}
 
-    Ok(data)
+fn escape_like(term: &str) -> String {
+    term.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
 }
 
-pub fn search_products(search: String, conn: &SqliteConnection) -> Result<Vec<(Product, Vec<(ProductVariant, Variant)>)>> {
-    let pattern = format!("%{}%", search);
-    let products_result = products::table
-        .filter(products::name.like(pattern))
-        .load::<Product>(conn)?;
-    let variants_result = ProductVariant::belonging_to(&products_result)
+/// Loads one page of the products selected by `query`, in `sort` order.
+///
+/// `query` is called twice since boxed queries can't be cloned: once to count
+/// every match and once to fetch the page itself.
+fn load_page<'a, F>(query: F, sort: ProductSort, page: &PageParams, conn: &SqliteConnection) -> Result<Page<ProductResponse>>
+where
+    F: Fn() -> products::BoxedQuery<'a, Sqlite>,
+{
+    let total = query().count().get_result::<i64>(conn)?;
+
+    let limit = page.limit();
+    let mut page_query = sorted(query(), sort).limit(limit + 1);
+    page_query = match page.cursor()? {
+        Some(cursor) => after_cursor(page_query, sort, cursor)?,
+        None => page_query.offset(page.offset()),
+    };
+    let mut products_result = page_query.load::<Product>(conn)?;
+
+    let next_cursor = if products_result.len() as i64 > limit {
+        products_result.truncate(limit as usize);
+        products_result
+            .last()
+            .map(|product| cursor_for(product, sort).encode())
+    } else {
+        None
+    };
+
+    let items = product_responses(products_result, conn)?;
+
+    Ok(Page { items, next_cursor, total })
+}
+
+/// Pairs each product with its variants and its effective price at the time
+/// of the call, keeping the order of `products`.
+fn product_responses(products: Vec<Product>, conn: &SqliteConnection) -> Result<Vec<ProductResponse>> {
+    let variants_result = ProductVariant::belonging_to(&products)
         .inner_join(variants::table)
+        .order(products_variants::id.asc())
         .load::<(ProductVariant, Variant)>(conn)?
-        .grouped_by(&products_result);
-    let data = products_result
+        .grouped_by(&products);
+    let product_ids = products.iter().map(|product| product.id).collect::<Vec<_>>();
+    let now = Utc::now().naive_utc();
+    let sale_costs = sales::current_sale_costs(&product_ids, now, conn)?;
+    let lowest_costs = price_history::lowest_prior_costs(&product_ids, now, conn)?;
+
+    Ok(products
         .into_iter()
         .zip(variants_result)
-        .collect::<Vec<_>>();
+        .map(|(product, variants)| {
+            let prices = CurrentPrices {
+                sale_cost: sale_costs.get(&product.id).copied(),
+                lowest_30_day_cost: lowest_costs.get(&product.id).copied(),
+            };
+            ProductResponse::new(product, variants, prices)
+        })
+        .collect())
+}
+
+fn sorted(query: products::BoxedQuery<'_, Sqlite>, sort: ProductSort) -> products::BoxedQuery<'_, Sqlite> {
+    use SortDirection::{Asc, Desc};
+    match (sort.field, sort.direction) {
+        (SortField::Id, Asc) => query.order(products::id.asc()),
+        (SortField::Id, Desc) => query.order(products::id.desc()),
+        (SortField::Name, Asc) => query.order((products::name.asc(), products::id.asc())),
+        (SortField::Name, Desc) => query.order((products::name.desc(), products::id.desc())),
+        (SortField::Cost, Asc) => query.order((products::cost.asc(), products::id.asc())),
+        (SortField::Cost, Desc) => query.order((products::cost.desc(), products::id.desc())),
+    }
+}
+
+fn after_cursor(
+    query: products::BoxedQuery<'_, Sqlite>,
+    sort: ProductSort,
+    cursor: Cursor,
+) -> Result<products::BoxedQuery<'_, Sqlite>> {
+    use SortDirection::{Asc, Desc};
+    let id = cursor.id;
+    let query = match (sort.field, sort.direction, cursor.key) {
+        (SortField::Id, Asc, None) => query.filter(products::id.gt(id)),
+        (SortField::Id, Desc, None) => query.filter(products::id.lt(id)),
+        (SortField::Name, Asc, Some(CursorKey::Name(name))) => query.filter(
+            products::name.gt(name.clone()).or(products::name.eq(name).and(products::id.gt(id))),
+        ),
+        (SortField::Name, Desc, Some(CursorKey::Name(name))) => query.filter(
+            products::name.lt(name.clone()).or(products::name.eq(name).and(products::id.lt(id))),
+        ),
+        (SortField::Cost, Asc, Some(CursorKey::Cost(cost))) => query.filter(
+            products::cost.gt(cost).or(products::cost.eq(cost).and(products::id.gt(id))),
+        ),
+        (SortField::Cost, Desc, Some(CursorKey::Cost(cost))) => query.filter(
+            products::cost.lt(cost).or(products::cost.eq(cost).and(products::id.lt(id))),
+        ),
+        _ => {
+            return Err(ApiError::Validation(
+                "cursor does not match the requested sort".to_string(),
+            ))
+        }
+    };
+    Ok(query)
+}
 
-    Ok(data)
+fn cursor_for(product: &Product, sort: ProductSort) -> Cursor {
+    let key = match sort.field {
+        SortField::Id => None,
+        SortField::Name => Some(CursorKey::Name(product.name.clone())),
+        SortField::Cost => Some(CursorKey::Cost(product.cost)),
+    };
+    Cursor { id: product.id, key }
 }
 
+/// Updates a product along with the variant values of `form_product`, which
+/// must all be values of that product. A variant can't end up with the same
+/// value twice.
 pub fn update_product(product_id: i32, form_product: FormProduct, conn: &SqliteConnection) -> Result<i32> {
     conn.transaction(|| {
-        diesel::update(products::table.find(product_id))
+        let updated = diesel::update(products::table.find(product_id))
             .set(&form_product.product)
             .execute(conn)?;
+        if updated == 0 {
+            return Err(ApiError::NotFound(format!("product {}", product_id)));
+        }
 
         for mut form_product_variant in form_product.variants {
+            if form_product_variant.product_variant.product_id != product_id {
+                return Err(ApiError::Validation(format!(
+                    "variant values of product {} can't be updated through product {}",
+                    form_product_variant.product_variant.product_id, product_id
+                )));
+            }
+
             if form_product_variant.product_variant.variant_id.is_none() {
                 diesel::insert_into(variants::dsl::variants)
                     .values(form_product_variant.variant)
@@ -107,9 +280,19 @@ pub fn update_product(product_id: i32, form_product: FormProduct, conn: &SqliteC
             }
 
             if let Some(product_variant_id) = form_product_variant.product_variant.id {
-                diesel::update(products_variants::table.find(product_variant_id))
-                    .set(&form_product_variant.product_variant)
-                    .execute(conn)?;
+                let updated = diesel::update(
+                    products_variants::table
+                        .find(product_variant_id)
+                        .filter(products_variants::product_id.eq(product_id)),
+                )
+                .set(&form_product_variant.product_variant)
+                .execute(conn)?;
+                if updated == 0 {
+                    return Err(ApiError::NotFound(format!(
+                        "variant value {} of product {}",
+                        product_variant_id, product_id
+                    )));
+                }
             } else {
                 diesel::insert_into(products_variants::table)
                     .values(&form_product_variant.product_variant)
@@ -117,13 +300,44 @@ pub fn update_product(product_id: i32, form_product: FormProduct, conn: &SqliteC
             }
         }
 
+        // The payload was checked for duplicates, not against the values kept
+        let mut values = products_variants::table
+            .filter(products_variants::product_id.eq(product_id))
+            .select((products_variants::variant_id, products_variants::value))
+            .load::<(i32, Option<String>)>(conn)?;
+        values.sort();
+        if let Some(pair) = values.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(ApiError::Validation(format!(
+                "product {} would have the value {} twice for variant {}",
+                product_id,
+                pair[0].1.as_deref().unwrap_or("null"),
+                pair[0].0
+            )));
+        }
+
         Ok(product_id)
     })
 }
 
-pub fn delete_product(id: i32, conn: &SqliteConnection) ->  Result<i32> {
-    diesel::delete(products::table.find(id))
-        .execute(conn)?;
+/// Deletes a product along with its variant values, prices, sales and SKUs.
+/// Lines of past orders keep their copy of its name and price. A product with
+/// a SKU that had stock is kept for its inventory history, it can be
+/// deactivated instead.
+pub fn delete_product(id: i32, conn: &SqliteConnection) -> Result<i32> {
+    let sku_ids = skus::product_combinations(id, conn)?
+        .into_iter()
+        .map(|(sku_id, _)| sku_id)
+        .collect::<Vec<_>>();
+    if inventory::has_movements(&sku_ids, conn)? {
+        return Err(ApiError::Conflict(format!(
+            "product {} has an inventory history, deactivate it instead",
+            id
+        )));
+    }
+    let deleted = diesel::delete(products::table.find(id)).execute(conn)?;
+    if deleted == 0 {
+        return Err(ApiError::NotFound(format!("product {}", id)));
+    }
 
     Ok(id)
 }
\ No newline at end of file
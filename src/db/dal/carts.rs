@@ -0,0 +1,231 @@
+use super::prices::{convert_amount, price_products};
+use super::promotions::evaluate_promo_code;
+use super::skus::{checked_values, product_combinations};
+use super::{product_responses, Result};
+use crate::db::models::{Cart, CartLine, FormCartLine, Product, ProductVariant, Variant};
+use crate::db::money::Money;
+use crate::db::responses::{CartLineResponse, CartResponse, SkuOption};
+use crate::db::schema::{products, products_variants, skus, variants};
+use crate::errors::ApiError;
+use diesel::{sqlite::SqliteConnection, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
+use std::collections::HashMap;
+
+/// Most lines a cart may have, so that it still fits in a session cookie.
+pub const MAX_CART_LINES: usize = 50;
+
+/// Adds a line to `cart`, or adds to the quantity of the line with the same
+/// product and values. Only active products can be added.
+pub fn add_cart_line(cart: &mut Cart, form: FormCartLine, conn: &SqliteConnection) -> Result<()> {
+    let active = products::table
+        .find(form.product_id)
+        .select(products::active)
+        .first::<bool>(conn)
+        .optional()?
+        .ok_or_else(|| ApiError::NotFound(format!("product {}", form.product_id)))?;
+    if !active {
+        return Err(ApiError::Validation(format!("product {} is not for sale", form.product_id)));
+    }
+    let product_variant_ids = checked_values(form.product_id, &form.product_variant_ids, conn)?;
+
+    let same_line = cart
+        .lines
+        .iter_mut()
+        .find(|line| line.product_id == form.product_id && line.product_variant_ids == product_variant_ids);
+    match same_line {
+        Some(line) => {
+            line.quantity = line
+                .quantity
+                .checked_add(form.quantity)
+                .ok_or_else(|| ApiError::Validation(format!("the quantity of cart line {} is too large", line.id)))?;
+        }
+        None => {
+            if cart.lines.len() >= MAX_CART_LINES {
+                return Err(ApiError::Validation(format!(
+                    "a cart can't have more than {} lines",
+                    MAX_CART_LINES
+                )));
+            }
+            cart.next_line_id += 1;
+            cart.lines.push(CartLine {
+                id: cart.next_line_id,
+                product_id: form.product_id,
+                product_variant_ids,
+                quantity: form.quantity,
+            });
+        }
+    }
+    Ok(())
+}
+
+pub fn update_cart_line(cart: &mut Cart, line_id: u32, quantity: i32) -> Result<()> {
+    let line = cart
+        .lines
+        .iter_mut()
+        .find(|line| line.id == line_id)
+        .ok_or_else(|| ApiError::NotFound(format!("cart line {}", line_id)))?;
+    line.quantity = quantity;
+    Ok(())
+}
+
+pub fn remove_cart_line(cart: &mut Cart, line_id: u32) -> Result<()> {
+    let lines = cart.lines.len();
+    cart.lines.retain(|line| line.id != line_id);
+    if cart.lines.len() == lines {
+        return Err(ApiError::NotFound(format!("cart line {}", line_id)));
+    }
+    Ok(())
+}
+
+/// Prices every line of `cart` in `currency` at the current prices, as
+/// [`price_products`] does. Lines of products that no longer exist are
+/// dropped from the cart.
+///
+/// A SKU with its own price follows its product like sale and compare-at
+/// prices do: its price is scaled by the ratio of the effective price of the
+/// product in `currency` to its cost. So a running sale takes as much off the
+/// SKU as off the product. The price of a SKU of a product costing zero can't
+/// be scaled, it is only converted at the stored exchange rate.
+pub fn price_cart(cart: &mut Cart, currency: &str, conn: &SqliteConnection) -> Result<CartResponse> {
+    let product_ids = cart.lines.iter().map(|line| line.product_id).collect::<Vec<_>>();
+    let products = products::table
+        .filter(products::id.eq_any(&product_ids))
+        .load::<Product>(conn)?;
+    cart.lines
+        .retain(|line| products.iter().any(|product| product.id == line.product_id));
+
+    let currencies = products
+        .iter()
+        .map(|product| (product.id, product.currency.clone()))
+        .collect::<HashMap<_, _>>();
+    let costs = products
+        .iter()
+        .map(|product| (product.id, product.cost))
+        .collect::<HashMap<_, _>>();
+    let mut responses = product_responses(products, conn)?;
+    price_products(&mut responses, currency, conn)?;
+    let responses = responses
+        .into_iter()
+        .map(|product| (product.id, product))
+        .collect::<HashMap<_, _>>();
+
+    let sku_prices = skus::table
+        .filter(skus::product_id.eq_any(&product_ids))
+        .select((skus::id, skus::price))
+        .load::<(i32, Option<Money>)>(conn)?
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+    let mut combinations = HashMap::new();
+    for product_id in responses.keys() {
+        combinations.insert(*product_id, product_combinations(*product_id, conn)?);
+    }
+    let value_ids = cart
+        .lines
+        .iter()
+        .flat_map(|line| line.product_variant_ids.iter().copied())
+        .collect::<Vec<_>>();
+    let options = products_variants::table
+        .inner_join(variants::table)
+        .filter(products_variants::id.eq_any(&value_ids))
+        .load::<(ProductVariant, Variant)>(conn)?
+        .into_iter()
+        .map(|(product_variant, variant)| {
+            let option = SkuOption {
+                product_variant_id: product_variant.id,
+                variant_id: variant.id,
+                name: variant.name,
+                value: product_variant.value,
+            };
+            (product_variant.id, option)
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut lines = Vec::with_capacity(cart.lines.len());
+    let mut item_count = 0i32;
+    let mut total = Money::default();
+    for line in &cart.lines {
+        let product = &responses[&line.product_id];
+        let sku_id = combinations[&line.product_id]
+            .iter()
+            .find(|(_, combination)| *combination == line.product_variant_ids)
+            .map(|(sku_id, _)| *sku_id);
+        let too_large = || ApiError::Validation(format!("the total of cart line {} is too large", line.id));
+        let unit_price = match sku_id.and_then(|sku_id| sku_prices[&sku_id]) {
+            Some(price) if costs[&line.product_id].minor() != 0 => price
+                .checked_scale(product.effective_cost, costs[&line.product_id])
+                .ok_or_else(too_large)?,
+            Some(price) => convert_amount(price, &currencies[&line.product_id], currency, conn)?,
+            None => product.effective_cost,
+        };
+        let line_total = unit_price.checked_mul(i64::from(line.quantity)).ok_or_else(too_large)?;
+        if product.active {
+            item_count = item_count.saturating_add(line.quantity);
+            total = total.checked_add(line_total).ok_or_else(too_large)?;
+        }
+
+        let mut line_options = line
+            .product_variant_ids
+            .iter()
+            .filter_map(|id| options.get(id).cloned())
+            .collect::<Vec<_>>();
+        line_options.sort_by_key(|option| (option.variant_id, option.product_variant_id));
+        lines.push(CartLineResponse {
+            id: line.id,
+            product_id: line.product_id,
+            name: product.name.clone(),
+            sku_id,
+            options: line_options,
+            quantity: line.quantity,
+            unit_price,
+            total: line_total,
+            discount: Money::default(),
+            available: product.active,
+        });
+    }
+
+    let mut promo_code = cart.promo_code.clone();
+    let mut promo_rejection = None;
+    let mut discount = Money::default();
+    if let Some(code) = &cart.promo_code {
+        match evaluate_promo_code(code, &lines, currency, conn)? {
+            Ok(applied) => {
+                for (line, line_discount) in lines.iter_mut().zip(applied.discounts) {
+                    line.discount = line_discount;
+                }
+                promo_code = Some(applied.promo.code);
+                discount = applied.discount;
+            }
+            Err(rejection) => promo_rejection = Some(rejection.to_string()),
+        }
+    }
+
+    Ok(CartResponse {
+        currency: currency.to_string(),
+        lines,
+        item_count,
+        subtotal: total,
+        promo_code,
+        promo_rejection,
+        discount,
+        total: total.checked_sub(discount).unwrap_or_default(),
+    })
+}
+
+/// Enters a promo code on `cart`, which must apply to it. Responds with the
+/// cart priced in `currency` with the discount.
+pub fn apply_promo_code(cart: &mut Cart, code: &str, currency: &str, conn: &SqliteConnection) -> Result<CartResponse> {
+    let previous = cart.promo_code.replace(code.to_string());
+    let priced = price_cart(cart, currency, conn)?;
+    if let Some(rejection) = priced.promo_rejection {
+        cart.promo_code = previous;
+        return Err(ApiError::Validation(rejection));
+    }
+    cart.promo_code = priced.promo_code.clone();
+    Ok(priced)
+}
+
+pub fn remove_promo_code(cart: &mut Cart) -> Result<()> {
+    cart.promo_code
+        .take()
+        .map(|_| ())
+        .ok_or_else(|| ApiError::NotFound("promo code of the cart".to_string()))
+}
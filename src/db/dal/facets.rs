@@ -0,0 +1,89 @@
+use super::{filtered_products, search_query, Result};
+use crate::db::filters::ProductFilter;
+use crate::db::schema::{products, products_variants, variants};
+use diesel::{
+    dsl::sql, query_dsl::GroupByDsl, sql_types::BigInt, sqlite::SqliteConnection,
+    ExpressionMethods, QueryDsl, RunQueryDsl,
+};
+use serde::{Deserialize, Serialize};
+
+/// Distinct values of one variant among the filtered products.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Facet {
+    pub variant_id: i32,
+    pub name: String,
+    pub values: Vec<FacetValue>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct FacetValue {
+    pub value: String,
+    pub count: i64,
+}
+
+/// Counts, for every variant, how many of the products matching `filter`
+/// carry each of its values.
+///
+/// A variant that is itself filtered on is counted without its own filter, so
+/// that the sidebar keeps offering the values that would widen the selection.
+pub fn product_facets(filter: ProductFilter, conn: &SqliteConnection) -> Result<Vec<Facet>> {
+    filter.validate()?;
+    let search = search_query(&filter, conn)?;
+    let mut facets = count_values(&filter, search.as_deref(), None, conn)?;
+
+    for name in filter.variants.keys() {
+        let mut unfiltered = filter.clone();
+        unfiltered.variants.remove(name);
+        let recounted = count_values(&unfiltered, search.as_deref(), Some(name), conn)?;
+
+        facets.retain(|facet| &facet.name != name);
+        facets.extend(recounted);
+    }
+    facets.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(facets)
+}
+
+fn count_values(
+    filter: &ProductFilter,
+    search: Option<&str>,
+    only_variant: Option<&str>,
+    conn: &SqliteConnection,
+) -> Result<Vec<Facet>> {
+    let mut query = products_variants::table
+        .inner_join(variants::table)
+        .filter(products_variants::product_id.eq_any(filtered_products(filter, search).select(products::id)))
+        .filter(products_variants::value.is_not_null())
+        .into_boxed();
+    if let Some(name) = only_variant {
+        query = query.filter(variants::name.eq(name));
+    }
+    let rows = query
+        .select((
+            variants::id,
+            variants::name,
+            products_variants::value,
+            sql::<BigInt>("COUNT(DISTINCT products_variants.product_id)"),
+        ))
+        .group_by((variants::id, products_variants::value))
+        .order((variants::name.asc(), products_variants::value.asc()))
+        .load::<(i32, String, Option<String>, i64)>(conn)?;
+
+    let mut facets: Vec<Facet> = Vec::new();
+    for (variant_id, name, value, count) in rows {
+        let value = FacetValue {
+            value: value.unwrap_or_default(),
+            count,
+        };
+        match facets.last_mut() {
+            Some(facet) if facet.variant_id == variant_id => facet.values.push(value),
+            _ => facets.push(Facet {
+                variant_id,
+                name,
+                values: vec![value],
+            }),
+        }
+    }
+
+    Ok(facets)
+}
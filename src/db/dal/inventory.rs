@@ -0,0 +1,195 @@
+use super::{last_insert_rowid, Result};
+use crate::db::models::{FormInventoryAdjustment, FormTransfer, InventoryMovement, MovementReason, Transfer};
+use crate::db::schema::{inventory_movements, location_stocks, locations, skus, transfers};
+use crate::errors::ApiError;
+use chrono::Utc;
+use diesel::{sqlite::SqliteConnection, Connection, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
+
+pub fn adjust_stock(sku_id: i32, form: FormInventoryAdjustment, conn: &SqliteConnection) -> Result<InventoryMovement> {
+    record_movement(sku_id, form.location_id, form.reason, form.quantity, form.note.as_deref(), conn)
+}
+
+/// Every movement of the stock of a SKU, oldest first.
+pub fn list_movements(sku_id: i32, conn: &SqliteConnection) -> Result<Vec<InventoryMovement>> {
+    allows_backorder(sku_id, conn)?;
+    Ok(inventory_movements::table
+        .filter(inventory_movements::sku_id.eq(sku_id))
+        .order(inventory_movements::id.asc())
+        .load::<InventoryMovement>(conn)?)
+}
+
+/// Moves stock of a SKU between two locations, as one transfer and a
+/// movement on each side, all or nothing. Backorders don't apply: the origin
+/// must hold the whole quantity.
+pub fn transfer_stock(sku_id: i32, form: FormTransfer, conn: &SqliteConnection) -> Result<Transfer> {
+    conn.transaction(|| {
+        allows_backorder(sku_id, conn)?;
+        let from_balance = move_stock(sku_id, form.from_location_id, -form.quantity, false, conn)?;
+        let to_balance = move_stock(sku_id, form.to_location_id, form.quantity, false, conn)?;
+
+        diesel::insert_into(transfers::table)
+            .values((
+                transfers::sku_id.eq(sku_id),
+                transfers::from_location_id.eq(form.from_location_id),
+                transfers::to_location_id.eq(form.to_location_id),
+                transfers::quantity.eq(form.quantity),
+                transfers::note.eq(&form.note),
+                transfers::created_at.eq(Utc::now().naive_utc()),
+            ))
+            .execute(conn)?;
+        let transfer = transfers::table
+            .find(diesel::select(last_insert_rowid).first::<i32>(conn)?)
+            .first::<Transfer>(conn)?;
+
+        let movement = |location_id: i32, quantity: i32, balance: i32| Movement {
+            sku_id,
+            location_id,
+            transfer_id: Some(transfer.id),
+            reason: MovementReason::Transfer,
+            quantity,
+            balance,
+            note: form.note.as_deref(),
+        };
+        insert_movement(movement(form.from_location_id, -form.quantity, from_balance), conn)?;
+        insert_movement(movement(form.to_location_id, form.quantity, to_balance), conn)?;
+
+        Ok(transfer)
+    })
+}
+
+/// Every transfer of a SKU, oldest first.
+pub fn list_transfers(sku_id: i32, conn: &SqliteConnection) -> Result<Vec<Transfer>> {
+    allows_backorder(sku_id, conn)?;
+    Ok(transfers::table
+        .filter(transfers::sku_id.eq(sku_id))
+        .order(transfers::id.asc())
+        .load::<Transfer>(conn)?)
+}
+
+/// Appends a movement of `quantity` at a location to the ledger of a SKU and
+/// moves its stock there by as much, in one transaction. Fails with a
+/// conflict when the stock at the location would go below zero and the SKU
+/// doesn't allow backorders.
+pub(super) fn record_movement(
+    sku_id: i32,
+    location_id: i32,
+    reason: MovementReason,
+    quantity: i32,
+    note: Option<&str>,
+    conn: &SqliteConnection,
+) -> Result<InventoryMovement> {
+    conn.transaction(|| {
+        let allow_backorder = allows_backorder(sku_id, conn)?;
+        let balance = move_stock(sku_id, location_id, quantity, allow_backorder, conn)?;
+        insert_movement(
+            Movement {
+                sku_id,
+                location_id,
+                transfer_id: None,
+                reason,
+                quantity,
+                balance,
+                note,
+            },
+            conn,
+        )
+    })
+}
+
+struct Movement<'a> {
+    sku_id: i32,
+    location_id: i32,
+    transfer_id: Option<i32>,
+    reason: MovementReason,
+    quantity: i32,
+    balance: i32,
+    note: Option<&'a str>,
+}
+
+fn insert_movement(movement: Movement, conn: &SqliteConnection) -> Result<InventoryMovement> {
+    diesel::insert_into(inventory_movements::table)
+        .values((
+            inventory_movements::sku_id.eq(movement.sku_id),
+            inventory_movements::location_id.eq(movement.location_id),
+            inventory_movements::transfer_id.eq(movement.transfer_id),
+            inventory_movements::reason.eq(movement.reason),
+            inventory_movements::quantity.eq(movement.quantity),
+            inventory_movements::balance.eq(movement.balance),
+            inventory_movements::note.eq(movement.note),
+            inventory_movements::created_at.eq(Utc::now().naive_utc()),
+        ))
+        .execute(conn)?;
+    let id = diesel::select(last_insert_rowid).first::<i32>(conn)?;
+    Ok(inventory_movements::table.find(id).first::<InventoryMovement>(conn)?)
+}
+
+/// Moves the stock of a SKU at a location, and so its total stock, by
+/// `quantity` and returns the new stock at the location.
+///
+/// The stock is checked and updated by a single statement, so that no
+/// concurrent movement can slip between the check and the update.
+fn move_stock(
+    sku_id: i32,
+    location_id: i32,
+    quantity: i32,
+    allow_backorder: bool,
+    conn: &SqliteConnection,
+) -> Result<i32> {
+    let location = locations::table
+        .find(location_id)
+        .select(locations::id)
+        .first::<i32>(conn)
+        .optional()?;
+    if location.is_none() {
+        return Err(ApiError::NotFound(format!("location {}", location_id)));
+    }
+
+    diesel::insert_or_ignore_into(location_stocks::table)
+        .values((
+            location_stocks::location_id.eq(location_id),
+            location_stocks::sku_id.eq(sku_id),
+            location_stocks::stock.eq(0),
+        ))
+        .execute(conn)?;
+    let stock = location_stocks::table.find((location_id, sku_id));
+    let new_stock = location_stocks::stock.eq(location_stocks::stock + quantity);
+    let updated = if allow_backorder {
+        diesel::update(stock).set(new_stock).execute(conn)?
+    } else {
+        diesel::update(stock.filter((location_stocks::stock + quantity).ge(0)))
+            .set(new_stock)
+            .execute(conn)?
+    };
+    if updated == 0 {
+        let current = stock.select(location_stocks::stock).first::<i32>(conn)?;
+        return Err(ApiError::Conflict(format!(
+            "sku {} has {} in stock at location {}, it can't go down by {}",
+            sku_id, current, location_id, -quantity
+        )));
+    }
+
+    diesel::update(skus::table.find(sku_id))
+        .set(skus::stock.eq(skus::stock + quantity))
+        .execute(conn)?;
+    Ok(stock.select(location_stocks::stock).first::<i32>(conn)?)
+}
+
+/// Whether one of `sku_ids` has movements. Its ledger is kept for good, so it
+/// can't be deleted.
+pub(super) fn has_movements(sku_ids: &[i32], conn: &SqliteConnection) -> Result<bool> {
+    let movement = inventory_movements::table
+        .filter(inventory_movements::sku_id.eq_any(sku_ids))
+        .select(inventory_movements::id)
+        .first::<i32>(conn)
+        .optional()?;
+    Ok(movement.is_some())
+}
+
+fn allows_backorder(sku_id: i32, conn: &SqliteConnection) -> Result<bool> {
+    skus::table
+        .find(sku_id)
+        .select(skus::allow_backorder)
+        .first::<bool>(conn)
+        .optional()?
+        .ok_or_else(|| ApiError::NotFound(format!("sku {}", sku_id)))
+}
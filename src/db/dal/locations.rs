@@ -0,0 +1,146 @@
+use super::{last_insert_rowid, Result};
+use crate::db::models::{FormLocation, Location, DEFAULT_LOCATION_ID};
+use crate::db::responses::{Availability, LocationStock, ProductResponse};
+use crate::db::schema::{inventory_movements, location_stocks, locations, skus, transfers};
+use crate::errors::ApiError;
+use diesel::{
+    dsl::exists, sqlite::SqliteConnection, BoolExpressionMethods, Connection, ExpressionMethods, OptionalExtension,
+    QueryDsl, RunQueryDsl,
+};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+pub fn create_location(form: FormLocation, conn: &SqliteConnection) -> Result<Location> {
+    conn.transaction(|| {
+        diesel::insert_into(locations::table).values(&form).execute(conn)?;
+        let id = diesel::select(last_insert_rowid).first(conn)?;
+        show_location(id, conn)
+    })
+}
+
+pub fn list_locations(conn: &SqliteConnection) -> Result<Vec<Location>> {
+    Ok(locations::table.order(locations::id.asc()).load::<Location>(conn)?)
+}
+
+pub fn show_location(id: i32, conn: &SqliteConnection) -> Result<Location> {
+    locations::table
+        .find(id)
+        .first::<Location>(conn)
+        .optional()?
+        .ok_or_else(|| ApiError::NotFound(format!("location {}", id)))
+}
+
+pub fn update_location(id: i32, form: FormLocation, conn: &SqliteConnection) -> Result<Location> {
+    let updated = diesel::update(locations::table.find(id)).set(&form).execute(conn)?;
+    if updated == 0 {
+        return Err(ApiError::NotFound(format!("location {}", id)));
+    }
+    show_location(id, conn)
+}
+
+/// Deletes a location that never held any stock. The central warehouse is
+/// never deleted, as movements without a location go there.
+pub fn delete_location(id: i32, conn: &SqliteConnection) -> Result<i32> {
+    conn.transaction(|| {
+        show_location(id, conn)?;
+        if id == DEFAULT_LOCATION_ID {
+            return Err(ApiError::Conflict(format!("location {} is the default location", id)));
+        }
+        let movements = inventory_movements::table.filter(inventory_movements::location_id.eq(id));
+        let transfers = transfers::table.filter(transfers::from_location_id.eq(id).or(transfers::to_location_id.eq(id)));
+        let has_history = diesel::select(exists(movements)).get_result::<bool>(conn)?
+            || diesel::select(exists(transfers)).get_result::<bool>(conn)?;
+        if has_history {
+            return Err(ApiError::Conflict(format!("location {} has inventory history", id)));
+        }
+
+        diesel::delete(location_stocks::table.filter(location_stocks::location_id.eq(id))).execute(conn)?;
+        diesel::delete(locations::table.find(id)).execute(conn)?;
+        Ok(id)
+    })
+}
+
+/// Stock of a SKU at every location, including those without any.
+pub fn sku_stock(sku_id: i32, conn: &SqliteConnection) -> Result<Vec<LocationStock>> {
+    let sku = skus::table.find(sku_id).select(skus::id).first::<i32>(conn).optional()?;
+    if sku.is_none() {
+        return Err(ApiError::NotFound(format!("sku {}", sku_id)));
+    }
+
+    let stocks = location_stocks::table
+        .filter(location_stocks::sku_id.eq(sku_id))
+        .select((location_stocks::location_id, location_stocks::stock))
+        .load::<(i32, i32)>(conn)?
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+    Ok(list_locations(conn)?
+        .into_iter()
+        .map(|location| LocationStock {
+            location_id: location.id,
+            stock: stocks.get(&location.id).copied().unwrap_or(0),
+            name: location.name,
+        })
+        .collect())
+}
+
+/// How much detail the availability of products is reported with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AvailabilityView {
+    /// A single stock over every location.
+    Total,
+    /// The total along with the stock at each location.
+    PerLocation,
+}
+
+impl FromStr for AvailabilityView {
+    type Err = ApiError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "total" => Ok(AvailabilityView::Total),
+            "location" => Ok(AvailabilityView::PerLocation),
+            other => Err(ApiError::Validation(format!(
+                "unknown availability '{}', expected total or location",
+                other
+            ))),
+        }
+    }
+}
+
+/// Fills in the availability of each of `products`.
+pub fn add_availability<'a, I>(products: I, view: AvailabilityView, conn: &SqliteConnection) -> Result<()>
+where
+    I: IntoIterator<Item = &'a mut ProductResponse>,
+{
+    let products = products.into_iter().collect::<Vec<_>>();
+    let product_ids = products.iter().map(|product| product.id).collect::<Vec<_>>();
+    let mut stocks: HashMap<(i32, i32), i32> = HashMap::new();
+    for (product_id, location_id, stock) in location_stocks::table
+        .inner_join(skus::table)
+        .filter(skus::product_id.eq_any(&product_ids))
+        .select((skus::product_id, location_stocks::location_id, location_stocks::stock))
+        .load::<(i32, i32, i32)>(conn)?
+    {
+        *stocks.entry((product_id, location_id)).or_default() += stock.max(0);
+    }
+    let all_locations = list_locations(conn)?;
+
+    for product in products {
+        let locations = all_locations
+            .iter()
+            .map(|location| LocationStock {
+                location_id: location.id,
+                name: location.name.clone(),
+                stock: stocks.get(&(product.id, location.id)).copied().unwrap_or(0),
+            })
+            .collect::<Vec<_>>();
+        product.availability = Some(Availability {
+            stock: locations.iter().map(|location| location.stock).sum(),
+            locations: match view {
+                AvailabilityView::Total => None,
+                AvailabilityView::PerLocation => Some(locations),
+            },
+        });
+    }
+    Ok(())
+}
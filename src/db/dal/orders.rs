@@ -0,0 +1,259 @@
+use super::carts::price_cart;
+use super::inventory::record_movement;
+use super::payments::{reserve_order_refunds, settle_refund};
+use super::promotions::{check_customer, find_promo_code, normalize_email};
+use super::{last_insert_rowid, Result};
+use crate::db::models::{
+    Cart, FormOrderTransition, MovementReason, Order, OrderEvent, OrderLine, OrderLineOption, Refund,
+    DEFAULT_LOCATION_ID,
+};
+use crate::db::order_status::{OrderStatus, OrderTransition};
+use crate::db::responses::{OrderResponse, OrderTransitionResponse};
+use crate::db::schema::{order_events, order_line_options, order_lines, orders, refunds, skus};
+use crate::errors::ApiError;
+use crate::payments::PaymentGateway;
+use chrono::Utc;
+use diesel::{
+    dsl::exists, sqlite::SqliteConnection, BelongingToDsl, Connection, ExpressionMethods, GroupedBy, OptionalExtension,
+    QueryDsl, RunQueryDsl,
+};
+
+/// Places an order for everything in `cart`, priced in `currency` at the
+/// current prices, then empties the cart and takes its promo code off.
+///
+/// The order, its lines and the sale of each SKU out of the central warehouse
+/// are recorded in one transaction: a SKU short of stock fails the whole
+/// checkout with a conflict and leaves the cart as it was. Products that have
+/// SKUs can only be ordered through one of them.
+///
+/// The promo code of the cart must still apply, `customer_email` being who
+/// its limit per customer is counted against. An order it leaves nothing to
+/// pay for is paid right away.
+pub fn checkout(
+    cart: &mut Cart,
+    currency: &str,
+    customer_email: Option<&str>,
+    conn: &SqliteConnection,
+) -> Result<OrderResponse> {
+    let customer_email = customer_email.map(normalize_email);
+    let order_id = conn.transaction(|| {
+        let priced = price_cart(cart, currency, conn)?;
+        if priced.lines.is_empty() {
+            return Err(ApiError::Validation("the cart is empty".to_string()));
+        }
+        if let Some(rejection) = priced.promo_rejection {
+            return Err(ApiError::Validation(rejection));
+        }
+        let promo = match &priced.promo_code {
+            Some(code) => find_promo_code(code, conn)?,
+            None => None,
+        };
+        if let Some(promo) = &promo {
+            if let Some(rejection) = check_customer(promo, customer_email.as_deref(), conn)? {
+                return Err(rejection.into());
+            }
+        }
+        for line in &priced.lines {
+            if !line.available {
+                return Err(ApiError::Validation(format!("product {} is no longer for sale", line.product_id)));
+            }
+            let has_skus = diesel::select(exists(skus::table.filter(skus::product_id.eq(line.product_id))))
+                .get_result::<bool>(conn)?;
+            if line.sku_id.is_none() && has_skus {
+                return Err(ApiError::Validation(format!(
+                    "product {} has no SKU with the values of cart line {}",
+                    line.product_id, line.id
+                )));
+            }
+        }
+
+        diesel::insert_into(orders::table)
+            .values((
+                orders::currency.eq(&priced.currency),
+                orders::item_count.eq(priced.item_count),
+                orders::total.eq(priced.total),
+                orders::created_at.eq(Utc::now().naive_utc()),
+                orders::promo_code_id.eq(promo.as_ref().map(|promo| promo.id)),
+                orders::promo_code.eq(promo.as_ref().map(|promo| &promo.code)),
+                orders::discount.eq(priced.discount),
+                orders::customer_email.eq(&customer_email),
+            ))
+            .execute(conn)?;
+        let order_id = diesel::select(last_insert_rowid).first::<i32>(conn)?;
+        insert_event(order_id, None, OrderStatus::Pending, None, conn)?;
+        let note = format!("order {}", order_id);
+
+        for line in priced.lines {
+            diesel::insert_into(order_lines::table)
+                .values((
+                    order_lines::order_id.eq(order_id),
+                    order_lines::product_id.eq(line.product_id),
+                    order_lines::sku_id.eq(line.sku_id),
+                    order_lines::product_name.eq(&line.name),
+                    order_lines::quantity.eq(line.quantity),
+                    order_lines::unit_price.eq(line.unit_price),
+                    order_lines::total.eq(line.total),
+                    order_lines::discount.eq(line.discount),
+                ))
+                .execute(conn)?;
+            let order_line_id = diesel::select(last_insert_rowid).first::<i32>(conn)?;
+            let options = line
+                .options
+                .into_iter()
+                .zip(0..)
+                .map(|(option, position)| OrderLineOption {
+                    order_line_id,
+                    position,
+                    name: option.name,
+                    value: option.value,
+                })
+                .collect::<Vec<_>>();
+            diesel::insert_into(order_line_options::table).values(&options).execute(conn)?;
+
+            if let Some(sku_id) = line.sku_id {
+                record_movement(sku_id, DEFAULT_LOCATION_ID, MovementReason::Sale, -line.quantity, Some(&note), conn)?;
+            }
+        }
+        // No gateway takes a payment of nothing
+        if !priced.total.is_positive() {
+            apply_transition(order_id, OrderTransition::Pay, Some("nothing to pay"), conn)?;
+        }
+        Ok(order_id)
+    })?;
+
+    cart.lines.clear();
+    cart.promo_code = None;
+    show_order(order_id, conn)
+}
+
+pub fn show_order(id: i32, conn: &SqliteConnection) -> Result<OrderResponse> {
+    let order = orders::table
+        .find(id)
+        .first::<Order>(conn)
+        .optional()?
+        .ok_or_else(|| ApiError::NotFound(format!("order {}", id)))?;
+    let lines = OrderLine::belonging_to(&order)
+        .order(order_lines::id.asc())
+        .load::<OrderLine>(conn)?;
+    let options = OrderLineOption::belonging_to(&lines)
+        .order(order_line_options::position.asc())
+        .load::<OrderLineOption>(conn)?
+        .grouped_by(&lines);
+
+    Ok(OrderResponse::new(order, lines.into_iter().zip(options).collect()))
+}
+
+/// Moves an order on to the status `transition` leads to, recording the
+/// event. Cancelling an order, or refunding it before it is shipped, puts the
+/// stock of its SKUs back into the central warehouse, where checkout took it
+/// from.
+///
+/// Orders are only paid through their payments. Cancelling or refunding one
+/// refunds whatever is left of its captured payments through `gateway`. The
+/// order has moved on by then, so every refund is asked for and those that
+/// fail are returned along with it.
+pub fn transition_order(
+    id: i32,
+    form: FormOrderTransition,
+    gateway: &dyn PaymentGateway,
+    conn: &SqliteConnection,
+) -> Result<OrderTransitionResponse> {
+    if form.transition == OrderTransition::Pay {
+        return Err(ApiError::Validation(format!("order {} can only be paid through a payment", id)));
+    }
+    let refunds = conn.transaction(|| {
+        apply_transition(id, form.transition, form.note.as_deref(), conn)?;
+        match form.transition {
+            OrderTransition::Cancel | OrderTransition::Refund => reserve_order_refunds(id, gateway, conn),
+            _ => Ok(Vec::new()),
+        }
+    })?;
+    let mut failed = Vec::new();
+    for refund in refunds {
+        if let Err(error) = settle_refund(refund, gateway, conn) {
+            log::warn!("refund {} of order {} failed: {}", refund, id, error);
+            failed.push(refund);
+        }
+    }
+    let failed_refunds = refunds::table
+        .filter(refunds::id.eq_any(&failed))
+        .order(refunds::id.asc())
+        .load::<Refund>(conn)?;
+
+    Ok(OrderTransitionResponse {
+        order: show_order(id, conn)?,
+        failed_refunds,
+    })
+}
+
+/// Same as [`transition_order`], to be run in a transaction of the caller.
+pub(super) fn apply_transition(
+    id: i32,
+    transition: OrderTransition,
+    note: Option<&str>,
+    conn: &SqliteConnection,
+) -> Result<OrderStatus> {
+    let status = orders::table
+        .find(id)
+        .select(orders::status)
+        .first::<OrderStatus>(conn)
+        .optional()?
+        .ok_or_else(|| ApiError::NotFound(format!("order {}", id)))?;
+    let next = status.try_apply(id, transition)?;
+    // Only move on from the status the transition was checked against
+    let updated = diesel::update(orders::table.find(id).filter(orders::status.eq(status)))
+        .set(orders::status.eq(next))
+        .execute(conn)?;
+    if updated == 0 {
+        return Err(ApiError::Conflict(format!("order {} changed status, try again", id)));
+    }
+    insert_event(id, Some(status), next, note, conn)?;
+
+    // Items that never left the warehouse go back into its stock
+    if matches!(next, OrderStatus::Cancelled | OrderStatus::Refunded) && !status.is_shipped() {
+        let note = format!("order {} {}", id, transition);
+        let lines = order_lines::table
+            .filter(order_lines::order_id.eq(id))
+            .order(order_lines::id.asc())
+            .load::<OrderLine>(conn)?;
+        for line in lines {
+            // Lines whose SKU was deleted since have nothing to go back to
+            if let Some(sku_id) = line.sku_id {
+                let reason = MovementReason::Return;
+                record_movement(sku_id, DEFAULT_LOCATION_ID, reason, line.quantity, Some(&note), conn)?;
+            }
+        }
+    }
+    Ok(next)
+}
+
+/// Every status an order went through, oldest first.
+pub fn list_order_events(id: i32, conn: &SqliteConnection) -> Result<Vec<OrderEvent>> {
+    let order = orders::table
+        .find(id)
+        .first::<Order>(conn)
+        .optional()?
+        .ok_or_else(|| ApiError::NotFound(format!("order {}", id)))?;
+    Ok(OrderEvent::belonging_to(&order)
+        .order(order_events::id.asc())
+        .load::<OrderEvent>(conn)?)
+}
+
+fn insert_event(
+    order_id: i32,
+    from_status: Option<OrderStatus>,
+    to_status: OrderStatus,
+    note: Option<&str>,
+    conn: &SqliteConnection,
+) -> Result<()> {
+    diesel::insert_into(order_events::table)
+        .values((
+            order_events::order_id.eq(order_id),
+            order_events::from_status.eq(from_status),
+            order_events::to_status.eq(to_status),
+            order_events::note.eq(note),
+            order_events::created_at.eq(Utc::now().naive_utc()),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
@@ -0,0 +1,343 @@
+use super::orders::apply_transition;
+use super::{last_insert_rowid, Result};
+use crate::db::models::{FormPayment, FormRefund, Order, Payment, PaymentStatus, Refund, RefundStatus};
+use crate::db::money::Money;
+use crate::db::order_status::{OrderStatus, OrderTransition};
+use crate::db::schema::{orders, payments, refunds};
+use crate::errors::ApiError;
+use crate::payments::PaymentGateway;
+use chrono::Utc;
+use diesel::{
+    dsl::exists, sqlite::SqliteConnection, BelongingToDsl, Connection, ExpressionMethods, OptionalExtension, QueryDsl,
+    RunQueryDsl,
+};
+
+/// Pays the total of a pending order through `gateway`.
+///
+/// An authorized payment is captured right away and marks the order paid. One
+/// that requires action from the customer waits on a webhook, see
+/// [`handle_webhook`]. Declined payments are recorded as well, the order can
+/// be paid again.
+pub fn create_payment(
+    order_id: i32,
+    form: FormPayment,
+    gateway: &dyn PaymentGateway,
+    conn: &SqliteConnection,
+) -> Result<Payment> {
+    let order = find_order(order_id, conn)?;
+    order.status.try_apply(order_id, OrderTransition::Pay)?;
+    let awaiting = payments::table
+        .filter(payments::order_id.eq(order_id))
+        .filter(payments::status.eq_any([PaymentStatus::RequiresAction, PaymentStatus::Capturing]));
+    if diesel::select(exists(awaiting)).get_result::<bool>(conn)? {
+        return Err(ApiError::Conflict(format!("order {} already has a payment under way", order_id)));
+    }
+
+    let authorization = gateway.authorize(order.total, &order.currency, &form.source)?;
+    let now = Utc::now().naive_utc();
+    // Recorded before capturing, the gateway knows about it either way
+    diesel::insert_into(payments::table)
+        .values((
+            payments::order_id.eq(order_id),
+            payments::gateway.eq(gateway.name()),
+            payments::reference.eq(&authorization.reference),
+            payments::status.eq(authorization.status),
+            payments::amount.eq(order.total),
+            payments::currency.eq(&order.currency),
+            payments::created_at.eq(now),
+            payments::updated_at.eq(now),
+        ))
+        .execute(conn)?;
+    let id = diesel::select(last_insert_rowid).first::<i32>(conn)?;
+
+    if authorization.status == PaymentStatus::Authorized {
+        let payment = show_payment(id, conn)?;
+        capture(&payment, gateway, conn)?;
+    }
+    show_payment(id, conn)
+}
+
+/// Applies the outcome of a payment that required action, as reported by a
+/// webhook of `gateway`. An authorized payment is voided rather than captured
+/// when its order can't be paid anymore. Gateways may deliver a webhook more
+/// than once, a payment that already has the reported outcome is left as is.
+pub fn handle_webhook(
+    payload: &[u8],
+    signature: &str,
+    gateway: &dyn PaymentGateway,
+    conn: &SqliteConnection,
+) -> Result<Payment> {
+    let event = gateway.verify_webhook(payload, signature)?;
+    let payment = payments::table
+        .filter(payments::gateway.eq(gateway.name()))
+        .filter(payments::reference.eq(&event.reference))
+        .first::<Payment>(conn)
+        .optional()?
+        .ok_or_else(|| ApiError::NotFound(format!("payment {}", event.reference)))?;
+
+    match (payment.status, event.status) {
+        (PaymentStatus::RequiresAction, _) => {}
+        (PaymentStatus::Capturing | PaymentStatus::Captured | PaymentStatus::Voided, PaymentStatus::Authorized)
+        | (PaymentStatus::Declined, PaymentStatus::Declined) => {
+            return Ok(payment)
+        }
+        (status, _) => {
+            return Err(ApiError::Conflict(format!(
+                "payment {} is {}, it doesn't await action",
+                payment.id,
+                status.as_str()
+            )))
+        }
+    }
+
+    if event.status == PaymentStatus::Authorized {
+        capture(&payment, gateway, conn)?;
+    } else {
+        diesel::update(
+            payments::table
+                .find(payment.id)
+                .filter(payments::status.eq(PaymentStatus::RequiresAction)),
+        )
+        .set((payments::status.eq(event.status), payments::updated_at.eq(Utc::now().naive_utc())))
+        .execute(conn)?;
+    }
+    show_payment(payment.id, conn)
+}
+
+/// Refunds `form.amount` of a captured payment, all that is left of it by
+/// default. Refunding a payment in full refunds its order, which must allow
+/// it, unless the order was already cancelled or refunded.
+///
+/// The refund is recorded as pending before the gateway is asked for it, so
+/// that a retry can't refund the same amount twice. When the gateway fails,
+/// the refund is marked failed and its amount can be refunded again.
+pub fn refund_payment(
+    id: i32,
+    form: FormRefund,
+    gateway: &dyn PaymentGateway,
+    conn: &SqliteConnection,
+) -> Result<Payment> {
+    let refund_id = conn.transaction(|| {
+        let payment = show_payment(id, conn)?;
+        if payment.status != PaymentStatus::Captured {
+            return Err(ApiError::Conflict(format!(
+                "payment {} is {}, only captured payments can be refunded",
+                id,
+                payment.status.as_str()
+            )));
+        }
+        if payment.gateway != gateway.name() {
+            return Err(ApiError::Conflict(format!("payment {} was made through {}", id, payment.gateway)));
+        }
+        let remaining = payment
+            .amount
+            .checked_sub(payment.refunded)
+            .ok_or_else(|| ApiError::Internal(format!("payment {} has an invalid refunded amount", id)))?;
+        let amount = form.amount.unwrap_or(remaining);
+        if amount > remaining {
+            return Err(ApiError::Validation(format!(
+                "payment {} has only {} {} left to refund",
+                id, remaining, payment.currency
+            )));
+        }
+        if amount == remaining {
+            let order = find_order(payment.order_id, conn)?;
+            if !matches!(order.status, OrderStatus::Cancelled | OrderStatus::Refunded) {
+                let note = format!("payment {} refunded", id);
+                apply_transition(order.id, OrderTransition::Refund, Some(&note), conn)?;
+            }
+        }
+        reserve_refund(&payment, amount, conn)
+    })?;
+    settle_refund(refund_id, gateway, conn)?;
+    show_payment(id, conn)
+}
+
+/// Every refund of a payment, oldest first, failed ones included.
+pub fn list_refunds(payment_id: i32, conn: &SqliteConnection) -> Result<Vec<Refund>> {
+    let payment = show_payment(payment_id, conn)?;
+    Ok(Refund::belonging_to(&payment)
+        .order(refunds::id.asc())
+        .load::<Refund>(conn)?)
+}
+
+pub fn show_payment(id: i32, conn: &SqliteConnection) -> Result<Payment> {
+    payments::table
+        .find(id)
+        .first::<Payment>(conn)
+        .optional()?
+        .ok_or_else(|| ApiError::NotFound(format!("payment {}", id)))
+}
+
+/// Every payment of an order, oldest first.
+pub fn list_payments(order_id: i32, conn: &SqliteConnection) -> Result<Vec<Payment>> {
+    let order = find_order(order_id, conn)?;
+    Ok(Payment::belonging_to(&order)
+        .order(payments::id.asc())
+        .load::<Payment>(conn)?)
+}
+
+fn find_order(id: i32, conn: &SqliteConnection) -> Result<Order> {
+    orders::table
+        .find(id)
+        .first::<Order>(conn)
+        .optional()?
+        .ok_or_else(|| ApiError::NotFound(format!("order {}", id)))
+}
+
+/// Captures an authorized payment, or one that required action, and marks
+/// its order paid. When the order can't be paid anymore, e.g. it was
+/// cancelled while the customer went through a challenge, the authorization
+/// is voided instead.
+///
+/// As with refunds, the capture is recorded before the gateway is asked for
+/// it and settled afterwards, so that no transaction is held open meanwhile.
+/// A capturing payment can't be captured again nor replaced. When the gateway
+/// fails, the payment goes back to what it was and can be captured again.
+fn capture(payment: &Payment, gateway: &dyn PaymentGateway, conn: &SqliteConnection) -> Result<()> {
+    let status = conn.transaction(|| {
+        let order = find_order(payment.order_id, conn)?;
+        let status = match order.status.try_apply(order.id, OrderTransition::Pay) {
+            Ok(_) => PaymentStatus::Capturing,
+            Err(ApiError::Conflict(_)) => PaymentStatus::Voided,
+            Err(error) => return Err(error),
+        };
+        set_status(payment, payment.status, status, conn)?;
+        Ok(status)
+    })?;
+    if status == PaymentStatus::Voided {
+        return gateway.void(&payment.reference);
+    }
+
+    if let Err(error) = gateway.capture(&payment.reference, payment.amount, &payment.currency) {
+        set_status(payment, PaymentStatus::Capturing, payment.status, conn)?;
+        return Err(error);
+    }
+    let refund_id = conn.transaction(|| {
+        set_status(payment, PaymentStatus::Capturing, PaymentStatus::Captured, conn)?;
+        let note = format!("payment {} captured", payment.id);
+        match apply_transition(payment.order_id, OrderTransition::Pay, Some(&note), conn) {
+            Ok(_) => Ok(None),
+            // The order was cancelled while the gateway captured, the money
+            // goes back
+            Err(ApiError::Conflict(_)) => {
+                let payment = show_payment(payment.id, conn)?;
+                reserve_refund(&payment, payment.amount, conn).map(Some)
+            }
+            Err(error) => Err(error),
+        }
+    })?;
+    match refund_id {
+        Some(refund_id) => settle_refund(refund_id, gateway, conn),
+        None => Ok(()),
+    }
+}
+
+/// Moves a payment from `from` to `to`, failing with a conflict when it
+/// isn't `from` anymore.
+fn set_status(payment: &Payment, from: PaymentStatus, to: PaymentStatus, conn: &SqliteConnection) -> Result<()> {
+    let updated = diesel::update(payments::table.find(payment.id).filter(payments::status.eq(from)))
+        .set((payments::status.eq(to), payments::updated_at.eq(Utc::now().naive_utc())))
+        .execute(conn)?;
+    if updated == 0 {
+        return Err(ApiError::Conflict(format!("payment {} changed, try again", payment.id)));
+    }
+    Ok(())
+}
+
+/// Records a pending refund of `amount` of a payment, counting it as refunded
+/// until the gateway says otherwise. To be run in a transaction of the caller.
+pub(super) fn reserve_refund(payment: &Payment, amount: Money, conn: &SqliteConnection) -> Result<i32> {
+    let refunded = payment
+        .refunded
+        .checked_add(amount)
+        .ok_or_else(|| ApiError::Validation("the refund is too large".to_string()))?;
+    // Only from the refunded amount that was checked
+    let updated = diesel::update(
+        payments::table
+            .find(payment.id)
+            .filter(payments::refunded.eq(payment.refunded)),
+    )
+    .set((payments::refunded.eq(refunded), payments::updated_at.eq(Utc::now().naive_utc())))
+    .execute(conn)?;
+    if updated == 0 {
+        return Err(ApiError::Conflict(format!("payment {} changed, try again", payment.id)));
+    }
+    let now = Utc::now().naive_utc();
+    diesel::insert_into(refunds::table)
+        .values((
+            refunds::payment_id.eq(payment.id),
+            refunds::amount.eq(amount),
+            refunds::status.eq(RefundStatus::Pending),
+            refunds::created_at.eq(now),
+            refunds::updated_at.eq(now),
+        ))
+        .execute(conn)?;
+    Ok(diesel::select(last_insert_rowid).first::<i32>(conn)?)
+}
+
+/// Records a pending refund of what is left of every captured payment of an
+/// order, made through `gateway`. To be run in a transaction of the caller.
+pub(super) fn reserve_order_refunds(
+    order_id: i32,
+    gateway: &dyn PaymentGateway,
+    conn: &SqliteConnection,
+) -> Result<Vec<i32>> {
+    let captured = payments::table
+        .filter(payments::order_id.eq(order_id))
+        .filter(payments::status.eq(PaymentStatus::Captured))
+        .order(payments::id.asc())
+        .load::<Payment>(conn)?;
+    let mut refunds = Vec::new();
+    for payment in captured {
+        if payment.gateway != gateway.name() {
+            return Err(ApiError::Conflict(format!("payment {} was made through {}", payment.id, payment.gateway)));
+        }
+        match payment.amount.checked_sub(payment.refunded) {
+            Some(remaining) if remaining.is_positive() => refunds.push(reserve_refund(&payment, remaining, conn)?),
+            _ => {}
+        }
+    }
+    Ok(refunds)
+}
+
+/// Asks the gateway for a pending refund. A payment refunded in full once it
+/// succeeds is marked so. When it fails, its amount is taken off what was
+/// refunded of the payment and the error of the gateway returned.
+pub(super) fn settle_refund(id: i32, gateway: &dyn PaymentGateway, conn: &SqliteConnection) -> Result<()> {
+    let (refund, payment) = refunds::table
+        .inner_join(payments::table)
+        .filter(refunds::id.eq(id))
+        .filter(refunds::status.eq(RefundStatus::Pending))
+        .first::<(Refund, Payment)>(conn)
+        .optional()?
+        .ok_or_else(|| ApiError::Conflict(format!("refund {} isn't pending", id)))?;
+
+    let outcome = gateway.refund(&payment.reference, refund.amount, &payment.currency);
+    let now = Utc::now().naive_utc();
+    conn.transaction(|| {
+        let status = if outcome.is_ok() { RefundStatus::Succeeded } else { RefundStatus::Failed };
+        diesel::update(refunds::table.find(id))
+            .set((refunds::status.eq(status), refunds::updated_at.eq(now)))
+            .execute(conn)?;
+        let payment = show_payment(payment.id, conn)?;
+        if outcome.is_ok() {
+            if payment.refunded == payment.amount {
+                diesel::update(payments::table.find(payment.id).filter(payments::status.eq(PaymentStatus::Captured)))
+                    .set((payments::status.eq(PaymentStatus::Refunded), payments::updated_at.eq(now)))
+                    .execute(conn)?;
+            }
+        } else {
+            let refunded = payment.refunded.checked_sub(refund.amount).unwrap_or_default();
+            diesel::update(payments::table.find(payment.id))
+                .set((
+                    payments::refunded.eq(refunded),
+                    payments::status.eq(PaymentStatus::Captured),
+                    payments::updated_at.eq(now),
+                ))
+                .execute(conn)?;
+        }
+        Ok::<_, ApiError>(())
+    })?;
+    outcome
+}
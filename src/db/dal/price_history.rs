@@ -0,0 +1,184 @@
+use super::Result;
+use crate::db::models::ProductPriceChange;
+use crate::db::money::{Money, Rate};
+use crate::db::schema::{product_price_changes, product_sales, products};
+use crate::errors::ApiError;
+use chrono::{Duration, NaiveDateTime};
+use diesel::{sqlite::SqliteConnection, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
+use std::collections::HashMap;
+
+/// Length of the window the lowest recent price is computed over.
+pub const LOWEST_PRICE_DAYS: i64 = 30;
+
+/// Every change of the cost of a product and of its explicit prices in other
+/// currencies, in chronological order. Changes are recorded by triggers, so
+/// this also covers direct database updates.
+pub fn list_price_history(product_id: i32, conn: &SqliteConnection) -> Result<Vec<ProductPriceChange>> {
+    let product = products::table
+        .find(product_id)
+        .select(products::id)
+        .first::<i32>(conn)
+        .optional()?;
+    if product.is_none() {
+        return Err(ApiError::NotFound(format!("product {}", product_id)));
+    }
+
+    Ok(product_price_changes::table
+        .filter(product_price_changes::product_id.eq(product_id))
+        .order((product_price_changes::changed_at.asc(), product_price_changes::id.asc()))
+        .load(conn)?)
+}
+
+/// Lowest price each of `product_ids` had before its current reduction, in
+/// the currency of the product.
+///
+/// For a product on sale at `now` this is the lowest of its costs and sales
+/// over the [`LOWEST_PRICE_DAYS`] days before the sale started, the sale
+/// itself left out. For any other product the window is the days before
+/// `now`. Products that had no price over the window are left out.
+///
+/// Only prices in the current currency of a product are considered.
+pub fn lowest_prior_costs(product_ids: &[i32], now: NaiveDateTime, conn: &SqliteConnection) -> Result<HashMap<i32, Money>> {
+    lowest_prior(product_ids, None, now, conn)
+}
+
+/// Same as [`lowest_prior_costs`] in `currency`, following the explicit
+/// prices the products had in `currency` over time. Sales are scaled by the
+/// ratio of the explicit price to the cost at the time, as
+/// [`price_products`](super::prices::price_products) does. Times without an
+/// explicit price are converted at `rates`, the current rates into `currency`
+/// by base currency, and left out when there is none.
+pub fn lowest_prior_prices(
+    product_ids: &[i32],
+    currency: &str,
+    rates: &HashMap<String, Rate>,
+    now: NaiveDateTime,
+    conn: &SqliteConnection,
+) -> Result<HashMap<i32, Money>> {
+    lowest_prior(product_ids, Some((currency, rates)), now, conn)
+}
+
+/// What the price of a product depends on at a given time.
+#[derive(Default)]
+struct PriceHistory {
+    /// Changes of its cost, oldest first.
+    costs: Vec<(NaiveDateTime, Money)>,
+    /// Changes of its explicit price in the currency asked for, oldest first.
+    explicit_prices: Vec<(NaiveDateTime, Option<Money>)>,
+    /// Its sales, with when they start and end.
+    sales: Vec<(NaiveDateTime, NaiveDateTime, Money)>,
+}
+
+impl PriceHistory {
+    /// The price at `at` once the first `costs` changes of the cost and the
+    /// first `explicit_prices` changes of the explicit price were made. In the
+    /// currency of the product when `target` is `None`, else in the currency
+    /// asked for, `target` holding the rate into it. `None` before the product
+    /// had a cost or when it can't be priced.
+    fn price_at(
+        &self,
+        at: NaiveDateTime,
+        (costs, explicit_prices): (usize, usize),
+        target: Option<Option<Rate>>,
+    ) -> Option<Money> {
+        let cost = self.costs[..costs].last()?.1;
+        let sale = self
+            .sales
+            .iter()
+            .filter(|(starts_at, ends_at, _)| *starts_at <= at && at < *ends_at)
+            .map(|(_, _, cost)| *cost)
+            .min();
+        let rate = match target {
+            Some(rate) => rate,
+            None => return Some(sale.unwrap_or(cost)),
+        };
+        let explicit_price = self.explicit_prices[..explicit_prices].last().and_then(|(_, price)| *price);
+        match (explicit_price, rate) {
+            (Some(price), _) if cost.minor() != 0 => match sale {
+                Some(sale) => sale.checked_scale(price, cost),
+                None => Some(price),
+            },
+            (_, Some(rate)) => sale.unwrap_or(cost).convert(rate),
+            (explicit_price, None) => explicit_price,
+        }
+    }
+}
+
+fn lowest_prior(
+    product_ids: &[i32],
+    target: Option<(&str, &HashMap<String, Rate>)>,
+    now: NaiveDateTime,
+    conn: &SqliteConnection,
+) -> Result<HashMap<i32, Money>> {
+    let currencies = products::table
+        .filter(products::id.eq_any(product_ids))
+        .select((products::id, products::currency))
+        .load::<(i32, String)>(conn)?
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+
+    let mut histories: HashMap<i32, PriceHistory> = HashMap::new();
+    let changes = product_price_changes::table
+        .filter(product_price_changes::product_id.eq_any(product_ids))
+        .filter(product_price_changes::changed_at.le(now))
+        .order((product_price_changes::changed_at.asc(), product_price_changes::id.asc()))
+        .load::<ProductPriceChange>(conn)?;
+    for change in changes {
+        let history = histories.entry(change.product_id).or_default();
+        if currencies.get(&change.product_id) == Some(&change.currency) {
+            history.costs.extend(change.cost.map(|cost| (change.changed_at, cost)));
+        } else if target.map(|(currency, _)| currency) == Some(change.currency.as_str()) {
+            history.explicit_prices.push((change.changed_at, change.cost));
+        }
+    }
+    let sales = product_sales::table
+        .filter(product_sales::product_id.eq_any(product_ids))
+        .filter(product_sales::starts_at.le(now))
+        .select((product_sales::product_id, product_sales::starts_at, product_sales::ends_at, product_sales::cost))
+        .load::<(i32, NaiveDateTime, NaiveDateTime, Money)>(conn)?;
+    for (product_id, starts_at, ends_at, cost) in sales {
+        histories.entry(product_id).or_default().sales.push((starts_at, ends_at, cost));
+    }
+
+    let mut lowest = HashMap::new();
+    for (product_id, history) in histories {
+        let target = target.map(|(_, rates)| currencies.get(&product_id).and_then(|currency| rates.get(currency)).copied());
+        // The reduction being disclosed is the sale making the current price,
+        // the cheapest one running
+        let until = history
+            .sales
+            .iter()
+            .filter(|(starts_at, ends_at, _)| *starts_at <= now && now < *ends_at)
+            .min_by_key(|(starts_at, _, cost)| (*cost, *starts_at))
+            .map_or(now, |(starts_at, _, _)| *starts_at);
+        let since = until - Duration::days(LOWEST_PRICE_DAYS);
+        // The price only changes with each change and when sales start or end,
+        // changes made at the same time each counting
+        let made_by = |at: NaiveDateTime| {
+            (
+                history.costs.iter().take_while(|(changed_at, _)| *changed_at <= at).count(),
+                history.explicit_prices.iter().take_while(|(changed_at, _)| *changed_at <= at).count(),
+            )
+        };
+        let cost_changes = history.costs.iter().enumerate().map(|(i, (at, _))| (*at, (i + 1, made_by(*at).1)));
+        let explicit_price_changes = history
+            .explicit_prices
+            .iter()
+            .enumerate()
+            .map(|(i, (at, _))| (*at, (made_by(*at).0, i + 1)));
+        let sale_bounds = history
+            .sales
+            .iter()
+            .flat_map(|(starts_at, ends_at, _)| [*starts_at, *ends_at])
+            .map(|at| (at, made_by(at)));
+        let price = cost_changes
+            .chain(explicit_price_changes)
+            .chain(sale_bounds)
+            .filter(|(at, _)| since < *at && *at < until)
+            .chain(std::iter::once((since, made_by(since))))
+            .filter_map(|(at, made)| history.price_at(at, made, target))
+            .min();
+        lowest.extend(price.map(|price| (product_id, price)));
+    }
+    Ok(lowest)
+}
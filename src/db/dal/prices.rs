@@ -0,0 +1,226 @@
+use super::price_history::lowest_prior_prices;
+use super::Result;
+use crate::db::models::{ExchangeRate, FormExchangeRate, FormProductPrice, ProductPrice};
+use crate::db::money::Money;
+use crate::db::responses::ProductResponse;
+use crate::db::schema::{exchange_rates, product_prices, products};
+use crate::errors::ApiError;
+use crate::validation::currency_code;
+use chrono::Utc;
+use diesel::{sqlite::SqliteConnection, Connection, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
+use std::collections::HashMap;
+
+/// Normalizes a currency code given by a client, e.g. `gbp` into `GBP`.
+pub fn parse_currency(code: &str) -> Result<String> {
+    let code = code.to_ascii_uppercase();
+    currency_code(&code).map_err(|_| ApiError::Validation(format!("'{}' is not a currency code", code)))?;
+    Ok(code)
+}
+
+/// Rewrites the prices of each of `products` in `currency`.
+///
+/// A product keeps its own prices when they are already in `currency`.
+/// Otherwise its cost becomes its explicit price in `currency` or, lacking
+/// one, its cost converted at the stored exchange rate, rounded as
+/// [`Money::convert`] does. Other prices, e.g. sale or compare-at prices,
+/// follow the cost: they are scaled by the ratio of the explicit price to the
+/// cost, or converted at the same rate. The lowest recent price is computed
+/// again in `currency`, following the explicit prices of the time.
+///
+/// Fails when a product has neither an explicit price nor a rate. A product
+/// with an explicit price whose other prices can't be scaled nor converted,
+/// as its cost is zero and there is no rate, is only offered at that price.
+pub fn price_products<'a, I>(products: I, currency: &str, conn: &SqliteConnection) -> Result<()>
+where
+    I: IntoIterator<Item = &'a mut ProductResponse>,
+{
+    let products = products
+        .into_iter()
+        .filter(|product| product.currency != currency)
+        .collect::<Vec<_>>();
+    if products.is_empty() {
+        return Ok(());
+    }
+
+    let product_ids = products.iter().map(|product| product.id).collect::<Vec<_>>();
+    let prices = product_prices::table
+        .filter(product_prices::product_id.eq_any(&product_ids))
+        .filter(product_prices::currency.eq(currency))
+        .load::<ProductPrice>(conn)?
+        .into_iter()
+        .map(|price| (price.product_id, price.amount))
+        .collect::<HashMap<_, _>>();
+    let rates = exchange_rates::table
+        .filter(exchange_rates::quote_currency.eq(currency))
+        .load::<ExchangeRate>(conn)?
+        .into_iter()
+        .map(|rate| (rate.base_currency, rate.rate))
+        .collect::<HashMap<_, _>>();
+    let lowest_prices = lowest_prior_prices(&product_ids, currency, &rates, Utc::now().naive_utc(), conn)?;
+
+    for product in products {
+        let cost = product.cost;
+        let explicit_price = prices.get(&product.id).copied();
+        let rate = rates.get(&product.currency).copied();
+        let overflow = || ApiError::Internal(format!("price of product {} overflows", product.id));
+        let convert = |amount: Money| match (explicit_price, rate) {
+            (Some(price), _) if cost.minor() != 0 => amount.checked_scale(price, cost).ok_or_else(overflow).map(Some),
+            (_, Some(rate)) => amount.convert(rate).ok_or_else(overflow).map(Some),
+            (Some(_), None) => Ok(None),
+            (None, None) => Err(ApiError::Validation(format!(
+                "product {} has no price in {} and there is no exchange rate from {}",
+                product.id, currency, product.currency
+            ))),
+        };
+        let new_cost = match explicit_price {
+            Some(price) => price,
+            None => convert(cost)?.ok_or_else(overflow)?,
+        };
+        let effective_cost = convert(product.effective_cost)?.unwrap_or(new_cost);
+        let lowest_30_day_cost = lowest_prices.get(&product.id).copied().unwrap_or(effective_cost);
+        let compare_at_cost = product.compare_at_cost.map(convert).transpose()?.flatten();
+        product.cost = new_cost;
+        product.effective_cost = effective_cost;
+        product.lowest_30_day_cost = lowest_30_day_cost;
+        product.compare_at_cost = compare_at_cost;
+        product.currency = currency.to_string();
+    }
+    Ok(())
+}
+
+/// Converts `amount` from one currency into another at the stored exchange
+/// rate, rounded as [`Money::convert`] does.
+pub fn convert_amount(amount: Money, from: &str, to: &str, conn: &SqliteConnection) -> Result<Money> {
+    if from == to {
+        return Ok(amount);
+    }
+    let rate = exchange_rates::table
+        .filter(exchange_rates::base_currency.eq(from))
+        .filter(exchange_rates::quote_currency.eq(to))
+        .first::<ExchangeRate>(conn)
+        .optional()?
+        .ok_or_else(|| ApiError::Validation(format!("there is no exchange rate from {} to {}", from, to)))?;
+    amount
+        .convert(rate.rate)
+        .ok_or_else(|| ApiError::Internal(format!("{} {} overflows in {}", amount, from, to)))
+}
+
+pub fn list_product_prices(product_id: i32, conn: &SqliteConnection) -> Result<Vec<ProductPrice>> {
+    product_currency(product_id, conn)?;
+    Ok(product_prices::table
+        .filter(product_prices::product_id.eq(product_id))
+        .order(product_prices::currency.asc())
+        .load::<ProductPrice>(conn)?)
+}
+
+/// Creates or replaces the price of a product in `currency`.
+pub fn set_product_price(
+    product_id: i32,
+    currency: &str,
+    form: FormProductPrice,
+    conn: &SqliteConnection,
+) -> Result<ProductPrice> {
+    let currency = parse_currency(currency)?;
+    conn.transaction(|| {
+        if product_currency(product_id, conn)? == currency {
+            return Err(ApiError::Validation(format!(
+                "the price of product {} in {} is its cost",
+                product_id, currency
+            )));
+        }
+
+        let price = product_prices::table
+            .filter(product_prices::product_id.eq(product_id))
+            .filter(product_prices::currency.eq(&currency));
+        let updated = diesel::update(price)
+            .set(product_prices::amount.eq(form.amount))
+            .execute(conn)?;
+        if updated == 0 {
+            diesel::insert_into(product_prices::table)
+                .values((
+                    product_prices::product_id.eq(product_id),
+                    product_prices::currency.eq(&currency),
+                    product_prices::amount.eq(form.amount),
+                ))
+                .execute(conn)?;
+        }
+
+        Ok(price.first::<ProductPrice>(conn)?)
+    })
+}
+
+pub fn delete_product_price(product_id: i32, currency: &str, conn: &SqliteConnection) -> Result<i32> {
+    let currency = parse_currency(currency)?;
+    let deleted = diesel::delete(
+        product_prices::table
+            .filter(product_prices::product_id.eq(product_id))
+            .filter(product_prices::currency.eq(&currency)),
+    )
+    .execute(conn)?;
+    if deleted == 0 {
+        return Err(ApiError::NotFound(format!("price of product {} in {}", product_id, currency)));
+    }
+
+    Ok(product_id)
+}
+
+pub fn list_exchange_rates(conn: &SqliteConnection) -> Result<Vec<ExchangeRate>> {
+    Ok(exchange_rates::table
+        .order((exchange_rates::base_currency.asc(), exchange_rates::quote_currency.asc()))
+        .load::<ExchangeRate>(conn)?)
+}
+
+/// Creates or replaces the rate from `base` to `quote`. The reverse rate is
+/// a separate entry, it is never derived from this one.
+pub fn set_exchange_rate(base: &str, quote: &str, form: FormExchangeRate, conn: &SqliteConnection) -> Result<ExchangeRate> {
+    let base = parse_currency(base)?;
+    let quote = parse_currency(quote)?;
+    if base == quote {
+        return Err(ApiError::Validation("an exchange rate needs two distinct currencies".to_string()));
+    }
+
+    conn.transaction(|| {
+        let rate = exchange_rates::table
+            .filter(exchange_rates::base_currency.eq(&base))
+            .filter(exchange_rates::quote_currency.eq(&quote));
+        let updated = diesel::update(rate)
+            .set(exchange_rates::rate.eq(form.rate))
+            .execute(conn)?;
+        if updated == 0 {
+            diesel::insert_into(exchange_rates::table)
+                .values((
+                    exchange_rates::base_currency.eq(&base),
+                    exchange_rates::quote_currency.eq(&quote),
+                    exchange_rates::rate.eq(form.rate),
+                ))
+                .execute(conn)?;
+        }
+
+        Ok(rate.first::<ExchangeRate>(conn)?)
+    })
+}
+
+pub fn delete_exchange_rate(base: &str, quote: &str, conn: &SqliteConnection) -> Result<()> {
+    let base = parse_currency(base)?;
+    let quote = parse_currency(quote)?;
+    let deleted = diesel::delete(
+        exchange_rates::table
+            .filter(exchange_rates::base_currency.eq(&base))
+            .filter(exchange_rates::quote_currency.eq(&quote)),
+    )
+    .execute(conn)?;
+    if deleted == 0 {
+        return Err(ApiError::NotFound(format!("exchange rate from {} to {}", base, quote)));
+    }
+
+    Ok(())
+}
+
+fn product_currency(product_id: i32, conn: &SqliteConnection) -> Result<String> {
+    products::table
+        .find(product_id)
+        .select(products::currency)
+        .first::<String>(conn)
+        .optional()?
+        .ok_or_else(|| ApiError::NotFound(format!("product {}", product_id)))
+}
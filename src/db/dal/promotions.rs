@@ -0,0 +1,408 @@
+use super::prices::convert_amount;
+use super::{last_insert_rowid, Result};
+use crate::db::models::{FormPromoCode, PromoCode, PromoCodeVariantRule};
+use crate::db::money::Money;
+use crate::db::order_status::OrderStatus;
+use crate::db::responses::{CartLineResponse, PromoCodeResponse};
+use crate::db::schema::{orders, promo_code_products, promo_code_variant_rules, promo_codes};
+use crate::errors::ApiError;
+use chrono::{NaiveDateTime, Utc};
+use diesel::{
+    sqlite::SqliteConnection, BelongingToDsl, Connection, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl,
+};
+use std::fmt;
+
+/// Why a promo code doesn't apply to a cart, as told to the customer.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PromoRejection {
+    Unknown(String),
+    Inactive(String),
+    NotStarted(String, NaiveDateTime),
+    Expired(String, NaiveDateTime),
+    /// The cart is below the minimum total, in the currency of the cart.
+    BelowMinimum(String, Money, String),
+    UsedUp(String),
+    /// The customer used the code as many times as they may.
+    UsedUpByCustomer(String),
+    /// The code is limited per customer, who must then be known.
+    CustomerRequired(String),
+    NoMatchingLines(String),
+}
+
+impl fmt::Display for PromoRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PromoRejection::Unknown(code) => write!(f, "promo code {} doesn't exist", code),
+            PromoRejection::Inactive(code) => write!(f, "promo code {} is no longer valid", code),
+            PromoRejection::NotStarted(code, at) => write!(f, "promo code {} is only valid from {}", code, at),
+            PromoRejection::Expired(code, at) => write!(f, "promo code {} expired on {}", code, at),
+            PromoRejection::BelowMinimum(code, total, currency) => {
+                write!(f, "promo code {} needs a cart of at least {} {}", code, total, currency)
+            }
+            PromoRejection::UsedUp(code) => write!(f, "promo code {} has been used up", code),
+            PromoRejection::UsedUpByCustomer(code) => {
+                write!(f, "promo code {} was already used by this customer", code)
+            }
+            PromoRejection::CustomerRequired(code) => {
+                write!(f, "promo code {} is limited per customer, an email is needed to use it", code)
+            }
+            PromoRejection::NoMatchingLines(code) => {
+                write!(f, "promo code {} doesn't apply to anything in the cart", code)
+            }
+        }
+    }
+}
+
+impl From<PromoRejection> for ApiError {
+    fn from(rejection: PromoRejection) -> Self {
+        ApiError::Validation(rejection.to_string())
+    }
+}
+
+/// A promo code that applies to a cart, with the discount of each of its
+/// lines.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppliedPromo {
+    pub promo: PromoCode,
+    /// Aligned with the lines of the cart, zero for those it doesn't apply to.
+    pub discounts: Vec<Money>,
+    pub discount: Money,
+}
+
+pub fn create_promo_code(form: FormPromoCode, conn: &SqliteConnection) -> Result<PromoCodeResponse> {
+    conn.transaction(|| {
+        diesel::insert_into(promo_codes::table)
+            .values(PromoCodeRow::new(&form))
+            .execute(conn)?;
+        let id = diesel::select(last_insert_rowid).first::<i32>(conn)?;
+        insert_scope(id, &form, conn)?;
+        show_promo_code(id, conn)
+    })
+}
+
+pub fn list_promo_codes(conn: &SqliteConnection) -> Result<Vec<PromoCodeResponse>> {
+    promo_codes::table
+        .order(promo_codes::id.asc())
+        .load::<PromoCode>(conn)?
+        .into_iter()
+        .map(|promo| promo_code_response(promo, conn))
+        .collect()
+}
+
+pub fn show_promo_code(id: i32, conn: &SqliteConnection) -> Result<PromoCodeResponse> {
+    let promo = promo_codes::table
+        .find(id)
+        .first::<PromoCode>(conn)
+        .optional()?
+        .ok_or_else(|| ApiError::NotFound(format!("promo code {}", id)))?;
+    promo_code_response(promo, conn)
+}
+
+/// Replaces a promo code, along with the products and variant values it
+/// applies to. Orders placed with it keep their discount.
+pub fn update_promo_code(id: i32, form: FormPromoCode, conn: &SqliteConnection) -> Result<PromoCodeResponse> {
+    conn.transaction(|| {
+        let updated = diesel::update(promo_codes::table.find(id))
+            .set(PromoCodeRow::new(&form))
+            .execute(conn)?;
+        if updated == 0 {
+            return Err(ApiError::NotFound(format!("promo code {}", id)));
+        }
+        delete_scope(id, conn)?;
+        insert_scope(id, &form, conn)?;
+        show_promo_code(id, conn)
+    })
+}
+
+/// Deletes a promo code. Orders placed with it keep a copy of the code.
+pub fn delete_promo_code(id: i32, conn: &SqliteConnection) -> Result<i32> {
+    let deleted = diesel::delete(promo_codes::table.find(id)).execute(conn)?;
+    if deleted == 0 {
+        return Err(ApiError::NotFound(format!("promo code {}", id)));
+    }
+    Ok(id)
+}
+
+/// The promo code `code`, whatever its case.
+pub fn find_promo_code(code: &str, conn: &SqliteConnection) -> Result<Option<PromoCode>> {
+    Ok(promo_codes::table
+        .filter(promo_codes::code.eq(normalize_code(code)))
+        .first::<PromoCode>(conn)
+        .optional()?)
+}
+
+/// Works out the discount `code` gives on the available `lines` of a cart
+/// priced in `currency`, or why it doesn't apply.
+///
+/// Percentages are taken off each matching line, rounded to the nearest minor
+/// unit. Fixed amounts are spread over the matching lines in proportion to
+/// their totals, and never take more than those totals. Limits per customer
+/// are left to [`check_customer`].
+pub fn evaluate_promo_code(
+    code: &str,
+    lines: &[CartLineResponse],
+    currency: &str,
+    conn: &SqliteConnection,
+) -> Result<std::result::Result<AppliedPromo, PromoRejection>> {
+    let promo = match find_promo_code(code, conn)? {
+        Some(promo) => promo,
+        None => return Ok(Err(PromoRejection::Unknown(normalize_code(code)))),
+    };
+    let now = Utc::now().naive_utc();
+    let code = promo.code.clone();
+    if !promo.active {
+        return Ok(Err(PromoRejection::Inactive(code)));
+    }
+    if let Some(starts_at) = promo.starts_at.filter(|starts_at| *starts_at > now) {
+        return Ok(Err(PromoRejection::NotStarted(code, starts_at)));
+    }
+    if let Some(ends_at) = promo.ends_at.filter(|ends_at| *ends_at <= now) {
+        return Ok(Err(PromoRejection::Expired(code, ends_at)));
+    }
+
+    let too_large = || ApiError::Validation("the total of the cart is too large".to_string());
+    let subtotal = lines
+        .iter()
+        .filter(|line| line.available)
+        .try_fold(Money::default(), |total, line| total.checked_add(line.total))
+        .ok_or_else(too_large)?;
+    if let Some(min_cart_total) = promo.min_cart_total {
+        let min_cart_total = convert_amount(min_cart_total, &promo.currency, currency, conn)?;
+        if subtotal < min_cart_total {
+            return Ok(Err(PromoRejection::BelowMinimum(code, min_cart_total, currency.to_string())));
+        }
+    }
+    if let Some(usage_limit) = promo.usage_limit {
+        if uses(promo.id, None, conn)? >= i64::from(usage_limit) {
+            return Ok(Err(PromoRejection::UsedUp(code)));
+        }
+    }
+
+    let product_ids = promo_code_products::table
+        .filter(promo_code_products::promo_code_id.eq(promo.id))
+        .select(promo_code_products::product_id)
+        .load::<i32>(conn)?;
+    let rules = PromoCodeVariantRule::belonging_to(&promo).load::<PromoCodeVariantRule>(conn)?;
+    let matching = lines
+        .iter()
+        .map(|line| {
+            line.available
+                && (product_ids.is_empty() || product_ids.contains(&line.product_id))
+                && rules.iter().all(|rule| {
+                    // Rules of the same variant are alternatives
+                    rules
+                        .iter()
+                        .filter(|other| other.variant_name == rule.variant_name)
+                        .any(|other| matches_rule(other, line))
+                })
+        })
+        .collect::<Vec<_>>();
+    let eligible = lines
+        .iter()
+        .zip(&matching)
+        .filter(|(_, matching)| **matching)
+        .try_fold(Money::default(), |total, (line, _)| total.checked_add(line.total))
+        .ok_or_else(too_large)?;
+    if !matching.contains(&true) {
+        return Ok(Err(PromoRejection::NoMatchingLines(code)));
+    }
+
+    let mut discounts = match (promo.percent_off, promo.amount_off) {
+        (Some(percent_off), _) => lines
+            .iter()
+            .zip(&matching)
+            .map(|(line, matching)| match matching {
+                true => share(line.total, i128::from(percent_off), 100),
+                false => Money::default(),
+            })
+            .collect::<Vec<_>>(),
+        (None, Some(amount_off)) => {
+            let amount_off = convert_amount(amount_off, &promo.currency, currency, conn)?.min(eligible);
+            let mut discounts = lines
+                .iter()
+                .zip(&matching)
+                .map(|(line, matching)| match matching {
+                    true if eligible.is_positive() => {
+                        share_down(line.total, i128::from(amount_off.minor()), i128::from(eligible.minor()))
+                    }
+                    _ => Money::default(),
+                })
+                .collect::<Vec<_>>();
+            // Hand out what rounding down left, a minor unit at a time
+            let mut left = amount_off.minor() - discounts.iter().map(|discount| discount.minor()).sum::<i64>();
+            for ((line, matching), discount) in lines.iter().zip(&matching).zip(discounts.iter_mut()) {
+                let room = line.total.minor() - discount.minor();
+                if *matching && left > 0 && room > 0 {
+                    let extra = left.min(room);
+                    *discount = Money::from_minor(discount.minor() + extra);
+                    left -= extra;
+                }
+            }
+            discounts
+        }
+        (None, None) => vec![Money::default(); lines.len()],
+    };
+    for (discount, line) in discounts.iter_mut().zip(lines) {
+        *discount = (*discount).min(line.total);
+    }
+    let discount = Money::from_minor(discounts.iter().map(|discount| discount.minor()).sum());
+    Ok(Ok(AppliedPromo { promo, discounts, discount }))
+}
+
+/// Checks the limit per customer of `promo`, `customer_email` being the
+/// customer placing the order if known.
+///
+/// Customers are only told apart by the email they give at checkout, compared
+/// once normalized by [`normalize_email`]. Until they have accounts the limit
+/// is advisory: a customer giving another address gets around it.
+pub fn check_customer(
+    promo: &PromoCode,
+    customer_email: Option<&str>,
+    conn: &SqliteConnection,
+) -> Result<Option<PromoRejection>> {
+    let per_customer_limit = match promo.per_customer_limit {
+        Some(per_customer_limit) => per_customer_limit,
+        None => return Ok(None),
+    };
+    let customer_email = match customer_email {
+        Some(customer_email) => normalize_email(customer_email),
+        None => return Ok(Some(PromoRejection::CustomerRequired(promo.code.clone()))),
+    };
+    if uses(promo.id, Some(&customer_email), conn)? >= i64::from(per_customer_limit) {
+        return Ok(Some(PromoRejection::UsedUpByCustomer(promo.code.clone())));
+    }
+    Ok(None)
+}
+
+/// Codes are stored uppercase, without surrounding spaces.
+fn normalize_code(code: &str) -> String {
+    code.trim().to_uppercase()
+}
+
+/// Emails of customers are stored and compared lowercase, without
+/// surrounding spaces, so that `Ann@example.com ` is `ann@example.com`.
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// `amount * numerator / denominator`, rounded to the nearest minor unit with
+/// halves rounded up.
+fn share(amount: Money, numerator: i128, denominator: i128) -> Money {
+    let exact = i128::from(amount.minor()) * numerator;
+    Money::from_minor(i64::try_from((exact + denominator / 2) / denominator).unwrap_or(i64::MAX))
+}
+
+/// `amount * numerator / denominator`, rounded down.
+fn share_down(amount: Money, numerator: i128, denominator: i128) -> Money {
+    let exact = i128::from(amount.minor()) * numerator;
+    Money::from_minor(i64::try_from(exact / denominator).unwrap_or(i64::MAX))
+}
+
+fn matches_rule(rule: &PromoCodeVariantRule, line: &CartLineResponse) -> bool {
+    line.options
+        .iter()
+        .filter(|option| option.name == rule.variant_name)
+        .filter_map(|option| option.value.as_deref())
+        .any(|value| match &rule.value {
+            Some(expected) => value.trim().eq_ignore_ascii_case(expected.trim()),
+            None => match value.trim().replace(',', ".").parse::<f64>() {
+                Ok(number) => {
+                    rule.min_value.is_none_or(|min| number >= min) && rule.max_value.is_none_or(|max| number <= max)
+                }
+                Err(_) => false,
+            },
+        })
+}
+
+/// Orders placed with a promo code that weren't cancelled, only those of
+/// `customer_email` if given.
+fn uses(promo_code_id: i32, customer_email: Option<&str>, conn: &SqliteConnection) -> Result<i64> {
+    let mut query = orders::table
+        .filter(orders::promo_code_id.eq(promo_code_id))
+        .filter(orders::status.ne(OrderStatus::Cancelled))
+        .into_boxed();
+    if let Some(customer_email) = customer_email {
+        query = query.filter(orders::customer_email.eq(customer_email));
+    }
+    Ok(query.count().get_result::<i64>(conn)?)
+}
+
+fn promo_code_response(promo: PromoCode, conn: &SqliteConnection) -> Result<PromoCodeResponse> {
+    let product_ids = promo_code_products::table
+        .filter(promo_code_products::promo_code_id.eq(promo.id))
+        .order(promo_code_products::product_id.asc())
+        .select(promo_code_products::product_id)
+        .load::<i32>(conn)?;
+    let rules = PromoCodeVariantRule::belonging_to(&promo)
+        .order(promo_code_variant_rules::id.asc())
+        .load::<PromoCodeVariantRule>(conn)?;
+    let uses = uses(promo.id, None, conn)?;
+    Ok(PromoCodeResponse::new(promo, product_ids, rules, uses))
+}
+
+/// The columns of a promo code, from its form.
+#[derive(Insertable, AsChangeset)]
+#[table_name = "promo_codes"]
+#[changeset_options(treat_none_as_null = "true")]
+struct PromoCodeRow<'a> {
+    code: String,
+    percent_off: Option<i32>,
+    amount_off: Option<Money>,
+    currency: &'a str,
+    min_cart_total: Option<Money>,
+    usage_limit: Option<i32>,
+    per_customer_limit: Option<i32>,
+    starts_at: Option<NaiveDateTime>,
+    ends_at: Option<NaiveDateTime>,
+    active: bool,
+}
+
+impl<'a> PromoCodeRow<'a> {
+    fn new(form: &'a FormPromoCode) -> Self {
+        PromoCodeRow {
+            code: normalize_code(&form.code),
+            percent_off: form.percent_off,
+            amount_off: form.amount_off,
+            currency: &form.currency,
+            min_cart_total: form.min_cart_total,
+            usage_limit: form.usage_limit,
+            per_customer_limit: form.per_customer_limit,
+            starts_at: form.starts_at,
+            ends_at: form.ends_at,
+            active: form.active,
+        }
+    }
+}
+
+fn insert_scope(id: i32, form: &FormPromoCode, conn: &SqliteConnection) -> Result<()> {
+    let mut product_ids = form.product_ids.clone();
+    product_ids.sort_unstable();
+    product_ids.dedup();
+    for product_id in product_ids {
+        diesel::insert_into(promo_code_products::table)
+            .values((
+                promo_code_products::promo_code_id.eq(id),
+                promo_code_products::product_id.eq(product_id),
+            ))
+            .execute(conn)?;
+    }
+    for rule in &form.variant_rules {
+        diesel::insert_into(promo_code_variant_rules::table)
+            .values((
+                promo_code_variant_rules::promo_code_id.eq(id),
+                promo_code_variant_rules::variant_name.eq(rule.variant.trim()),
+                promo_code_variant_rules::value.eq(&rule.value),
+                promo_code_variant_rules::min_value.eq(rule.min),
+                promo_code_variant_rules::max_value.eq(rule.max),
+            ))
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+fn delete_scope(id: i32, conn: &SqliteConnection) -> Result<()> {
+    diesel::delete(promo_code_products::table.filter(promo_code_products::promo_code_id.eq(id))).execute(conn)?;
+    diesel::delete(promo_code_variant_rules::table.filter(promo_code_variant_rules::promo_code_id.eq(id)))
+        .execute(conn)?;
+    Ok(())
+}
@@ -0,0 +1,278 @@
+use super::inventory::record_movement;
+use super::payments::refund_payment;
+use super::{last_insert_rowid, Result};
+use crate::db::models::{
+    FormRefund, FormReturn, FormReturnReceipt, MovementReason, Order, OrderLine, Payment, PaymentStatus, ReturnLine,
+    ReturnReason, ReturnRequest, ReturnStatus,
+};
+use crate::db::money::Money;
+use crate::db::responses::ReturnResponse;
+use crate::db::schema::{order_lines, orders, payments, return_lines, return_requests};
+use crate::errors::ApiError;
+use crate::payments::PaymentGateway;
+use chrono::Utc;
+use diesel::expression::functions::aggregate_folding::sum;
+use diesel::{
+    sqlite::SqliteConnection, BelongingToDsl, Connection, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl,
+};
+use std::collections::BTreeMap;
+
+/// Every status of a return request but rejected.
+const PENDING_OR_REFUNDED: &[ReturnStatus] = &[
+    ReturnStatus::Requested,
+    ReturnStatus::Approved,
+    ReturnStatus::Received,
+    ReturnStatus::Refunded,
+];
+
+/// Requests the return of items of a shipped or delivered order, to be
+/// refunded at the price they were ordered at, less their share of the
+/// discount. Items can only be returned once, unless their return was
+/// rejected.
+///
+/// The refund is worked out as [`line_refund`] does, counting the items of
+/// earlier requests that weren't rejected. It is settled when refunded.
+pub fn create_return(order_id: i32, form: FormReturn, conn: &SqliteConnection) -> Result<ReturnResponse> {
+    conn.transaction(|| {
+        let order = find_order(order_id, conn)?;
+        if !order.status.is_shipped() {
+            return Err(ApiError::Conflict(format!(
+                "order {} is {}, only shipped or delivered orders can be returned",
+                order_id, order.status
+            )));
+        }
+        let order_lines = OrderLine::belonging_to(&order).load::<OrderLine>(conn)?;
+
+        let mut quantities = BTreeMap::<i32, i64>::new();
+        for line in &form.lines {
+            if !order_lines.iter().any(|order_line| order_line.id == line.order_line_id) {
+                return Err(ApiError::Validation(format!(
+                    "order line {} isn't part of order {}",
+                    line.order_line_id, order_id
+                )));
+            }
+            *quantities.entry(line.order_line_id).or_default() += i64::from(line.quantity);
+        }
+        let mut refund = Money::default();
+        for (order_line_id, quantity) in quantities {
+            let order_line = order_lines
+                .iter()
+                .find(|order_line| order_line.id == order_line_id)
+                .ok_or_else(|| ApiError::Internal(format!("order line {} vanished", order_line_id)))?;
+            let returned = returned_quantity(order_line_id, PENDING_OR_REFUNDED, conn)?;
+            let left = i64::from(order_line.quantity) - returned;
+            if quantity > left {
+                return Err(ApiError::Validation(format!(
+                    "only {} of order line {} can be returned",
+                    left, order_line_id
+                )));
+            }
+            refund = line_refund(order_line, returned, quantity)
+                .and_then(|line_refund| refund.checked_add(line_refund))
+                .ok_or_else(|| ApiError::Validation("the refund is too large".to_string()))?;
+        }
+
+        let now = Utc::now().naive_utc();
+        diesel::insert_into(return_requests::table)
+            .values((
+                return_requests::order_id.eq(order_id),
+                return_requests::refund.eq(refund),
+                return_requests::note.eq(&form.note),
+                return_requests::created_at.eq(now),
+                return_requests::updated_at.eq(now),
+            ))
+            .execute(conn)?;
+        let id = diesel::select(last_insert_rowid).first::<i32>(conn)?;
+        for line in &form.lines {
+            diesel::insert_into(return_lines::table)
+                .values((
+                    return_lines::return_request_id.eq(id),
+                    return_lines::order_line_id.eq(line.order_line_id),
+                    return_lines::quantity.eq(line.quantity),
+                    return_lines::reason.eq(line.reason),
+                ))
+                .execute(conn)?;
+        }
+        show_return(id, conn)
+    })
+}
+
+pub fn approve_return(id: i32, conn: &SqliteConnection) -> Result<ReturnResponse> {
+    move_return(id, ReturnStatus::Requested, ReturnStatus::Approved, conn)?;
+    show_return(id, conn)
+}
+
+/// Rejects a return request, its items can be asked to be returned again.
+pub fn reject_return(id: i32, conn: &SqliteConnection) -> Result<ReturnResponse> {
+    move_return(id, ReturnStatus::Requested, ReturnStatus::Rejected, conn)?;
+    show_return(id, conn)
+}
+
+/// Records that the items of an approved return came back, putting them into
+/// the stock of `form.location_id`. Defective items aren't put back.
+pub fn receive_return(id: i32, form: FormReturnReceipt, conn: &SqliteConnection) -> Result<ReturnResponse> {
+    conn.transaction(|| {
+        move_return(id, ReturnStatus::Approved, ReturnStatus::Received, conn)?;
+        let note = format!("return {}", id);
+        for (line, order_line) in load_lines(id, conn)? {
+            // Lines whose SKU was deleted since have nothing to go back to
+            match (line.reason, order_line.sku_id) {
+                (ReturnReason::Defective, _) | (_, None) => {}
+                (_, Some(sku_id)) => {
+                    let reason = MovementReason::Return;
+                    record_movement(sku_id, form.location_id, reason, line.quantity, Some(&note), conn)?;
+                }
+            }
+        }
+        show_return(id, conn)
+    })
+}
+
+/// Pays the refund of a received return back through the latest captured
+/// payment of its order. Refunding what is left of the payment refunds the
+/// order.
+///
+/// The refund is worked out again from what was refunded so far of each
+/// line, see [`create_return`], and the return marked refunded before the
+/// gateway is asked for it. A refund the gateway fails leaves the return
+/// received, to be refunded again.
+pub fn refund_return(id: i32, gateway: &dyn PaymentGateway, conn: &SqliteConnection) -> Result<ReturnResponse> {
+    let (request, payment) = conn.transaction(|| {
+        let mut quantities = BTreeMap::<i32, (OrderLine, i64)>::new();
+        for (line, order_line) in load_lines(id, conn)? {
+            quantities.entry(order_line.id).or_insert((order_line, 0)).1 += i64::from(line.quantity);
+        }
+        let mut refund = Money::default();
+        for (order_line, quantity) in quantities.values() {
+            let refunded = returned_quantity(order_line.id, &[ReturnStatus::Refunded], conn)?;
+            refund = line_refund(order_line, refunded, *quantity)
+                .and_then(|line_refund| refund.checked_add(line_refund))
+                .ok_or_else(|| ApiError::Validation("the refund is too large".to_string()))?;
+        }
+        let request = move_return(id, ReturnStatus::Received, ReturnStatus::Refunded, conn)?;
+        diesel::update(return_requests::table.find(id))
+            .set(return_requests::refund.eq(refund))
+            .execute(conn)?;
+
+        let payment = if refund.is_positive() {
+            let payment = payments::table
+                .filter(payments::order_id.eq(request.order_id))
+                .filter(payments::status.eq(PaymentStatus::Captured))
+                .order(payments::id.desc())
+                .first::<Payment>(conn)
+                .optional()?
+                .ok_or_else(|| {
+                    ApiError::Conflict(format!("order {} has no captured payment to refund", request.order_id))
+                })?;
+            Some(payment)
+        } else {
+            None
+        };
+        Ok::<_, ApiError>((ReturnRequest { refund, ..request }, payment))
+    })?;
+
+    if let Some(payment) = payment {
+        let form = FormRefund { amount: Some(request.refund) };
+        if let Err(error) = refund_payment(payment.id, form, gateway, conn) {
+            move_return(id, ReturnStatus::Refunded, ReturnStatus::Received, conn)?;
+            return Err(error);
+        }
+    }
+    show_return(id, conn)
+}
+
+pub fn show_return(id: i32, conn: &SqliteConnection) -> Result<ReturnResponse> {
+    let request = find_return(id, conn)?;
+    let currency = orders::table
+        .find(request.order_id)
+        .select(orders::currency)
+        .first::<String>(conn)?;
+    let lines = load_lines(id, conn)?;
+    Ok(ReturnResponse::new(request, currency, lines))
+}
+
+/// Every return request of an order, oldest first.
+pub fn list_returns(order_id: i32, conn: &SqliteConnection) -> Result<Vec<ReturnResponse>> {
+    let order = find_order(order_id, conn)?;
+    ReturnRequest::belonging_to(&order)
+        .order(return_requests::id.asc())
+        .load::<ReturnRequest>(conn)?
+        .into_iter()
+        .map(|request| {
+            let lines = load_lines(request.id, conn)?;
+            Ok(ReturnResponse::new(request, order.currency.clone(), lines))
+        })
+        .collect()
+}
+
+fn find_order(id: i32, conn: &SqliteConnection) -> Result<Order> {
+    orders::table
+        .find(id)
+        .first::<Order>(conn)
+        .optional()?
+        .ok_or_else(|| ApiError::NotFound(format!("order {}", id)))
+}
+
+fn find_return(id: i32, conn: &SqliteConnection) -> Result<ReturnRequest> {
+    return_requests::table
+        .find(id)
+        .first::<ReturnRequest>(conn)
+        .optional()?
+        .ok_or_else(|| ApiError::NotFound(format!("return {}", id)))
+}
+
+fn load_lines(id: i32, conn: &SqliteConnection) -> Result<Vec<(ReturnLine, OrderLine)>> {
+    Ok(return_lines::table
+        .inner_join(order_lines::table)
+        .filter(return_lines::return_request_id.eq(id))
+        .order(return_lines::id.asc())
+        .load::<(ReturnLine, OrderLine)>(conn)?)
+}
+
+/// Units of an order line in its return requests with one of `statuses`.
+fn returned_quantity(order_line_id: i32, statuses: &[ReturnStatus], conn: &SqliteConnection) -> Result<i64> {
+    Ok(return_lines::table
+        .inner_join(return_requests::table)
+        .filter(return_lines::order_line_id.eq(order_line_id))
+        .filter(return_requests::status.eq_any(statuses))
+        .select(sum(return_lines::quantity))
+        .first::<Option<i64>>(conn)?
+        .unwrap_or(0))
+}
+
+/// The refund of `quantity` units of an order line once `before` of its units
+/// were returned: what was paid for the line, its share of the discount taken
+/// off, prorated on the units returned in all and rounded down, less what the
+/// first `before` units come to. A line returned in several goes is thus
+/// refunded in full. `None` when the refund doesn't fit.
+fn line_refund(order_line: &OrderLine, before: i64, quantity: i64) -> Option<Money> {
+    let paid = i128::from(order_line.total.minor()) - i128::from(order_line.discount.minor());
+    let prorated = |units: i64| paid * i128::from(units) / i128::from(order_line.quantity);
+    i64::try_from(prorated(before + quantity) - prorated(before)).ok().map(Money::from_minor)
+}
+
+/// Moves a return request on from status `from` to `to`, responding with it
+/// as it was.
+fn move_return(id: i32, from: ReturnStatus, to: ReturnStatus, conn: &SqliteConnection) -> Result<ReturnRequest> {
+    let request = find_return(id, conn)?;
+    if request.status != from {
+        return Err(ApiError::Conflict(format!(
+            "return {} is {}, only {} returns can be {}",
+            id,
+            request.status.as_str(),
+            from.as_str(),
+            to.as_str()
+        )));
+    }
+    // Only move on from the status that was checked
+    let updated = diesel::update(return_requests::table.find(id).filter(return_requests::status.eq(from)))
+        .set((
+            return_requests::status.eq(to),
+            return_requests::updated_at.eq(Utc::now().naive_utc()),
+        ))
+        .execute(conn)?;
+    if updated == 0 {
+        return Err(ApiError::Conflict(format!("return {} changed status, try again", id)));
+    }
+    Ok(request)
+}
@@ -0,0 +1,71 @@
+use super::Result;
+use crate::db::models::{FormProductSale, ProductSale};
+use crate::db::money::Money;
+use crate::db::schema::{product_sales, products};
+use crate::errors::ApiError;
+use chrono::NaiveDateTime;
+use diesel::{sqlite::SqliteConnection, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
+use std::collections::HashMap;
+
+pub fn create_sale(product_id: i32, form: FormProductSale, conn: &SqliteConnection) -> Result<ProductSale> {
+    let product = products::table
+        .find(product_id)
+        .select(products::id)
+        .first::<i32>(conn)
+        .optional()?;
+    if product.is_none() {
+        return Err(ApiError::NotFound(format!("product {}", product_id)));
+    }
+
+    diesel::insert_into(product_sales::table)
+        .values((
+            product_sales::product_id.eq(product_id),
+            product_sales::cost.eq(form.cost),
+            product_sales::starts_at.eq(form.starts_at),
+            product_sales::ends_at.eq(form.ends_at),
+        ))
+        .execute(conn)?;
+    Ok(product_sales::table
+        .find(diesel::select(super::last_insert_rowid).first::<i32>(conn)?)
+        .first(conn)?)
+}
+
+/// Every sale of a product, past ones included, in chronological order.
+pub fn list_sales(product_id: i32, conn: &SqliteConnection) -> Result<Vec<ProductSale>> {
+    Ok(product_sales::table
+        .filter(product_sales::product_id.eq(product_id))
+        .order((product_sales::starts_at.asc(), product_sales::id.asc()))
+        .load(conn)?)
+}
+
+pub fn delete_sale(product_id: i32, sale_id: i32, conn: &SqliteConnection) -> Result<i32> {
+    let deleted = diesel::delete(
+        product_sales::table
+            .filter(product_sales::product_id.eq(product_id))
+            .filter(product_sales::id.eq(sale_id)),
+    )
+    .execute(conn)?;
+    if deleted == 0 {
+        return Err(ApiError::NotFound(format!("sale {} of product {}", sale_id, product_id)));
+    }
+
+    Ok(sale_id)
+}
+
+/// Cost of the sale running at `now` for each of `product_ids` that is on
+/// sale. When sales overlap the cheapest one wins.
+pub fn current_sale_costs(product_ids: &[i32], now: NaiveDateTime, conn: &SqliteConnection) -> Result<HashMap<i32, Money>> {
+    let sales = product_sales::table
+        .filter(product_sales::product_id.eq_any(product_ids))
+        .filter(product_sales::starts_at.le(now))
+        .filter(product_sales::ends_at.gt(now))
+        .select((product_sales::product_id, product_sales::cost))
+        .load::<(i32, Money)>(conn)?;
+
+    let mut costs: HashMap<i32, Money> = HashMap::new();
+    for (product_id, cost) in sales {
+        let lowest = costs.entry(product_id).or_insert(cost);
+        *lowest = (*lowest).min(cost);
+    }
+    Ok(costs)
+}
@@ -0,0 +1,427 @@
+use super::synonyms::synonyms_of;
+use super::{product_responses, Result};
+use crate::db::models::Product;
+use crate::db::pagination::{Cursor, CursorKey, Page, PageParams};
+use crate::db::responses::ProductResponse;
+use crate::db::schema::products;
+use crate::errors::ApiError;
+use diesel::{
+    dsl::sql,
+    expression::{bound::Bound, SqlLiteral, UncheckedBind},
+    sql_types::{BigInt, Bool, Double, Integer, Nullable, Text},
+    sqlite::SqliteConnection,
+    ExpressionMethods, QueryDsl, RunQueryDsl,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Control characters FTS5 wraps matches in, turned into `<mark>` tags once
+/// the text around them is escaped.
+const MATCH_OPEN: char = '\u{2}';
+const MATCH_CLOSE: char = '\u{3}';
+
+/// A product matching a full-text search, best matches first.
+///
+/// `highlight` is the product name with the matched terms wrapped in `<mark>`
+/// tags and `snippet` the matching excerpt of its variant values, if any.
+/// Both are HTML, the catalog text in them escaped.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub product: ProductResponse,
+    pub rank: f64,
+    pub highlight: String,
+    pub snippet: String,
+}
+
+#[derive(QueryableByName)]
+struct SearchRow {
+    #[sql_type = "Integer"]
+    id: i32,
+    #[sql_type = "Double"]
+    rank: f64,
+    #[sql_type = "Text"]
+    highlight: String,
+    #[sql_type = "Text"]
+    snippet: String,
+}
+
+#[derive(QueryableByName)]
+struct VocabularyRow {
+    #[sql_type = "Text"]
+    term: String,
+    #[sql_type = "BigInt"]
+    doc: i64,
+}
+
+#[derive(QueryableByName)]
+struct CountRow {
+    #[sql_type = "BigInt"]
+    count: i64,
+}
+
+/// A word or `"quoted phrase"` of a search, optionally ending with `*`.
+#[derive(Debug, Clone, PartialEq)]
+struct Term {
+    text: String,
+    phrase: bool,
+    prefix: bool,
+}
+
+impl Term {
+    fn render(&self) -> String {
+        let star = if self.prefix { "*" } else { "" };
+        format!("{}{}", quote(&self.text), star)
+    }
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "\"\""))
+}
+
+fn parse_terms(input: &str) -> Vec<Term> {
+    let mut terms = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        let mut text = String::new();
+        let phrase = c == '"';
+        if phrase {
+            for c in chars.by_ref() {
+                if c == '"' {
+                    break;
+                }
+                text.push(c);
+            }
+        } else {
+            text.push(c);
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '"' || c == '*' {
+                    break;
+                }
+                text.push(c);
+                chars.next();
+            }
+        }
+        let prefix = chars.peek() == Some(&'*');
+        if prefix {
+            chars.next();
+        }
+
+        let text = text.trim();
+        if !text.is_empty() && text != "*" {
+            terms.push(Term {
+                text: text.to_string(),
+                phrase,
+                prefix,
+            });
+        }
+    }
+
+    terms
+}
+
+/// Turns user input into an FTS5 query, or `None` when it has no terms.
+///
+/// Words are ANDed together, `"quoted words"` are matched as a phrase and a
+/// trailing `*` turns a word or phrase into a prefix query. Everything else is
+/// quoted so that it is never interpreted as FTS5 syntax.
+pub fn fts_query(input: &str) -> Option<String> {
+    let terms = parse_terms(input);
+    if terms.is_empty() {
+        return None;
+    }
+    Some(terms.iter().map(Term::render).collect::<Vec<_>>().join(" "))
+}
+
+/// Like [`fts_query`], but each word or phrase also matches the other terms
+/// of its synonym group, so that "sneakers" finds "trainers".
+///
+/// With `fuzzy` set, plain words also match the catalog words within a few
+/// typos of them, so that "snaekers" still finds "sneakers". Prefix words are
+/// kept as typed.
+pub fn expanded_fts_query(input: &str, fuzzy: bool, conn: &SqliteConnection) -> Result<Option<String>> {
+    let terms = parse_terms(input);
+    if terms.is_empty() {
+        return Ok(None);
+    }
+
+    let texts = terms.iter().map(|term| term.text.clone()).collect::<Vec<_>>();
+    let synonyms = synonyms_of(&texts, conn)?;
+
+    let mut rendered = Vec::with_capacity(terms.len());
+    for term in &terms {
+        if term.prefix {
+            rendered.push(term.render());
+            continue;
+        }
+        let word = term.text.to_lowercase();
+        let mut alternatives = vec![quote(&term.text)];
+        if let Some(others) = synonyms.get(&word) {
+            alternatives.extend(others.iter().map(|other| quote(other)));
+        }
+        if fuzzy && !term.phrase {
+            alternatives.extend(typo_candidates(&word, conn)?.iter().map(|candidate| quote(candidate)));
+        }
+
+        if alternatives.len() == 1 {
+            rendered.push(term.render());
+        } else {
+            rendered.push(format!("({})", alternatives.join(" OR ")));
+        }
+    }
+
+    Ok(Some(rendered.join(" AND ")))
+}
+
+/// Catalog words within a few typos of `word`, closest and most common first.
+///
+/// Only the words starting with the same character and of a close enough
+/// length are read from the vocabulary, never all of it: as with most spell
+/// checkers, a typo in the first character isn't corrected.
+fn typo_candidates(word: &str, conn: &SqliteConnection) -> Result<Vec<String>> {
+    let max_distance = max_typos(word);
+    let first = match word.chars().next() {
+        Some(first) if max_distance > 0 => first,
+        _ => return Ok(vec![]),
+    };
+    let length = word.chars().count();
+    // fts5vocab answers range constraints on `term` without a full scan
+    let rows = diesel::sql_query(
+        "SELECT term, doc FROM products_words_vocab \
+         WHERE term >= ?1 AND term < ?2 AND length(term) BETWEEN ?3 AND ?4",
+    )
+    .bind::<Text, _>(first.to_string())
+    .bind::<Text, _>(format!("{}{}", first, char::MAX))
+    .bind::<BigInt, _>(length.saturating_sub(max_distance) as i64)
+    .bind::<BigInt, _>((length + max_distance) as i64)
+    .load::<VocabularyRow>(conn)?;
+
+    let mut candidates = rows
+        .into_iter()
+        .filter(|row| row.term != word)
+        .filter_map(|row| {
+            let distance = edit_distance(word, &row.term);
+            (distance <= max_distance).then(|| (distance, -row.doc, row.term))
+        })
+        .collect::<Vec<_>>();
+    candidates.sort();
+
+    Ok(candidates
+        .into_iter()
+        .take(MAX_FUZZY_CANDIDATES)
+        .map(|(_, _, candidate)| candidate)
+        .collect())
+}
+
+const MAX_FUZZY_CANDIDATES: usize = 5;
+
+/// Number of typos tolerated in a word: none for very short words, where
+/// anything would match, then more as the word gets longer.
+fn max_typos(word: &str) -> usize {
+    match word.chars().count() {
+        0..=3 => 0,
+        4..=5 => 1,
+        _ => 2,
+    }
+}
+
+/// Optimal string alignment distance: insertions, deletions, substitutions and
+/// transpositions of adjacent characters each count as one edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = distance;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+/// Escapes text marked up by FTS5 with [`MATCH_OPEN`] and [`MATCH_CLOSE`]
+/// into HTML, the matches wrapped in `<mark>` tags.
+fn mark_matches(text: &str) -> String {
+    let mut html = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            MATCH_OPEN => html.push_str("<mark>"),
+            MATCH_CLOSE => html.push_str("</mark>"),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            c => html.push(c),
+        }
+    }
+    html
+}
+
+/// SQL condition keeping the products matched by an FTS5 query built with
+/// [`expanded_fts_query`].
+pub(crate) fn matching_products(query: String) -> SqlLiteral<Bool, UncheckedBind<SqlLiteral<Bool>, Bound<Text, String>>> {
+    sql::<Bool>("products.id IN (SELECT rowid FROM products_fts WHERE products_fts MATCH ")
+        .bind::<Text, _>(query)
+        .sql(")")
+}
+
+/// Full-text search over product names and variant values, ranked by
+/// relevance. Words match their synonyms and, with `fuzzy` set, words a few
+/// typos away.
+///
+/// Cursors from this function carry the rank of the last hit, so they can
+/// only be used to page through the same search.
+pub fn search_products(search: String, fuzzy: bool, page: PageParams, conn: &SqliteConnection) -> Result<Page<SearchHit>> {
+    let query = expanded_fts_query(&search, fuzzy, conn)?
+        .ok_or_else(|| ApiError::Validation("search must contain at least one term".to_string()))?;
+
+    let total = diesel::sql_query("SELECT COUNT(*) AS count FROM products_fts WHERE products_fts MATCH ?")
+        .bind::<Text, _>(&query)
+        .get_result::<CountRow>(conn)?
+        .count;
+
+    let (after_rank, after_id) = match page.cursor()? {
+        Some(Cursor { id, key: Some(CursorKey::Rank(rank)) }) => (Some(rank), Some(id)),
+        Some(_) => {
+            return Err(ApiError::Validation(
+                "cursor does not belong to a search".to_string(),
+            ))
+        }
+        None => (None, None),
+    };
+    let offset = if after_rank.is_some() { 0 } else { page.offset() };
+    let limit = page.limit();
+
+    let mut rows = diesel::sql_query(format!(
+        "SELECT products_fts.rowid AS id, products_fts.rank AS rank, \
+            highlight(products_fts, 0, '{open}', '{close}') AS highlight, \
+            snippet(products_fts, 1, '{open}', '{close}', '…', 8) AS snippet \
+         FROM products_fts \
+         WHERE products_fts MATCH ?1 \
+            AND (?2 IS NULL OR products_fts.rank > ?2 OR (products_fts.rank = ?2 AND products_fts.rowid > ?3)) \
+         ORDER BY products_fts.rank, products_fts.rowid \
+         LIMIT ?4 OFFSET ?5",
+        open = MATCH_OPEN,
+        close = MATCH_CLOSE,
+    ))
+    .bind::<Text, _>(&query)
+    .bind::<Nullable<Double>, _>(after_rank)
+    .bind::<Nullable<Integer>, _>(after_id)
+    .bind::<BigInt, _>(limit + 1)
+    .bind::<BigInt, _>(offset)
+    .load::<SearchRow>(conn)?;
+
+    let next_cursor = if rows.len() as i64 > limit {
+        rows.truncate(limit as usize);
+        rows.last().map(|row| {
+            Cursor {
+                id: row.id,
+                key: Some(CursorKey::Rank(row.rank)),
+            }
+            .encode()
+        })
+    } else {
+        None
+    };
+
+    let ids = rows.iter().map(|row| row.id).collect::<Vec<_>>();
+    let mut products_result = products::table
+        .filter(products::id.eq_any(&ids))
+        .load::<Product>(conn)?;
+    products_result.sort_by_key(|product| ids.iter().position(|id| *id == product.id));
+
+    let mut rows_by_id = rows.into_iter().map(|row| (row.id, row)).collect::<HashMap<_, _>>();
+    let items = product_responses(products_result, conn)?
+        .into_iter()
+        .filter_map(|product| {
+            let row = rows_by_id.remove(&product.id)?;
+            Some(SearchHit {
+                product,
+                rank: row.rank,
+                highlight: mark_matches(&row.highlight),
+                snippet: mark_matches(&row.snippet),
+            })
+        })
+        .collect();
+
+    Ok(Page { items, next_cursor, total })
+}
+
+pub const DEFAULT_SUGGESTIONS: u16 = 10;
+pub const MAX_SUGGESTIONS: u16 = 50;
+
+/// A product name completing what the user typed so far.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Suggestion {
+    pub product_id: i32,
+    pub name: String,
+}
+
+#[derive(QueryableByName)]
+struct SuggestionRow {
+    #[sql_type = "Integer"]
+    id: i32,
+    #[sql_type = "Text"]
+    name: String,
+}
+
+/// Suggests product names for type-ahead, best matches first.
+///
+/// The last word typed is treated as a prefix. When nothing completes the
+/// input, falls back to fuzzy matching so that typos still get suggestions.
+pub fn suggest_products(input: String, limit: Option<u16>, conn: &SqliteConnection) -> Result<Vec<Suggestion>> {
+    let limit = limit.unwrap_or(DEFAULT_SUGGESTIONS).min(MAX_SUGGESTIONS);
+    let mut terms = parse_terms(&input);
+    match terms.last_mut() {
+        Some(last) => last.prefix = true,
+        None => return Ok(vec![]),
+    }
+    let query = terms.iter().map(Term::render).collect::<Vec<_>>().join(" ");
+
+    let suggestions = suggest_names(&query, limit, conn)?;
+    if !suggestions.is_empty() {
+        return Ok(suggestions);
+    }
+    match expanded_fts_query(&input, true, conn)? {
+        Some(query) => suggest_names(&query, limit, conn),
+        None => Ok(vec![]),
+    }
+}
+
+fn suggest_names(query: &str, limit: u16, conn: &SqliteConnection) -> Result<Vec<Suggestion>> {
+    let rows = diesel::sql_query(
+        "SELECT products_fts.rowid AS id, products_fts.name AS name \
+         FROM products_fts \
+         WHERE products_fts MATCH ?1 \
+         ORDER BY products_fts.rank, products_fts.rowid \
+         LIMIT ?2",
+    )
+    .bind::<Text, _>(format!("name : ({})", query))
+    .bind::<Integer, _>(i32::from(limit))
+    .load::<SuggestionRow>(conn)?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| Suggestion {
+            product_id: row.id,
+            name: row.name,
+        })
+        .collect())
+}
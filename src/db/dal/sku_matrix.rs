@@ -0,0 +1,203 @@
+use super::{skus, Result};
+use crate::db::models::{FormSku, NewCompleteProduct, ProductVariant, SkuMatrix, Variant};
+use crate::db::responses::{SkuPlan, SkuPlanOption, SkuResponse};
+use crate::db::schema::{products_variants, variants};
+use crate::errors::ApiError;
+use diesel::{sqlite::SqliteConnection, Connection, ExpressionMethods, QueryDsl, RunQueryDsl};
+
+/// Most SKUs a single matrix may generate.
+pub const MAX_MATRIX_SKUS: usize = 1000;
+
+/// The values of one variant of a product, along with the id of their
+/// `products_variants` row once the product exists.
+struct MatrixVariant {
+    name: String,
+    values: Vec<(Option<i32>, Option<String>)>,
+}
+
+/// SKUs `create_product` would generate for `new_product`, without creating
+/// anything.
+pub fn preview_new_product(new_product: &NewCompleteProduct) -> Result<Vec<SkuPlan>> {
+    let matrix = new_product
+        .sku_matrix
+        .as_ref()
+        .ok_or_else(|| ApiError::Validation("a preview needs a sku_matrix".to_string()))?;
+    let variants = new_product
+        .variants
+        .iter()
+        .map(|variant| MatrixVariant {
+            name: variant.variant.name.clone(),
+            values: variant.values.iter().map(|value| (None, value.clone())).collect(),
+        })
+        .collect::<Vec<_>>();
+
+    Ok(combinations(&variants, matrix)?
+        .iter()
+        .map(|combination| plan(&variants, combination, matrix))
+        .collect())
+}
+
+/// SKUs [`generate_sku_matrix`] would create for a product, without creating
+/// anything.
+pub fn preview_sku_matrix(product_id: i32, matrix: &SkuMatrix, conn: &SqliteConnection) -> Result<Vec<SkuPlan>> {
+    let variants = product_variants(product_id, conn)?;
+    Ok(new_combinations(product_id, &variants, matrix, conn)?
+        .iter()
+        .map(|combination| plan(&variants, combination, matrix))
+        .collect())
+}
+
+/// Creates a SKU for every combination of the variant values of a product
+/// that is neither excluded nor already a SKU.
+pub fn generate_sku_matrix(product_id: i32, matrix: &SkuMatrix, conn: &SqliteConnection) -> Result<Vec<SkuResponse>> {
+    conn.transaction(|| {
+        let variants = product_variants(product_id, conn)?;
+        new_combinations(product_id, &variants, matrix, conn)?
+            .iter()
+            .map(|combination| {
+                let form = FormSku {
+                    code: plan(&variants, combination, matrix).code,
+                    stock: Some(matrix.stock),
+                    price: matrix.price,
+                    allow_backorder: false,
+                    product_variant_ids: combination
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(variant, value)| variants[variant].values[*value].0)
+                        .collect(),
+                };
+                skus::create_sku(product_id, form, conn)
+            })
+            .collect()
+    })
+}
+
+/// The variants of a product with their values, in the order they were
+/// added.
+fn product_variants(product_id: i32, conn: &SqliteConnection) -> Result<Vec<MatrixVariant>> {
+    skus::ensure_product(product_id, conn)?;
+    let values = products_variants::table
+        .inner_join(variants::table)
+        .filter(products_variants::product_id.eq(product_id))
+        .order(products_variants::id.asc())
+        .load::<(ProductVariant, Variant)>(conn)?;
+
+    let mut grouped: Vec<MatrixVariant> = Vec::new();
+    for (product_variant, variant) in values {
+        let value = (Some(product_variant.id), product_variant.value);
+        match grouped.iter_mut().find(|group| group.name == variant.name) {
+            Some(group) if group.values.iter().any(|(_, existing)| *existing == value.1) => {}
+            Some(group) => group.values.push(value),
+            None => grouped.push(MatrixVariant {
+                name: variant.name,
+                values: vec![value],
+            }),
+        }
+    }
+    Ok(grouped)
+}
+
+/// Combinations of the matrix that aren't SKUs of the product yet.
+fn new_combinations(
+    product_id: i32,
+    variants: &[MatrixVariant],
+    matrix: &SkuMatrix,
+    conn: &SqliteConnection,
+) -> Result<Vec<Vec<usize>>> {
+    let existing = skus::existing_combinations(product_id, conn)?;
+    Ok(combinations(variants, matrix)?
+        .into_iter()
+        .filter(|combination| {
+            let mut product_variant_ids = combination
+                .iter()
+                .enumerate()
+                .filter_map(|(variant, value)| variants[variant].values[*value].0)
+                .collect::<Vec<_>>();
+            product_variant_ids.sort_unstable();
+            !existing.contains(&product_variant_ids)
+        })
+        .collect())
+}
+
+/// Every combination of one value per variant that `matrix` doesn't exclude,
+/// as the index of the value of each variant.
+fn combinations(variants: &[MatrixVariant], matrix: &SkuMatrix) -> Result<Vec<Vec<usize>>> {
+    for exclusion in &matrix.exclude {
+        for (name, value) in exclusion {
+            let variant = variants
+                .iter()
+                .find(|variant| variant.name == *name)
+                .ok_or_else(|| ApiError::Validation(format!("excluded variant '{}' doesn't exist", name)))?;
+            if !variant.values.iter().any(|(_, existing)| existing.as_deref() == Some(value.as_str())) {
+                return Err(ApiError::Validation(format!(
+                    "excluded value '{}' isn't a value of variant '{}'",
+                    value, name
+                )));
+            }
+        }
+    }
+
+    let count = variants
+        .iter()
+        .try_fold(1usize, |count, variant| count.checked_mul(variant.values.len()));
+    if count.is_none_or(|count| count > MAX_MATRIX_SKUS) {
+        return Err(ApiError::Validation(format!(
+            "a SKU matrix can't have more than {} combinations",
+            MAX_MATRIX_SKUS
+        )));
+    }
+
+    let mut combinations = vec![Vec::new()];
+    for variant in variants {
+        combinations = combinations
+            .into_iter()
+            .flat_map(|combination| {
+                (0..variant.values.len()).map(move |value| {
+                    let mut combination = combination.clone();
+                    combination.push(value);
+                    combination
+                })
+            })
+            .collect();
+    }
+
+    let is_excluded = |combination: &Vec<usize>| {
+        matrix.exclude.iter().any(|exclusion| {
+            exclusion.iter().all(|(name, excluded)| {
+                variants.iter().zip(combination).any(|(variant, value)| {
+                    variant.name == *name && variant.values[*value].1.as_deref() == Some(excluded.as_str())
+                })
+            })
+        })
+    };
+    Ok(combinations.into_iter().filter(|combination| !is_excluded(combination)).collect())
+}
+
+fn plan(variants: &[MatrixVariant], combination: &[usize], matrix: &SkuMatrix) -> SkuPlan {
+    let options = variants
+        .iter()
+        .zip(combination)
+        .map(|(variant, value)| SkuPlanOption {
+            name: variant.name.clone(),
+            value: variant.values[*value].1.clone(),
+        })
+        .collect::<Vec<_>>();
+
+    let mut code = vec![matrix.code_prefix.trim().to_string()];
+    code.extend(options.iter().filter_map(|option| option.value.as_deref()).map(code_part));
+    SkuPlan {
+        code: code.into_iter().filter(|part| !part.is_empty()).collect::<Vec<_>>().join("-"),
+        options,
+    }
+}
+
+/// Uppercases a variant value and replaces anything but letters and digits
+/// with dashes, e.g. `light blue` into `LIGHT-BLUE`.
+fn code_part(value: &str) -> String {
+    value
+        .to_uppercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
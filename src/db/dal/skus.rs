@@ -0,0 +1,217 @@
+use super::inventory::{has_movements, record_movement};
+use super::{last_insert_rowid, Result};
+use crate::db::models::{FormSku, MovementReason, ProductVariant, Sku, SkuValue, Variant, DEFAULT_LOCATION_ID};
+use crate::db::responses::SkuResponse;
+use crate::db::schema::{products, products_variants, sku_values, skus, variants};
+use crate::errors::ApiError;
+use diesel::{
+    sqlite::SqliteConnection, BelongingToDsl, Connection, ExpressionMethods, GroupedBy, OptionalExtension,
+    QueryDsl, RunQueryDsl,
+};
+
+pub fn create_sku(product_id: i32, form: FormSku, conn: &SqliteConnection) -> Result<SkuResponse> {
+    conn.transaction(|| {
+        ensure_product(product_id, conn)?;
+        let combination = checked_combination(product_id, None, &form.product_variant_ids, conn)?;
+        diesel::insert_into(skus::table)
+            .values((
+                skus::product_id.eq(product_id),
+                skus::code.eq(form.code.trim()),
+                skus::price.eq(form.price),
+                skus::allow_backorder.eq(form.allow_backorder),
+            ))
+            .execute(conn)?;
+        let sku_id = diesel::select(last_insert_rowid).first(conn)?;
+        insert_values(sku_id, &combination, conn)?;
+        let stock = form.stock.unwrap_or(0);
+        if stock != 0 {
+            let note = Some("initial stock");
+            record_movement(sku_id, DEFAULT_LOCATION_ID, MovementReason::Correction, stock, note, conn)?;
+        }
+
+        show_sku(sku_id, conn)
+    })
+}
+
+/// Every SKU of a product, in creation order.
+pub fn list_skus(product_id: i32, conn: &SqliteConnection) -> Result<Vec<SkuResponse>> {
+    ensure_product(product_id, conn)?;
+    let skus_result = skus::table
+        .filter(skus::product_id.eq(product_id))
+        .order(skus::id.asc())
+        .load::<Sku>(conn)?;
+    sku_responses(skus_result, conn)
+}
+
+pub fn show_sku(id: i32, conn: &SqliteConnection) -> Result<SkuResponse> {
+    let sku = skus::table
+        .find(id)
+        .get_result::<Sku>(conn)
+        .optional()?
+        .ok_or_else(|| ApiError::NotFound(format!("sku {}", id)))?;
+
+    let mut responses = sku_responses(vec![sku], conn)?;
+    Ok(responses.remove(0))
+}
+
+/// Replaces the code, price and combination of a SKU. Its stock is spread
+/// over locations, so it can't be replaced here but only moved at one of them.
+pub fn update_sku(id: i32, form: FormSku, conn: &SqliteConnection) -> Result<SkuResponse> {
+    conn.transaction(|| {
+        let sku = show_sku(id, conn)?;
+        if form.stock.is_some_and(|stock| stock != sku.stock) {
+            return Err(ApiError::Validation(format!(
+                "the stock of sku {} changes through /skus/{}/inventory",
+                id, id
+            )));
+        }
+        let combination = checked_combination(sku.product_id, Some(id), &form.product_variant_ids, conn)?;
+        diesel::update(skus::table.find(id))
+            .set((
+                skus::code.eq(form.code.trim()),
+                skus::price.eq(form.price),
+                skus::allow_backorder.eq(form.allow_backorder),
+            ))
+            .execute(conn)?;
+        diesel::delete(sku_values::table.filter(sku_values::sku_id.eq(id))).execute(conn)?;
+        insert_values(id, &combination, conn)?;
+
+        show_sku(id, conn)
+    })
+}
+
+/// Deletes a SKU that never had stock. Lines of past orders are unlinked from
+/// it. A SKU with an inventory history is kept, its stock can be corrected to
+/// zero instead.
+pub fn delete_sku(id: i32, conn: &SqliteConnection) -> Result<i32> {
+    if has_movements(&[id], conn)? {
+        return Err(ApiError::Conflict(format!(
+            "sku {} has an inventory history, correct its stock to zero instead",
+            id
+        )));
+    }
+    let deleted = diesel::delete(skus::table.find(id)).execute(conn)?;
+    if deleted == 0 {
+        return Err(ApiError::NotFound(format!("sku {}", id)));
+    }
+
+    Ok(id)
+}
+
+pub(super) fn ensure_product(product_id: i32, conn: &SqliteConnection) -> Result<()> {
+    let product = products::table
+        .find(product_id)
+        .select(products::id)
+        .first::<i32>(conn)
+        .optional()?;
+    if product.is_none() {
+        return Err(ApiError::NotFound(format!("product {}", product_id)));
+    }
+    Ok(())
+}
+
+/// Checks that `product_variant_ids` are values of the product, at most one
+/// per variant, and that no other SKU of the product than `sku_id` has the
+/// same combination. Returns the combination sorted.
+fn checked_combination(
+    product_id: i32,
+    sku_id: Option<i32>,
+    product_variant_ids: &[i32],
+    conn: &SqliteConnection,
+) -> Result<Vec<i32>> {
+    let combination = checked_values(product_id, product_variant_ids, conn)?;
+    let others = product_combinations(product_id, conn)?;
+    if let Some((other, _)) = others
+        .iter()
+        .find(|(other, other_combination)| Some(*other) != sku_id && *other_combination == combination)
+    {
+        return Err(ApiError::Conflict(format!("sku {} already has these values", other)));
+    }
+
+    Ok(combination)
+}
+
+/// Checks that `product_variant_ids` are distinct values of the product, at
+/// most one per variant, and returns them sorted.
+pub(super) fn checked_values(product_id: i32, product_variant_ids: &[i32], conn: &SqliteConnection) -> Result<Vec<i32>> {
+    let mut combination = product_variant_ids.to_vec();
+    combination.sort_unstable();
+    combination.dedup();
+    if combination.len() != product_variant_ids.len() {
+        return Err(ApiError::Validation("the same value can't be chosen twice".to_string()));
+    }
+
+    let values = products_variants::table
+        .filter(products_variants::id.eq_any(&combination))
+        .filter(products_variants::product_id.eq(product_id))
+        .load::<ProductVariant>(conn)?;
+    if values.len() != combination.len() {
+        return Err(ApiError::Validation(format!(
+            "every value must be a variant value of product {}",
+            product_id
+        )));
+    }
+    let mut variant_ids = values.iter().map(|value| value.variant_id).collect::<Vec<_>>();
+    variant_ids.sort_unstable();
+    variant_ids.dedup();
+    if variant_ids.len() != values.len() {
+        return Err(ApiError::Validation("two values of the same variant can't be combined".to_string()));
+    }
+
+    Ok(combination)
+}
+
+/// The sorted combination of every SKU of a product.
+pub(super) fn existing_combinations(product_id: i32, conn: &SqliteConnection) -> Result<Vec<Vec<i32>>> {
+    Ok(product_combinations(product_id, conn)?
+        .into_iter()
+        .map(|(_, combination)| combination)
+        .collect())
+}
+
+/// Every SKU of a product by id, with its sorted combination.
+pub(super) fn product_combinations(product_id: i32, conn: &SqliteConnection) -> Result<Vec<(i32, Vec<i32>)>> {
+    let product_skus = skus::table.filter(skus::product_id.eq(product_id)).load::<Sku>(conn)?;
+    let values = SkuValue::belonging_to(&product_skus)
+        .load::<SkuValue>(conn)?
+        .grouped_by(&product_skus);
+    Ok(product_skus
+        .iter()
+        .zip(values)
+        .map(|(sku, values)| {
+            let mut combination = values.iter().map(|value| value.product_variant_id).collect::<Vec<_>>();
+            combination.sort_unstable();
+            (sku.id, combination)
+        })
+        .collect())
+}
+
+fn insert_values(sku_id: i32, combination: &[i32], conn: &SqliteConnection) -> Result<()> {
+    let values = combination
+        .iter()
+        .map(|product_variant_id| SkuValue {
+            sku_id,
+            product_variant_id: *product_variant_id,
+        })
+        .collect::<Vec<_>>();
+    diesel::insert_into(sku_values::table).values(&values).execute(conn)?;
+    Ok(())
+}
+
+/// Pairs each SKU with its variant values, keeping the order of `skus`.
+fn sku_responses(skus: Vec<Sku>, conn: &SqliteConnection) -> Result<Vec<SkuResponse>> {
+    let options = SkuValue::belonging_to(&skus)
+        .inner_join(products_variants::table.inner_join(variants::table))
+        .order((products_variants::variant_id.asc(), products_variants::id.asc()))
+        .load::<(SkuValue, (ProductVariant, Variant))>(conn)?
+        .grouped_by(&skus);
+
+    Ok(skus
+        .into_iter()
+        .zip(options)
+        .map(|(sku, options)| {
+            let options = options.into_iter().map(|(_, option)| option).collect();
+            SkuResponse::new(sku, options)
+        })
+        .collect())
+}
@@ -0,0 +1,141 @@
+use super::{last_insert_rowid, Result};
+use crate::db::models::{FormSynonymGroup, NewSynonym, Synonym, SynonymGroup, SynonymGroupTerms};
+use crate::db::schema::{synonym_groups, synonyms};
+use crate::errors::ApiError;
+use diesel::{
+    sqlite::SqliteConnection, BelongingToDsl, Connection, ExpressionMethods, GroupedBy,
+    OptionalExtension, QueryDsl, RunQueryDsl,
+};
+use std::collections::HashMap;
+
+pub fn create_synonym_group(form: FormSynonymGroup, conn: &SqliteConnection) -> Result<SynonymGroupTerms> {
+    let terms = normalized_terms(form)?;
+    conn.transaction(|| {
+        diesel::insert_into(synonym_groups::table)
+            .default_values()
+            .execute(conn)?;
+        let group_id = diesel::select(last_insert_rowid).first(conn)?;
+        insert_terms(group_id, &terms, conn)?;
+
+        Ok(SynonymGroupTerms { id: group_id, terms })
+    })
+}
+
+pub fn list_synonym_groups(conn: &SqliteConnection) -> Result<Vec<SynonymGroupTerms>> {
+    let groups = synonym_groups::table
+        .order(synonym_groups::id.asc())
+        .load::<SynonymGroup>(conn)?;
+    let terms = Synonym::belonging_to(&groups)
+        .order(synonyms::term.asc())
+        .load::<Synonym>(conn)?
+        .grouped_by(&groups);
+
+    Ok(groups
+        .into_iter()
+        .zip(terms)
+        .map(|(group, terms)| SynonymGroupTerms {
+            id: group.id,
+            terms: terms.into_iter().map(|synonym| synonym.term).collect(),
+        })
+        .collect())
+}
+
+pub fn show_synonym_group(id: i32, conn: &SqliteConnection) -> Result<SynonymGroupTerms> {
+    let group = synonym_groups::table
+        .find(id)
+        .get_result::<SynonymGroup>(conn)
+        .optional()?
+        .ok_or_else(|| ApiError::NotFound(format!("synonym group {}", id)))?;
+    let terms = Synonym::belonging_to(&group)
+        .order(synonyms::term.asc())
+        .select(synonyms::term)
+        .load::<String>(conn)?;
+
+    Ok(SynonymGroupTerms { id: group.id, terms })
+}
+
+/// Replaces every term of the group.
+pub fn update_synonym_group(id: i32, form: FormSynonymGroup, conn: &SqliteConnection) -> Result<SynonymGroupTerms> {
+    let terms = normalized_terms(form)?;
+    conn.transaction(|| {
+        show_synonym_group(id, conn)?;
+        diesel::delete(synonyms::table.filter(synonyms::synonym_group_id.eq(id))).execute(conn)?;
+        insert_terms(id, &terms, conn)?;
+
+        Ok(SynonymGroupTerms { id, terms })
+    })
+}
+
+pub fn delete_synonym_group(id: i32, conn: &SqliteConnection) -> Result<i32> {
+    let deleted = diesel::delete(synonym_groups::table.find(id)).execute(conn)?;
+    if deleted == 0 {
+        return Err(ApiError::NotFound(format!("synonym group {}", id)));
+    }
+
+    Ok(id)
+}
+
+/// Maps each of `words` that belongs to a synonym group to the other terms of
+/// its group. Words are compared case-insensitively.
+pub fn synonyms_of(words: &[String], conn: &SqliteConnection) -> Result<HashMap<String, Vec<String>>> {
+    let words = words.iter().map(|word| word.to_lowercase()).collect::<Vec<_>>();
+    let matches = synonyms::table
+        .filter(synonyms::term.eq_any(&words))
+        .load::<Synonym>(conn)?;
+    if matches.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let group_ids = matches.iter().map(|synonym| synonym.synonym_group_id).collect::<Vec<_>>();
+    let group_terms = synonyms::table
+        .filter(synonyms::synonym_group_id.eq_any(&group_ids))
+        .order(synonyms::term.asc())
+        .load::<Synonym>(conn)?;
+
+    Ok(matches
+        .into_iter()
+        .map(|word| {
+            let others = group_terms
+                .iter()
+                .filter(|synonym| synonym.synonym_group_id == word.synonym_group_id && synonym.id != word.id)
+                .map(|synonym| synonym.term.clone())
+                .collect();
+            (word.term, others)
+        })
+        .collect())
+}
+
+fn insert_terms(group_id: i32, terms: &[String], conn: &SqliteConnection) -> Result<()> {
+    let new_synonyms = terms
+        .iter()
+        .map(|term| NewSynonym {
+            synonym_group_id: group_id,
+            term,
+        })
+        .collect::<Vec<_>>();
+    diesel::insert_into(synonyms::table)
+        .values(&new_synonyms)
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Trims, lowercases and dedups the terms of a group, which needs at least
+/// two distinct terms to mean anything.
+fn normalized_terms(form: FormSynonymGroup) -> Result<Vec<String>> {
+    let mut terms = Vec::new();
+    for term in form.terms {
+        let term = term.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+        if term.is_empty() {
+            return Err(ApiError::Validation("synonym terms must not be blank".to_string()));
+        }
+        if !terms.contains(&term) {
+            terms.push(term);
+        }
+    }
+    if terms.len() < 2 {
+        return Err(ApiError::Validation(
+            "a synonym group needs at least two distinct terms".to_string(),
+        ));
+    }
+    Ok(terms)
+}
@@ -0,0 +1,144 @@
+use crate::db::dal::search::fts_query;
+use crate::db::money::Money;
+use crate::errors::ApiError;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// Prefix of the query parameters filtering on variant values, as in
+/// `variant.size=42`.
+pub const VARIANT_PARAM_PREFIX: &str = "variant.";
+
+/// Conditions a product must meet to be listed. Unset fields don't filter.
+///
+/// `search` is a full-text query over product names and variant values.
+/// `variants` maps a variant name to the values accepted for it: a product
+/// matches when, for every listed variant, it has at least one of the values.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ProductFilter {
+    pub search: Option<String>,
+    pub active: Option<bool>,
+    pub min_cost: Option<Money>,
+    pub max_cost: Option<Money>,
+    pub name_prefix: Option<String>,
+    #[serde(default)]
+    pub variants: BTreeMap<String, Vec<String>>,
+}
+
+impl ProductFilter {
+    /// Collects the `variant.<name>=<value>` pairs out of raw query parameters,
+    /// ignoring every other parameter.
+    pub fn variants_from_params(params: &[(String, String)]) -> Result<BTreeMap<String, Vec<String>>, ApiError> {
+        let mut variants: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (key, value) in params {
+            if let Some(name) = key.strip_prefix(VARIANT_PARAM_PREFIX) {
+                if name.is_empty() {
+                    return Err(ApiError::Validation(format!(
+                        "variant filter '{}' is missing a variant name",
+                        key
+                    )));
+                }
+                let values = variants.entry(name.to_string()).or_default();
+                if !values.contains(value) {
+                    values.push(value.clone());
+                }
+            }
+        }
+        Ok(variants)
+    }
+
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if let Some(search) = &self.search {
+            if fts_query(search).is_none() {
+                return Err(ApiError::Validation(
+                    "search must contain at least one term".to_string(),
+                ));
+            }
+        }
+        if let (Some(min_cost), Some(max_cost)) = (self.min_cost, self.max_cost) {
+            if min_cost > max_cost {
+                return Err(ApiError::Validation(
+                    "min_cost must not be greater than max_cost".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortField {
+    Id,
+    Name,
+    Cost,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+
+/// Ordering of a product listing. Ties are always broken by id, in the same
+/// direction, so that keyset cursors stay stable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProductSort {
+    pub field: SortField,
+    pub direction: SortDirection,
+}
+
+impl Default for ProductSort {
+    fn default() -> Self {
+        ProductSort {
+            field: SortField::Id,
+            direction: SortDirection::Asc,
+        }
+    }
+}
+
+impl FromStr for SortField {
+    type Err = ApiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "id" => Ok(SortField::Id),
+            "name" => Ok(SortField::Name),
+            "cost" => Ok(SortField::Cost),
+            other => Err(ApiError::Validation(format!(
+                "unknown sort key '{}', expected one of: id, name, cost",
+                other
+            ))),
+        }
+    }
+}
+
+impl FromStr for SortDirection {
+    type Err = ApiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "asc" => Ok(SortDirection::Asc),
+            "desc" => Ok(SortDirection::Desc),
+            other => Err(ApiError::Validation(format!(
+                "unknown sort order '{}', expected asc or desc",
+                other
+            ))),
+        }
+    }
+}
+
+impl ProductSort {
+    /// Builds a sort from the raw `sort` and `order` query parameters.
+    pub fn parse(field: Option<&str>, direction: Option<&str>) -> Result<Self, ApiError> {
+        let default = ProductSort::default();
+        Ok(ProductSort {
+            field: field.map(str::parse).transpose()?.unwrap_or(default.field),
+            direction: direction
+                .map(str::parse)
+                .transpose()?
+                .unwrap_or(default.direction),
+        })
+    }
+}
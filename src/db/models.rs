@@ -1,22 +1,73 @@
+use super::schema::exchange_rates;
+use super::schema::inventory_movements;
+use super::schema::locations;
+use super::schema::order_events;
+use super::schema::order_line_options;
+use super::schema::order_lines;
+use super::schema::orders;
+use super::schema::payments;
+use super::schema::product_price_changes;
+use super::schema::product_prices;
+use super::schema::product_sales;
 use super::schema::products;
 use super::schema::products_variants;
+use super::schema::promo_code_variant_rules;
+use super::schema::promo_codes;
+use super::schema::refunds;
+use super::schema::return_lines;
+use super::schema::return_requests;
+use super::schema::sku_values;
+use super::schema::skus;
+use super::schema::synonym_groups;
+use super::schema::synonyms;
+use super::schema::transfers;
 use super::schema::variants;
+use super::money::{Money, Rate, DEFAULT_CURRENCY};
+use super::order_status::{OrderStatus, OrderTransition};
+use crate::validation::{
+    adjustment_quantity, currency_code, distinct_locations, non_negative, not_blank, positive_amount, positive_rate,
+    promo_discount, sale_period, unique_product_variants, unique_values, variant_rule_condition,
+};
+use chrono::NaiveDateTime;
+use diesel::deserialize::{self, FromSql};
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use diesel::sqlite::Sqlite;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use validator::Validate;
 
 #[derive(Identifiable, Queryable, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Product {
     pub id: i32,
     pub name: String,
-    pub cost: f64,
+    pub cost: Money,
+    pub currency: String,
     pub active: bool,
+    pub compare_at_cost: Option<Money>,
 }
 
-#[derive(Insertable, Debug, AsChangeset, Serialize, Deserialize, Clone)]
+#[derive(Insertable, Debug, AsChangeset, Serialize, Deserialize, Clone, Validate)]
 #[table_name = "products"]
+#[changeset_options(treat_none_as_null = "true")]
 pub struct NewProduct {
+    #[validate(custom = "not_blank")]
     pub name: String,
-    pub cost: f64,
+    #[validate(custom = "non_negative")]
+    pub cost: Money,
+    #[serde(default = "default_currency")]
+    #[validate(custom = "currency_code")]
+    pub currency: String,
     pub active: bool,
+    /// Original price, shown struck through next to the cost.
+    #[serde(default)]
+    #[validate(custom = "non_negative")]
+    pub compare_at_cost: Option<Money>,
+}
+
+fn default_currency() -> String {
+    DEFAULT_CURRENCY.to_string()
 }
 
 #[derive(Identifiable, Queryable, Debug, Serialize, Deserialize)]
@@ -26,9 +77,10 @@ pub struct Variant {
     pub name: String,
 }
 
-#[derive(Insertable, Debug, Clone, Serialize, Deserialize)]
+#[derive(Insertable, Debug, Clone, Serialize, Deserialize, Validate)]
 #[table_name = "variants"]
 pub struct NewVariant {
+    #[validate(custom = "not_blank")]
     pub name: String,
 }
 
@@ -50,27 +102,38 @@ pub struct NewProductVariant {
     pub value: Option<String>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, Validate)]
 pub struct NewVariantValue {
+    #[validate]
     pub variant: NewVariant,
+    #[validate(custom = "unique_values")]
     pub values: Vec<Option<String>>,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, Validate)]
 pub struct NewCompleteProduct {
+    #[validate]
     pub product: NewProduct,
+    #[validate]
     pub variants: Vec<NewVariantValue>,
+    /// Generates a SKU for every combination of the values of `variants`.
+    #[serde(default)]
+    #[validate]
+    pub sku_matrix: Option<SkuMatrix>,
 }
 
 
-#[derive(Insertable, Queryable, AsChangeset, Debug, Clone, Serialize, Deserialize)]
+#[derive(Insertable, Queryable, AsChangeset, Debug, Clone, Serialize, Deserialize, Validate)]
 #[table_name="variants"]
 pub struct FormVariant {
     pub id: Option<i32>,
+    #[validate(custom = "not_blank")]
     pub name: String
 }
 
-#[derive(Insertable, Debug, AsChangeset, Serialize, Deserialize)]
+/// A variant value of a product, updated when `id` is given and added
+/// otherwise. `product_id` must be the product being updated.
+#[derive(Insertable, Debug, AsChangeset, Serialize, Deserialize, Validate)]
 #[table_name="products_variants"]
 pub struct FormProductVariant {
     pub id: Option<i32>,
@@ -79,14 +142,829 @@ pub struct FormProductVariant {
     pub value: Option<String>
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Validate)]
 pub struct FormProductVariantComplete {
+    #[validate]
     pub variant: Option<FormVariant>,
+    #[validate]
     pub product_variant: FormProductVariant,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Validate)]
+#[validate(schema(function = "unique_product_variants"))]
 pub struct FormProduct {
+    #[validate]
     pub product: NewProduct,
+    #[validate]
     pub variants: Vec<FormProductVariantComplete>
-}
\ No newline at end of file
+}
+
+#[derive(Identifiable, Queryable, Debug, Serialize, Deserialize)]
+#[table_name = "synonym_groups"]
+pub struct SynonymGroup {
+    pub id: i32,
+}
+
+#[derive(Identifiable, Queryable, Associations, Debug, Serialize, Deserialize)]
+#[belongs_to(SynonymGroup)]
+#[table_name = "synonyms"]
+pub struct Synonym {
+    pub id: i32,
+    pub synonym_group_id: i32,
+    pub term: String,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "synonyms"]
+pub struct NewSynonym<'a> {
+    pub synonym_group_id: i32,
+    pub term: &'a str,
+}
+
+/// A group of interchangeable search terms, as exposed by the API.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct SynonymGroupTerms {
+    pub id: i32,
+    pub terms: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FormSynonymGroup {
+    pub terms: Vec<String>,
+}
+
+/// The price of a product in a currency other than the one of its cost.
+#[derive(Identifiable, Queryable, Associations, PartialEq, Debug, Serialize, Deserialize)]
+#[belongs_to(Product)]
+#[table_name = "product_prices"]
+pub struct ProductPrice {
+    pub id: i32,
+    pub product_id: i32,
+    pub currency: String,
+    pub amount: Money,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct FormProductPrice {
+    #[validate(custom = "non_negative")]
+    pub amount: Money,
+}
+
+#[derive(Identifiable, Queryable, PartialEq, Debug, Serialize, Deserialize)]
+#[table_name = "exchange_rates"]
+pub struct ExchangeRate {
+    pub id: i32,
+    pub base_currency: String,
+    pub quote_currency: String,
+    pub rate: Rate,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct FormExchangeRate {
+    #[validate(custom = "positive_rate")]
+    pub rate: Rate,
+}
+
+/// A temporary price of a product, in the currency of its cost, applying from
+/// `starts_at` until `ends_at` excluded. Timestamps are UTC.
+#[derive(Identifiable, Queryable, Associations, PartialEq, Debug, Serialize, Deserialize)]
+#[belongs_to(Product)]
+#[table_name = "product_sales"]
+pub struct ProductSale {
+    pub id: i32,
+    pub product_id: i32,
+    pub cost: Money,
+    pub starts_at: NaiveDateTime,
+    pub ends_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[validate(schema(function = "sale_period"))]
+pub struct FormProductSale {
+    #[validate(custom = "non_negative")]
+    pub cost: Money,
+    pub starts_at: NaiveDateTime,
+    pub ends_at: NaiveDateTime,
+}
+
+/// A cost a product had from `changed_at` (UTC) until its next change in the
+/// same currency.
+#[derive(Identifiable, Queryable, Associations, PartialEq, Debug, Serialize, Deserialize)]
+#[belongs_to(Product)]
+#[table_name = "product_price_changes"]
+pub struct ProductPriceChange {
+    pub id: i32,
+    pub product_id: i32,
+    /// In `currency`, an explicit price when it isn't the currency of the
+    /// product. `None` from when that explicit price was removed.
+    pub cost: Option<Money>,
+    pub currency: String,
+    pub changed_at: NaiveDateTime,
+}
+
+/// A concrete combination of variant values of a product, with its own stock.
+#[derive(Identifiable, Queryable, Associations, PartialEq, Debug, Serialize, Deserialize)]
+#[belongs_to(Product)]
+#[table_name = "skus"]
+pub struct Sku {
+    pub id: i32,
+    pub product_id: i32,
+    pub code: String,
+    /// Replaces the cost of the product, in the same currency.
+    pub price: Option<Money>,
+    /// Balance of the last inventory movement of the SKU.
+    pub stock: i32,
+    /// Whether the SKU can still be sold once its stock runs out.
+    pub allow_backorder: bool,
+}
+
+#[derive(Identifiable, Queryable, Insertable, Associations, PartialEq, Debug)]
+#[belongs_to(Sku)]
+#[primary_key(sku_id, product_variant_id)]
+#[table_name = "sku_values"]
+pub struct SkuValue {
+    pub sku_id: i32,
+    pub product_variant_id: i32,
+}
+
+/// Payload creating or replacing a SKU. `product_variant_ids` are the
+/// `products_variants` rows making up the combination, at most one per
+/// variant.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct FormSku {
+    #[validate(custom = "not_blank")]
+    pub code: String,
+    /// Initial stock of a new SKU, recorded as a correction at the central
+    /// warehouse. Once created, its stock only changes through movements at
+    /// a location, `/skus/{id}/inventory`: a replaced SKU must leave it out
+    /// or give its current stock.
+    #[serde(default)]
+    #[validate(range(min = 0))]
+    pub stock: Option<i32>,
+    #[serde(default)]
+    #[validate(custom = "non_negative")]
+    pub price: Option<Money>,
+    #[serde(default)]
+    pub allow_backorder: bool,
+    pub product_variant_ids: Vec<i32>,
+}
+
+/// Options to generate one SKU per combination of the variant values of a
+/// product, which all get the same stock and price.
+///
+/// Each entry of `exclude` maps variant names to values and skips every
+/// combination having all of these values, e.g. `{"color": "red"}` skips
+/// every red SKU. Codes are `code_prefix` followed by the values, as in
+/// `BOOT-42-BLACK`.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct SkuMatrix {
+    #[validate(custom = "not_blank")]
+    pub code_prefix: String,
+    #[serde(default)]
+    #[validate(range(min = 0))]
+    pub stock: i32,
+    #[serde(default)]
+    #[validate(custom = "non_negative")]
+    pub price: Option<Money>,
+    #[serde(default)]
+    pub exclude: Vec<BTreeMap<String, String>>,
+}
+
+/// Why the stock of a SKU changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "snake_case")]
+#[sql_type = "Text"]
+pub enum MovementReason {
+    Receipt,
+    Sale,
+    Return,
+    Damage,
+    Correction,
+    /// One side of a [`Transfer`].
+    Transfer,
+}
+
+impl MovementReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MovementReason::Receipt => "receipt",
+            MovementReason::Sale => "sale",
+            MovementReason::Return => "return",
+            MovementReason::Damage => "damage",
+            MovementReason::Correction => "correction",
+            MovementReason::Transfer => "transfer",
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for MovementReason {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Sqlite>) -> serialize::Result {
+        <str as ToSql<Text, Sqlite>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Sqlite> for MovementReason {
+    fn from_sql(bytes: Option<&<Sqlite as diesel::backend::Backend>::RawValue>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Sqlite>>::from_sql(bytes)?.as_str() {
+            "receipt" => Ok(MovementReason::Receipt),
+            "sale" => Ok(MovementReason::Sale),
+            "return" => Ok(MovementReason::Return),
+            "damage" => Ok(MovementReason::Damage),
+            "correction" => Ok(MovementReason::Correction),
+            "transfer" => Ok(MovementReason::Transfer),
+            other => Err(format!("unknown movement reason '{}'", other).into()),
+        }
+    }
+}
+
+/// One change to the stock of a SKU at a location, leaving `balance` on hand
+/// there.
+#[derive(Identifiable, Queryable, Associations, PartialEq, Debug, Serialize, Deserialize)]
+#[belongs_to(Sku)]
+#[table_name = "inventory_movements"]
+pub struct InventoryMovement {
+    pub id: i32,
+    pub sku_id: i32,
+    pub location_id: i32,
+    pub transfer_id: Option<i32>,
+    pub reason: MovementReason,
+    pub quantity: i32,
+    pub balance: i32,
+    pub note: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+/// Payload posting a movement to the inventory ledger. `quantity` is signed:
+/// receipts and returns add stock, sales and damages remove it, corrections
+/// go either way. Transfers have their own payload, [`FormTransfer`].
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[validate(schema(function = "adjustment_quantity"))]
+pub struct FormInventoryAdjustment {
+    #[serde(default = "default_location_id")]
+    pub location_id: i32,
+    pub reason: MovementReason,
+    pub quantity: i32,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+/// Location of the movements that don't name one, the central warehouse.
+pub const DEFAULT_LOCATION_ID: i32 = 1;
+
+fn default_location_id() -> i32 {
+    DEFAULT_LOCATION_ID
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "snake_case")]
+#[sql_type = "Text"]
+pub enum LocationKind {
+    Warehouse,
+    Store,
+}
+
+impl LocationKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LocationKind::Warehouse => "warehouse",
+            LocationKind::Store => "store",
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for LocationKind {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Sqlite>) -> serialize::Result {
+        <str as ToSql<Text, Sqlite>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Sqlite> for LocationKind {
+    fn from_sql(bytes: Option<&<Sqlite as diesel::backend::Backend>::RawValue>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Sqlite>>::from_sql(bytes)?.as_str() {
+            "warehouse" => Ok(LocationKind::Warehouse),
+            "store" => Ok(LocationKind::Store),
+            other => Err(format!("unknown location kind '{}'", other).into()),
+        }
+    }
+}
+
+/// A place holding stock.
+#[derive(Identifiable, Queryable, PartialEq, Debug, Serialize, Deserialize)]
+#[table_name = "locations"]
+pub struct Location {
+    pub id: i32,
+    pub name: String,
+    pub kind: LocationKind,
+}
+
+#[derive(Insertable, AsChangeset, Debug, Clone, Serialize, Deserialize, Validate)]
+#[table_name = "locations"]
+pub struct FormLocation {
+    #[validate(custom = "not_blank")]
+    pub name: String,
+    pub kind: LocationKind,
+}
+
+/// Stock moved from one location to another.
+#[derive(Identifiable, Queryable, Associations, PartialEq, Debug, Serialize, Deserialize)]
+#[belongs_to(Sku)]
+#[table_name = "transfers"]
+pub struct Transfer {
+    pub id: i32,
+    pub sku_id: i32,
+    pub from_location_id: i32,
+    pub to_location_id: i32,
+    pub quantity: i32,
+    pub note: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[validate(schema(function = "distinct_locations"))]
+pub struct FormTransfer {
+    pub from_location_id: i32,
+    pub to_location_id: i32,
+    #[validate(range(min = 1))]
+    pub quantity: i32,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+/// A cart as kept in the session of a client. It holds no prices, they are
+/// looked up again every time the cart is shown.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cart {
+    pub next_line_id: u32,
+    pub lines: Vec<CartLine>,
+    /// Code entered by the customer, checked again whenever the cart is
+    /// priced.
+    #[serde(default)]
+    pub promo_code: Option<String>,
+}
+
+/// A quantity of a product with the variant values chosen for it, sorted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CartLine {
+    pub id: u32,
+    pub product_id: i32,
+    pub product_variant_ids: Vec<i32>,
+    pub quantity: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct FormCartLine {
+    pub product_id: i32,
+    #[serde(default)]
+    pub product_variant_ids: Vec<i32>,
+    #[validate(range(min = 1))]
+    pub quantity: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct FormCartQuantity {
+    #[validate(range(min = 1))]
+    pub quantity: i32,
+}
+
+/// An order placed at checkout.
+#[derive(Identifiable, Queryable, PartialEq, Debug, Serialize, Deserialize)]
+#[table_name = "orders"]
+pub struct Order {
+    pub id: i32,
+    pub currency: String,
+    pub item_count: i32,
+    pub total: Money,
+    pub created_at: NaiveDateTime,
+    pub status: OrderStatus,
+    /// `None` once the promo code is deleted, `promo_code` keeps it.
+    pub promo_code_id: Option<i32>,
+    pub promo_code: Option<String>,
+    /// Taken off the sum of the lines to get to `total`.
+    pub discount: Money,
+    pub customer_email: Option<String>,
+}
+
+/// A line of an order, with the name and unit price of the product when the
+/// order was placed.
+#[derive(Identifiable, Queryable, Associations, PartialEq, Debug, Serialize, Deserialize)]
+#[belongs_to(Order)]
+#[table_name = "order_lines"]
+pub struct OrderLine {
+    pub id: i32,
+    pub order_id: i32,
+    /// `None` once the product is deleted.
+    pub product_id: Option<i32>,
+    pub sku_id: Option<i32>,
+    pub product_name: String,
+    pub quantity: i32,
+    pub unit_price: Money,
+    pub total: Money,
+    /// Share of the discount of the order taken off `total`.
+    pub discount: Money,
+}
+
+/// A variant value of an order line, by name rather than by id.
+#[derive(Identifiable, Queryable, Insertable, Associations, PartialEq, Debug)]
+#[belongs_to(OrderLine)]
+#[primary_key(order_line_id, position)]
+#[table_name = "order_line_options"]
+pub struct OrderLineOption {
+    pub order_line_id: i32,
+    pub position: i32,
+    pub name: String,
+    pub value: Option<String>,
+}
+
+/// A change of status of an order, `from_status` being `None` when it was
+/// placed.
+#[derive(Identifiable, Queryable, Associations, PartialEq, Debug, Serialize, Deserialize)]
+#[belongs_to(Order)]
+#[table_name = "order_events"]
+pub struct OrderEvent {
+    pub id: i32,
+    pub order_id: i32,
+    pub from_status: Option<OrderStatus>,
+    pub to_status: OrderStatus,
+    pub note: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FormOrderTransition {
+    pub transition: OrderTransition,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+/// Where a payment stands with its gateway.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "snake_case")]
+#[sql_type = "Text"]
+pub enum PaymentStatus {
+    /// Waiting on the customer, e.g. for a 3-D Secure challenge. The gateway
+    /// reports the outcome through a webhook.
+    RequiresAction,
+    Authorized,
+    /// Being captured by the gateway. The order is marked paid once it is.
+    Capturing,
+    Captured,
+    Declined,
+    /// Authorized for an order that couldn't be paid anymore, e.g. cancelled
+    /// meanwhile. The authorization was released without collecting it.
+    Voided,
+    /// Refunded in full, partial refunds leave the payment captured.
+    Refunded,
+}
+
+impl PaymentStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PaymentStatus::RequiresAction => "requires_action",
+            PaymentStatus::Authorized => "authorized",
+            PaymentStatus::Capturing => "capturing",
+            PaymentStatus::Captured => "captured",
+            PaymentStatus::Declined => "declined",
+            PaymentStatus::Voided => "voided",
+            PaymentStatus::Refunded => "refunded",
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for PaymentStatus {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Sqlite>) -> serialize::Result {
+        <str as ToSql<Text, Sqlite>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Sqlite> for PaymentStatus {
+    fn from_sql(bytes: Option<&<Sqlite as diesel::backend::Backend>::RawValue>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Sqlite>>::from_sql(bytes)?.as_str() {
+            "requires_action" => Ok(PaymentStatus::RequiresAction),
+            "authorized" => Ok(PaymentStatus::Authorized),
+            "capturing" => Ok(PaymentStatus::Capturing),
+            "captured" => Ok(PaymentStatus::Captured),
+            "declined" => Ok(PaymentStatus::Declined),
+            "voided" => Ok(PaymentStatus::Voided),
+            "refunded" => Ok(PaymentStatus::Refunded),
+            other => Err(format!("unknown payment status '{}'", other).into()),
+        }
+    }
+}
+
+/// A payment of the total of an order through the gateway named `gateway`.
+#[derive(Identifiable, Queryable, Associations, PartialEq, Debug, Serialize, Deserialize)]
+#[belongs_to(Order)]
+#[table_name = "payments"]
+pub struct Payment {
+    pub id: i32,
+    pub order_id: i32,
+    pub gateway: String,
+    /// Id of the payment at the gateway.
+    pub reference: String,
+    pub status: PaymentStatus,
+    pub amount: Money,
+    /// Part of `amount` refunded so far, refunds still pending included.
+    pub refunded: Money,
+    pub currency: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// Where a refund stands with the gateway of its payment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "snake_case")]
+#[sql_type = "Text"]
+pub enum RefundStatus {
+    /// Recorded, the gateway wasn't done with it yet.
+    Pending,
+    Succeeded,
+    /// Turned down by the gateway, its amount can be refunded again.
+    Failed,
+}
+
+impl RefundStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RefundStatus::Pending => "pending",
+            RefundStatus::Succeeded => "succeeded",
+            RefundStatus::Failed => "failed",
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for RefundStatus {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Sqlite>) -> serialize::Result {
+        <str as ToSql<Text, Sqlite>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Sqlite> for RefundStatus {
+    fn from_sql(bytes: Option<&<Sqlite as diesel::backend::Backend>::RawValue>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Sqlite>>::from_sql(bytes)?.as_str() {
+            "pending" => Ok(RefundStatus::Pending),
+            "succeeded" => Ok(RefundStatus::Succeeded),
+            "failed" => Ok(RefundStatus::Failed),
+            other => Err(format!("unknown refund status '{}'", other).into()),
+        }
+    }
+}
+
+/// A refund of `amount` of a payment, in its currency.
+#[derive(Identifiable, Queryable, Associations, PartialEq, Debug, Serialize, Deserialize)]
+#[belongs_to(Payment)]
+#[table_name = "refunds"]
+pub struct Refund {
+    pub id: i32,
+    pub payment_id: i32,
+    pub amount: Money,
+    pub status: RefundStatus,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct FormPayment {
+    /// Payment method as the gateway knows it, e.g. a card token.
+    #[validate(custom = "not_blank")]
+    pub source: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate)]
+pub struct FormRefund {
+    /// Defaults to whatever wasn't refunded yet.
+    #[serde(default)]
+    #[validate(custom = "positive_amount")]
+    pub amount: Option<Money>,
+}
+
+/// Where a return request stands: requested, then approved or rejected. The
+/// items of an approved return are received back, then refunded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "snake_case")]
+#[sql_type = "Text"]
+pub enum ReturnStatus {
+    Requested,
+    Approved,
+    Rejected,
+    Received,
+    Refunded,
+}
+
+impl ReturnStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReturnStatus::Requested => "requested",
+            ReturnStatus::Approved => "approved",
+            ReturnStatus::Rejected => "rejected",
+            ReturnStatus::Received => "received",
+            ReturnStatus::Refunded => "refunded",
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for ReturnStatus {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Sqlite>) -> serialize::Result {
+        <str as ToSql<Text, Sqlite>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Sqlite> for ReturnStatus {
+    fn from_sql(bytes: Option<&<Sqlite as diesel::backend::Backend>::RawValue>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Sqlite>>::from_sql(bytes)?.as_str() {
+            "requested" => Ok(ReturnStatus::Requested),
+            "approved" => Ok(ReturnStatus::Approved),
+            "rejected" => Ok(ReturnStatus::Rejected),
+            "received" => Ok(ReturnStatus::Received),
+            "refunded" => Ok(ReturnStatus::Refunded),
+            other => Err(format!("unknown return status '{}'", other).into()),
+        }
+    }
+}
+
+/// Why an item is sent back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "snake_case")]
+#[sql_type = "Text"]
+pub enum ReturnReason {
+    TooSmall,
+    TooLarge,
+    /// Defective items aren't put back into stock when received.
+    Defective,
+}
+
+impl ReturnReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReturnReason::TooSmall => "too_small",
+            ReturnReason::TooLarge => "too_large",
+            ReturnReason::Defective => "defective",
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for ReturnReason {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Sqlite>) -> serialize::Result {
+        <str as ToSql<Text, Sqlite>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Sqlite> for ReturnReason {
+    fn from_sql(bytes: Option<&<Sqlite as diesel::backend::Backend>::RawValue>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Sqlite>>::from_sql(bytes)?.as_str() {
+            "too_small" => Ok(ReturnReason::TooSmall),
+            "too_large" => Ok(ReturnReason::TooLarge),
+            "defective" => Ok(ReturnReason::Defective),
+            other => Err(format!("unknown return reason '{}'", other).into()),
+        }
+    }
+}
+
+/// A request to return some of the items of an order, `refund` being their
+/// price, discount taken off, when the order was placed.
+#[derive(Identifiable, Queryable, Associations, PartialEq, Debug)]
+#[belongs_to(Order)]
+#[table_name = "return_requests"]
+pub struct ReturnRequest {
+    pub id: i32,
+    pub order_id: i32,
+    pub status: ReturnStatus,
+    pub refund: Money,
+    pub note: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Identifiable, Queryable, Associations, PartialEq, Debug)]
+#[belongs_to(ReturnRequest)]
+#[belongs_to(OrderLine)]
+#[table_name = "return_lines"]
+pub struct ReturnLine {
+    pub id: i32,
+    pub return_request_id: i32,
+    pub order_line_id: i32,
+    pub quantity: i32,
+    pub reason: ReturnReason,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct FormReturn {
+    #[validate(length(min = 1, message = "must not be empty"))]
+    #[validate]
+    pub lines: Vec<FormReturnLine>,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct FormReturnLine {
+    pub order_line_id: i32,
+    #[validate(range(min = 1))]
+    pub quantity: i32,
+    pub reason: ReturnReason,
+}
+
+/// Where the items of a return are put back into stock, the central
+/// warehouse by default.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FormReturnReceipt {
+    #[serde(default = "default_location_id")]
+    pub location_id: i32,
+}
+
+/// A discount code, taking either `percent_off` or `amount_off` off the cart
+/// lines it applies to. Amounts are in `currency`, converted into the
+/// currency of the cart.
+#[derive(Identifiable, Queryable, PartialEq, Debug, Clone)]
+#[table_name = "promo_codes"]
+pub struct PromoCode {
+    pub id: i32,
+    pub code: String,
+    pub percent_off: Option<i32>,
+    pub amount_off: Option<Money>,
+    pub currency: String,
+    pub min_cart_total: Option<Money>,
+    pub usage_limit: Option<i32>,
+    /// Advisory until customers have accounts, see
+    /// [`check_customer`](crate::db::dal::promotions::check_customer).
+    pub per_customer_limit: Option<i32>,
+    pub starts_at: Option<NaiveDateTime>,
+    pub ends_at: Option<NaiveDateTime>,
+    pub active: bool,
+}
+
+/// A condition on a variant value of the lines a promo code applies to.
+#[derive(Identifiable, Queryable, Associations, PartialEq, Debug, Clone)]
+#[belongs_to(PromoCode)]
+#[table_name = "promo_code_variant_rules"]
+pub struct PromoCodeVariantRule {
+    pub id: i32,
+    pub promo_code_id: i32,
+    pub variant_name: String,
+    pub value: Option<String>,
+    pub min_value: Option<f64>,
+    pub max_value: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[validate(schema(function = "promo_discount"))]
+pub struct FormPromoCode {
+    #[validate(custom = "not_blank")]
+    pub code: String,
+    #[serde(default)]
+    #[validate(range(min = 1, max = 100))]
+    pub percent_off: Option<i32>,
+    #[serde(default)]
+    #[validate(custom = "positive_amount")]
+    pub amount_off: Option<Money>,
+    #[serde(default = "default_currency")]
+    #[validate(custom = "currency_code")]
+    pub currency: String,
+    #[serde(default)]
+    #[validate(custom = "non_negative")]
+    pub min_cart_total: Option<Money>,
+    #[serde(default)]
+    #[validate(range(min = 1))]
+    pub usage_limit: Option<i32>,
+    /// Orders a customer may place with the code, customers being told apart
+    /// by the email they give at checkout only.
+    #[serde(default)]
+    #[validate(range(min = 1))]
+    pub per_customer_limit: Option<i32>,
+    #[serde(default)]
+    pub starts_at: Option<NaiveDateTime>,
+    #[serde(default)]
+    pub ends_at: Option<NaiveDateTime>,
+    #[serde(default = "default_active")]
+    pub active: bool,
+    /// Products the code applies to, every product when empty.
+    #[serde(default)]
+    pub product_ids: Vec<i32>,
+    #[serde(default)]
+    #[validate]
+    pub variant_rules: Vec<FormVariantRule>,
+}
+
+fn default_active() -> bool {
+    true
+}
+
+/// Matches lines whose value for `variant` is `value`, or a number between
+/// `min` and `max` included, e.g. sizes from 46 up.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Validate)]
+#[validate(schema(function = "variant_rule_condition"))]
+pub struct FormVariantRule {
+    #[validate(custom = "not_blank")]
+    pub variant: String,
+    #[serde(default)]
+    pub value: Option<String>,
+    #[serde(default)]
+    pub min: Option<f64>,
+    #[serde(default)]
+    pub max: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct FormCartPromoCode {
+    #[validate(custom = "not_blank")]
+    pub code: String,
+}
@@ -0,0 +1,245 @@
+use crate::errors::ApiError;
+use diesel::deserialize::{self, FromSql};
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::BigInt;
+use diesel::sqlite::Sqlite;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+/// Currency of the products created without one.
+pub const DEFAULT_CURRENCY: &str = "EUR";
+
+/// Number of decimal digits of the minor unit, the same for every currency
+/// we sell in.
+pub const MINOR_DIGITS: usize = 2;
+
+/// An exact amount of money, counted in minor units (cents) of its currency.
+///
+/// It goes over the wire as a decimal string such as `"13.23"` rather than a
+/// JSON number, so that clients never parse it into a float. JSON numbers are
+/// still read, for clients that sent costs as numbers before: a number is
+/// taken from the shortest decimal that reads back as the same float, which
+/// is the decimal the client wrote, so `13.23` is 13.23 and never 13.229999.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, AsExpression, FromSqlRow)]
+#[sql_type = "BigInt"]
+pub struct Money(i64);
+
+impl Money {
+    pub const fn from_minor(minor: i64) -> Self {
+        Money(minor)
+    }
+
+    pub const fn minor(self) -> i64 {
+        self.0
+    }
+
+    pub fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+
+    pub fn is_positive(self) -> bool {
+        self.0 > 0
+    }
+
+    /// `None` when the sum doesn't fit.
+    pub fn checked_add(self, other: Money) -> Option<Money> {
+        self.0.checked_add(other.0).map(Money)
+    }
+
+    /// `None` when the difference doesn't fit.
+    pub fn checked_sub(self, other: Money) -> Option<Money> {
+        self.0.checked_sub(other.0).map(Money)
+    }
+
+    /// This amount `quantity` times, `None` when the result doesn't fit.
+    pub fn checked_mul(self, quantity: i64) -> Option<Money> {
+        self.0.checked_mul(quantity).map(Money)
+    }
+
+    /// Converts this amount into another currency at `rate`.
+    ///
+    /// The exact product is rounded to the nearest minor unit, with halves
+    /// rounded away from zero: 10.05 EUR at 0.5 GBP/EUR is 5.03 GBP. `None`
+    /// when the result doesn't fit.
+    pub fn convert(self, rate: Rate) -> Option<Money> {
+        let scale = i128::from(10i64.pow(RATE_DIGITS as u32));
+        let product = i128::from(self.0) * i128::from(rate.0);
+        let rounded = (product.abs() + scale / 2) / scale * product.signum();
+        i64::try_from(rounded).ok().map(Money)
+    }
+
+    /// This amount times `numerator / denominator`, rounded as
+    /// [`Money::convert`] does. `None` when `denominator` is zero or the
+    /// result doesn't fit.
+    pub fn checked_scale(self, numerator: Money, denominator: Money) -> Option<Money> {
+        if denominator.0 == 0 {
+            return None;
+        }
+        let product = i128::from(self.0) * i128::from(numerator.0);
+        let denominator = i128::from(denominator.0);
+        let rounded = (product.abs() * 2 + denominator.abs()) / (denominator.abs() * 2);
+        i64::try_from(rounded * product.signum() * denominator.signum()).ok().map(Money)
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_decimal(f, self.0, MINOR_DIGITS)
+    }
+}
+
+impl FromStr for Money {
+    type Err = ApiError;
+
+    /// Parses a decimal amount with at most [`MINOR_DIGITS`] decimals, such
+    /// as `13`, `13.2` or `-13.23`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_decimal(s, MINOR_DIGITS)
+            .map(Money)
+            .ok_or_else(|| ApiError::Validation(format!("'{}' is not a valid amount of money", s)))
+    }
+}
+
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(MoneyVisitor)
+    }
+}
+
+struct MoneyVisitor;
+
+impl<'de> de::Visitor<'de> for MoneyVisitor {
+    type Value = Money;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("an amount of money such as \"13.23\"")
+    }
+
+    fn visit_str<E: de::Error>(self, amount: &str) -> Result<Money, E> {
+        amount.parse().map_err(E::custom)
+    }
+
+    fn visit_i64<E: de::Error>(self, amount: i64) -> Result<Money, E> {
+        self.visit_str(&amount.to_string())
+    }
+
+    fn visit_u64<E: de::Error>(self, amount: u64) -> Result<Money, E> {
+        self.visit_str(&amount.to_string())
+    }
+
+    fn visit_f64<E: de::Error>(self, amount: f64) -> Result<Money, E> {
+        // Displayed without an exponent, as the shortest decimal that parses
+        // back to `amount`
+        self.visit_str(&amount.to_string())
+    }
+}
+
+impl ToSql<BigInt, Sqlite> for Money {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Sqlite>) -> serialize::Result {
+        <i64 as ToSql<BigInt, Sqlite>>::to_sql(&self.0, out)
+    }
+}
+
+impl FromSql<BigInt, Sqlite> for Money {
+    fn from_sql(bytes: Option<&<Sqlite as diesel::backend::Backend>::RawValue>) -> deserialize::Result<Self> {
+        <i64 as FromSql<BigInt, Sqlite>>::from_sql(bytes).map(Money)
+    }
+}
+
+/// Number of decimal digits kept for exchange rates.
+pub const RATE_DIGITS: usize = 6;
+
+/// An exchange rate, the amount of the quote currency one unit of the base
+/// currency buys, kept exactly as a fixed-point number with [`RATE_DIGITS`]
+/// decimals.
+///
+/// Like [`Money`] it goes over the wire as a decimal string, e.g. `"0.8563"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, AsExpression, FromSqlRow)]
+#[sql_type = "BigInt"]
+pub struct Rate(i64);
+
+impl Rate {
+    pub fn is_positive(self) -> bool {
+        self.0 > 0
+    }
+}
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_decimal(f, self.0, RATE_DIGITS)
+    }
+}
+
+impl FromStr for Rate {
+    type Err = ApiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_decimal(s, RATE_DIGITS)
+            .map(Rate)
+            .ok_or_else(|| ApiError::Validation(format!("'{}' is not a valid exchange rate", s)))
+    }
+}
+
+impl Serialize for Rate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Rate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let rate = String::deserialize(deserializer)?;
+        rate.parse().map_err(de::Error::custom)
+    }
+}
+
+impl ToSql<BigInt, Sqlite> for Rate {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Sqlite>) -> serialize::Result {
+        <i64 as ToSql<BigInt, Sqlite>>::to_sql(&self.0, out)
+    }
+}
+
+impl FromSql<BigInt, Sqlite> for Rate {
+    fn from_sql(bytes: Option<&<Sqlite as diesel::backend::Backend>::RawValue>) -> deserialize::Result<Self> {
+        <i64 as FromSql<BigInt, Sqlite>>::from_sql(bytes).map(Rate)
+    }
+}
+
+/// Writes `value`, a number of `10^-digits` units, as a decimal.
+fn fmt_decimal(f: &mut fmt::Formatter<'_>, value: i64, digits: usize) -> fmt::Result {
+    let sign = if value < 0 { "-" } else { "" };
+    let value = value.unsigned_abs();
+    let scale = 10u64.pow(digits as u32);
+    write!(f, "{}{}.{:0width$}", sign, value / scale, value % scale, width = digits)
+}
+
+/// Parses a decimal with at most `digits` decimals into a number of
+/// `10^-digits` units, without ever going through a float.
+fn parse_decimal(s: &str, digits: usize) -> Option<i64> {
+    let (negative, unsigned) = match s.strip_prefix('-') {
+        Some(unsigned) => (true, unsigned),
+        None => (false, s),
+    };
+    let (whole, fraction) = match unsigned.split_once('.') {
+        Some((_, "")) => return None,
+        Some(parts) => parts,
+        None => (unsigned, ""),
+    };
+    let is_number = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+    if whole.is_empty() || !is_number(whole) || !is_number(fraction) || fraction.len() > digits {
+        return None;
+    }
+
+    let whole = whole.parse::<i64>().ok()?;
+    let fraction = format!("{:0<width$}", fraction, width = digits).parse::<i64>().ok()?;
+    let value = whole.checked_mul(10i64.pow(digits as u32))?.checked_add(fraction)?;
+    Some(if negative { -value } else { value })
+}
@@ -0,0 +1,120 @@
+use crate::errors::ApiError;
+use diesel::deserialize::{self, FromSql};
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use diesel::sqlite::Sqlite;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::Write;
+
+/// Where an order stands. Orders go pending → paid → packed → shipped →
+/// delivered, unless they are cancelled before shipping or refunded once
+/// paid. Cancelled and refunded orders stay so.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "snake_case")]
+#[sql_type = "Text"]
+pub enum OrderStatus {
+    Pending,
+    Paid,
+    Packed,
+    Shipped,
+    Delivered,
+    Cancelled,
+    Refunded,
+}
+
+/// What can happen to an order, each leading to one status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderTransition {
+    Pay,
+    Pack,
+    Ship,
+    Deliver,
+    Cancel,
+    Refund,
+}
+
+impl OrderStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OrderStatus::Pending => "pending",
+            OrderStatus::Paid => "paid",
+            OrderStatus::Packed => "packed",
+            OrderStatus::Shipped => "shipped",
+            OrderStatus::Delivered => "delivered",
+            OrderStatus::Cancelled => "cancelled",
+            OrderStatus::Refunded => "refunded",
+        }
+    }
+
+    /// The status `transition` leads to from this one, `None` when it isn't
+    /// allowed.
+    pub fn apply(self, transition: OrderTransition) -> Option<OrderStatus> {
+        use OrderStatus::*;
+        use OrderTransition::*;
+        match (self, transition) {
+            (Pending, Pay) => Some(Paid),
+            (Paid, Pack) => Some(Packed),
+            (Packed, Ship) => Some(Shipped),
+            (Shipped, Deliver) => Some(Delivered),
+            (Pending | Paid | Packed, Cancel) => Some(Cancelled),
+            (Paid | Packed | Shipped | Delivered, Refund) => Some(Refunded),
+            _ => None,
+        }
+    }
+
+    /// Whether the items of an order with this status left the warehouse.
+    pub fn is_shipped(self) -> bool {
+        matches!(self, OrderStatus::Shipped | OrderStatus::Delivered)
+    }
+
+    /// Same as [`OrderStatus::apply`], with a conflict naming both ends when
+    /// the transition isn't allowed.
+    pub fn try_apply(self, order_id: i32, transition: OrderTransition) -> Result<OrderStatus, ApiError> {
+        self.apply(transition).ok_or_else(|| {
+            ApiError::Conflict(format!("order {} is {}, it can't be {}", order_id, self, transition))
+        })
+    }
+}
+
+impl fmt::Display for OrderStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Display for OrderTransition {
+    /// The transition as a past participle, e.g. `shipped`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OrderTransition::Pay => "paid",
+            OrderTransition::Pack => "packed",
+            OrderTransition::Ship => "shipped",
+            OrderTransition::Deliver => "delivered",
+            OrderTransition::Cancel => "cancelled",
+            OrderTransition::Refund => "refunded",
+        })
+    }
+}
+
+impl ToSql<Text, Sqlite> for OrderStatus {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Sqlite>) -> serialize::Result {
+        <str as ToSql<Text, Sqlite>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Sqlite> for OrderStatus {
+    fn from_sql(bytes: Option<&<Sqlite as diesel::backend::Backend>::RawValue>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Sqlite>>::from_sql(bytes)?.as_str() {
+            "pending" => Ok(OrderStatus::Pending),
+            "paid" => Ok(OrderStatus::Paid),
+            "packed" => Ok(OrderStatus::Packed),
+            "shipped" => Ok(OrderStatus::Shipped),
+            "delivered" => Ok(OrderStatus::Delivered),
+            "cancelled" => Ok(OrderStatus::Cancelled),
+            "refunded" => Ok(OrderStatus::Refunded),
+            other => Err(format!("unknown order status '{}'", other).into()),
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use super::money::Money;
+use crate::errors::ApiError;
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_LIMIT: u16 = 50;
+pub const MAX_LIMIT: u16 = 200;
+
+/// Paging options accepted by the listing functions.
+///
+/// `cursor` takes precedence over `offset`: when a cursor is given the page
+/// starts right after the row it points to.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PageParams {
+    pub limit: Option<u16>,
+    pub offset: Option<u32>,
+    pub cursor: Option<String>,
+}
+
+impl PageParams {
+    pub fn limit(&self) -> i64 {
+        self.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT).into()
+    }
+
+    pub fn offset(&self) -> i64 {
+        self.offset.unwrap_or(0).into()
+    }
+
+    pub fn cursor(&self) -> Result<Option<Cursor>, ApiError> {
+        self.cursor.as_deref().map(Cursor::decode).transpose()
+    }
+}
+
+/// One page of results along with the total number of matching rows.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+    pub total: i64,
+}
+
+/// Keyset position of the last row of a page, handed to clients as an
+/// opaque url-safe string.
+///
+/// `key` holds the value of the sort column when sorting by something other
+/// than the id, or the relevance rank for full-text searches.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cursor {
+    pub id: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<CursorKey>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CursorKey {
+    Name(String),
+    Cost(Money),
+    Rank(f64),
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).expect("cursor is always serializable");
+        base64::encode_config(json, base64::URL_SAFE_NO_PAD)
+    }
+
+    pub fn decode(encoded: &str) -> Result<Cursor, ApiError> {
+        base64::decode_config(encoded, base64::URL_SAFE_NO_PAD)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .ok_or_else(|| ApiError::Validation("invalid cursor".to_string()))
+    }
+}
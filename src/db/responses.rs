@@ -0,0 +1,390 @@
+use super::models::{
+    FormVariantRule, Order, OrderLine, OrderLineOption, Product, ProductVariant, PromoCode, PromoCodeVariantRule,
+    Refund, ReturnLine, ReturnReason, ReturnRequest, ReturnStatus, Sku, Variant,
+};
+use super::money::Money;
+use super::order_status::OrderStatus;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+/// A product as returned by the API, with its variant values grouped by
+/// variant.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProductResponse {
+    pub id: i32,
+    pub name: String,
+    pub cost: Money,
+    pub currency: String,
+    /// Original price to show struck through, if any. Defaults to `cost`
+    /// while the product is on sale.
+    pub compare_at_cost: Option<Money>,
+    /// Price to pay at the time of the request, taking sales into account.
+    pub effective_cost: Money,
+    /// Lowest price of the product over the 30 days before its current sale
+    /// started, or before the request when it isn't on sale. The running sale
+    /// doesn't count.
+    pub lowest_30_day_cost: Money,
+    pub active: bool,
+    pub variants: Vec<VariantValues>,
+    /// Stock of the product, only filled in on request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub availability: Option<Availability>,
+}
+
+/// Every value a product has for one variant, e.g. all of its sizes.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct VariantValues {
+    pub variant_id: i32,
+    pub name: String,
+    pub values: Vec<VariantValue>,
+}
+
+/// A value of a variant, identified by the id of its `products_variants` row.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct VariantValue {
+    pub id: i32,
+    pub value: Option<String>,
+}
+
+/// Units of a product ready to sell, over all of its SKUs. SKUs on backorder
+/// count as none rather than making up for the stock of others.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Availability {
+    pub stock: i32,
+    /// The same stock broken down by location, every location included.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locations: Option<Vec<LocationStock>>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct LocationStock {
+    pub location_id: i32,
+    pub name: String,
+    pub stock: i32,
+}
+
+/// Prices of a product that depend on the time of the request, as computed
+/// by the dal.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CurrentPrices {
+    /// Cost of the sale running right now, if any.
+    pub sale_cost: Option<Money>,
+    pub lowest_30_day_cost: Option<Money>,
+}
+
+impl ProductResponse {
+    /// Groups `variants` by variant, in the order each variant first appears.
+    pub fn new(product: Product, variants: Vec<(ProductVariant, Variant)>, prices: CurrentPrices) -> Self {
+        let mut grouped: Vec<VariantValues> = Vec::new();
+        for (product_variant, variant) in variants {
+            let value = VariantValue {
+                id: product_variant.id,
+                value: product_variant.value,
+            };
+            match grouped.iter_mut().find(|group| group.variant_id == variant.id) {
+                Some(group) => group.values.push(value),
+                None => grouped.push(VariantValues {
+                    variant_id: variant.id,
+                    name: variant.name,
+                    values: vec![value],
+                }),
+            }
+        }
+
+        let effective_cost = prices.sale_cost.unwrap_or(product.cost);
+        ProductResponse {
+            id: product.id,
+            name: product.name,
+            cost: product.cost,
+            currency: product.currency,
+            compare_at_cost: product.compare_at_cost.or(prices.sale_cost.map(|_| product.cost)),
+            effective_cost,
+            lowest_30_day_cost: prices.lowest_30_day_cost.unwrap_or(effective_cost),
+            active: product.active,
+            variants: grouped,
+            availability: None,
+        }
+    }
+}
+
+/// A SKU as returned by the API, with the variant values it is made of.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct SkuResponse {
+    pub id: i32,
+    pub product_id: i32,
+    pub code: String,
+    pub stock: i32,
+    pub allow_backorder: bool,
+    pub price: Option<Money>,
+    pub options: Vec<SkuOption>,
+}
+
+/// One variant value of a SKU, e.g. size 42.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SkuOption {
+    pub product_variant_id: i32,
+    pub variant_id: i32,
+    pub name: String,
+    pub value: Option<String>,
+}
+
+impl SkuResponse {
+    pub fn new(sku: Sku, options: Vec<(ProductVariant, Variant)>) -> Self {
+        SkuResponse {
+            id: sku.id,
+            product_id: sku.product_id,
+            code: sku.code,
+            stock: sku.stock,
+            allow_backorder: sku.allow_backorder,
+            price: sku.price,
+            options: options
+                .into_iter()
+                .map(|(product_variant, variant)| SkuOption {
+                    product_variant_id: product_variant.id,
+                    variant_id: variant.id,
+                    name: variant.name,
+                    value: product_variant.value,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A SKU the matrix generator would create, for previews.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct SkuPlan {
+    pub code: String,
+    pub options: Vec<SkuPlanOption>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct SkuPlanOption {
+    pub name: String,
+    pub value: Option<String>,
+}
+
+/// A cart priced at the time of the request.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct CartResponse {
+    pub currency: String,
+    pub lines: Vec<CartLineResponse>,
+    /// Number of units in the available lines.
+    pub item_count: i32,
+    /// Sum of the available lines.
+    pub subtotal: Money,
+    /// The promo code of the cart, as entered when it doesn't apply.
+    pub promo_code: Option<String>,
+    /// Why the promo code of the cart doesn't apply, if it doesn't.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub promo_rejection: Option<String>,
+    /// Taken off `subtotal` by the promo code.
+    pub discount: Money,
+    pub total: Money,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct CartLineResponse {
+    pub id: u32,
+    pub product_id: i32,
+    pub name: String,
+    /// SKU with exactly the chosen values, if there is one.
+    pub sku_id: Option<i32>,
+    pub options: Vec<SkuOption>,
+    pub quantity: i32,
+    /// Price of the SKU if it has its own, otherwise the effective cost of
+    /// the product.
+    pub unit_price: Money,
+    pub total: Money,
+    /// Share of the discount of the cart taken off `total`.
+    pub discount: Money,
+    /// False once the product is no longer for sale, the line then counts
+    /// for nothing.
+    pub available: bool,
+}
+
+/// An order as returned by the API, with its lines.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct OrderResponse {
+    pub id: i32,
+    pub status: OrderStatus,
+    pub currency: String,
+    pub item_count: i32,
+    pub promo_code: Option<String>,
+    pub discount: Money,
+    /// What was left to pay once `discount` was taken off the lines.
+    pub total: Money,
+    pub customer_email: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub lines: Vec<OrderLineResponse>,
+}
+
+/// An order moved on by a transition, with the refunds it led to that the
+/// gateway didn't make. Those are left for a refund of their payment to try
+/// again.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct OrderTransitionResponse {
+    #[serde(flatten)]
+    pub order: OrderResponse,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failed_refunds: Vec<Refund>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct OrderLineResponse {
+    pub id: i32,
+    pub product_id: Option<i32>,
+    pub sku_id: Option<i32>,
+    pub product_name: String,
+    pub options: Vec<OrderOption>,
+    pub quantity: i32,
+    pub unit_price: Money,
+    pub total: Money,
+    pub discount: Money,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct OrderOption {
+    pub name: String,
+    pub value: Option<String>,
+}
+
+impl OrderResponse {
+    pub fn new(order: Order, lines: Vec<(OrderLine, Vec<OrderLineOption>)>) -> Self {
+        OrderResponse {
+            id: order.id,
+            status: order.status,
+            currency: order.currency,
+            item_count: order.item_count,
+            promo_code: order.promo_code,
+            discount: order.discount,
+            total: order.total,
+            customer_email: order.customer_email,
+            created_at: order.created_at,
+            lines: lines
+                .into_iter()
+                .map(|(line, options)| OrderLineResponse {
+                    id: line.id,
+                    product_id: line.product_id,
+                    sku_id: line.sku_id,
+                    product_name: line.product_name,
+                    options: options
+                        .into_iter()
+                        .map(|option| OrderOption {
+                            name: option.name,
+                            value: option.value,
+                        })
+                        .collect(),
+                    quantity: line.quantity,
+                    unit_price: line.unit_price,
+                    total: line.total,
+                    discount: line.discount,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A return request as returned by the API, with the order lines it returns.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReturnResponse {
+    pub id: i32,
+    pub order_id: i32,
+    pub status: ReturnStatus,
+    pub currency: String,
+    /// Price of the returned items when the order was placed, discount taken
+    /// off. Settled once the return is refunded, as rounding depends on what
+    /// was refunded of the same order lines before.
+    pub refund: Money,
+    pub note: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub lines: Vec<ReturnLineResponse>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReturnLineResponse {
+    pub id: i32,
+    pub order_line_id: i32,
+    pub sku_id: Option<i32>,
+    pub product_name: String,
+    pub quantity: i32,
+    pub reason: ReturnReason,
+    pub unit_price: Money,
+}
+
+impl ReturnResponse {
+    pub fn new(request: ReturnRequest, currency: String, lines: Vec<(ReturnLine, OrderLine)>) -> Self {
+        ReturnResponse {
+            id: request.id,
+            order_id: request.order_id,
+            status: request.status,
+            currency,
+            refund: request.refund,
+            note: request.note,
+            created_at: request.created_at,
+            updated_at: request.updated_at,
+            lines: lines
+                .into_iter()
+                .map(|(line, order_line)| ReturnLineResponse {
+                    id: line.id,
+                    order_line_id: line.order_line_id,
+                    sku_id: order_line.sku_id,
+                    product_name: order_line.product_name,
+                    quantity: line.quantity,
+                    reason: line.reason,
+                    unit_price: order_line.unit_price,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A promo code as returned by the API, with what it applies to and how many
+/// orders used it.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct PromoCodeResponse {
+    pub id: i32,
+    pub code: String,
+    pub percent_off: Option<i32>,
+    pub amount_off: Option<Money>,
+    pub currency: String,
+    pub min_cart_total: Option<Money>,
+    pub usage_limit: Option<i32>,
+    pub per_customer_limit: Option<i32>,
+    pub starts_at: Option<NaiveDateTime>,
+    pub ends_at: Option<NaiveDateTime>,
+    pub active: bool,
+    pub product_ids: Vec<i32>,
+    pub variant_rules: Vec<FormVariantRule>,
+    /// Orders placed with the code, cancelled ones aside.
+    pub uses: i64,
+}
+
+impl PromoCodeResponse {
+    pub fn new(promo: PromoCode, product_ids: Vec<i32>, rules: Vec<PromoCodeVariantRule>, uses: i64) -> Self {
+        PromoCodeResponse {
+            id: promo.id,
+            code: promo.code,
+            percent_off: promo.percent_off,
+            amount_off: promo.amount_off,
+            currency: promo.currency,
+            min_cart_total: promo.min_cart_total,
+            usage_limit: promo.usage_limit,
+            per_customer_limit: promo.per_customer_limit,
+            starts_at: promo.starts_at,
+            ends_at: promo.ends_at,
+            active: promo.active,
+            product_ids,
+            variant_rules: rules
+                .into_iter()
+                .map(|rule| FormVariantRule {
+                    variant: rule.variant_name,
+                    value: rule.value,
+                    min: rule.min_value,
+                    max: rule.max_value,
+                })
+                .collect(),
+            uses,
+        }
+    }
+}
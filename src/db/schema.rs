@@ -1,9 +1,143 @@
+table! {
+    exchange_rates (id) {
+        id -> Integer,
+        base_currency -> Text,
+        quote_currency -> Text,
+        rate -> BigInt,
+    }
+}
+
+table! {
+    inventory_movements (id) {
+        id -> Integer,
+        sku_id -> Integer,
+        location_id -> Integer,
+        transfer_id -> Nullable<Integer>,
+        reason -> Text,
+        quantity -> Integer,
+        balance -> Integer,
+        note -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    location_stocks (location_id, sku_id) {
+        location_id -> Integer,
+        sku_id -> Integer,
+        stock -> Integer,
+    }
+}
+
+table! {
+    locations (id) {
+        id -> Integer,
+        name -> Text,
+        kind -> Text,
+    }
+}
+
+table! {
+    order_events (id) {
+        id -> Integer,
+        order_id -> Integer,
+        from_status -> Nullable<Text>,
+        to_status -> Text,
+        note -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    order_line_options (order_line_id, position) {
+        order_line_id -> Integer,
+        position -> Integer,
+        name -> Text,
+        value -> Nullable<Text>,
+    }
+}
+
+table! {
+    order_lines (id) {
+        id -> Integer,
+        order_id -> Integer,
+        product_id -> Nullable<Integer>,
+        sku_id -> Nullable<Integer>,
+        product_name -> Text,
+        quantity -> Integer,
+        unit_price -> BigInt,
+        total -> BigInt,
+        discount -> BigInt,
+    }
+}
+
+table! {
+    orders (id) {
+        id -> Integer,
+        currency -> Text,
+        item_count -> Integer,
+        total -> BigInt,
+        created_at -> Timestamp,
+        status -> Text,
+        promo_code_id -> Nullable<Integer>,
+        promo_code -> Nullable<Text>,
+        discount -> BigInt,
+        customer_email -> Nullable<Text>,
+    }
+}
+
+table! {
+    payments (id) {
+        id -> Integer,
+        order_id -> Integer,
+        gateway -> Text,
+        reference -> Text,
+        status -> Text,
+        amount -> BigInt,
+        refunded -> BigInt,
+        currency -> Text,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    product_price_changes (id) {
+        id -> Integer,
+        product_id -> Integer,
+        cost -> Nullable<BigInt>,
+        currency -> Text,
+        changed_at -> Timestamp,
+    }
+}
+
+table! {
+    product_prices (id) {
+        id -> Integer,
+        product_id -> Integer,
+        currency -> Text,
+        amount -> BigInt,
+    }
+}
+
+table! {
+    product_sales (id) {
+        id -> Integer,
+        product_id -> Integer,
+        cost -> BigInt,
+        starts_at -> Timestamp,
+        ends_at -> Timestamp,
+    }
+}
+
 table! {
     products (id) {
         id -> Integer,
         name -> Text,
-        cost -> Double,
+        cost -> BigInt,
+        currency -> Text,
         active -> Bool,
+        compare_at_cost -> Nullable<BigInt>,
     }
 }
 
@@ -16,6 +150,117 @@ table! {
     }
 }
 
+table! {
+    promo_code_products (promo_code_id, product_id) {
+        promo_code_id -> Integer,
+        product_id -> Integer,
+    }
+}
+
+table! {
+    promo_code_variant_rules (id) {
+        id -> Integer,
+        promo_code_id -> Integer,
+        variant_name -> Text,
+        value -> Nullable<Text>,
+        min_value -> Nullable<Double>,
+        max_value -> Nullable<Double>,
+    }
+}
+
+table! {
+    promo_codes (id) {
+        id -> Integer,
+        code -> Text,
+        percent_off -> Nullable<Integer>,
+        amount_off -> Nullable<BigInt>,
+        currency -> Text,
+        min_cart_total -> Nullable<BigInt>,
+        usage_limit -> Nullable<Integer>,
+        per_customer_limit -> Nullable<Integer>,
+        starts_at -> Nullable<Timestamp>,
+        ends_at -> Nullable<Timestamp>,
+        active -> Bool,
+    }
+}
+
+table! {
+    refunds (id) {
+        id -> Integer,
+        payment_id -> Integer,
+        amount -> BigInt,
+        status -> Text,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    return_lines (id) {
+        id -> Integer,
+        return_request_id -> Integer,
+        order_line_id -> Integer,
+        quantity -> Integer,
+        reason -> Text,
+    }
+}
+
+table! {
+    return_requests (id) {
+        id -> Integer,
+        order_id -> Integer,
+        status -> Text,
+        refund -> BigInt,
+        note -> Nullable<Text>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    sku_values (sku_id, product_variant_id) {
+        sku_id -> Integer,
+        product_variant_id -> Integer,
+    }
+}
+
+table! {
+    skus (id) {
+        id -> Integer,
+        product_id -> Integer,
+        code -> Text,
+        price -> Nullable<BigInt>,
+        stock -> Integer,
+        allow_backorder -> Bool,
+    }
+}
+
+table! {
+    synonym_groups (id) {
+        id -> Integer,
+    }
+}
+
+table! {
+    synonyms (id) {
+        id -> Integer,
+        synonym_group_id -> Integer,
+        term -> Text,
+    }
+}
+
+table! {
+    transfers (id) {
+        id -> Integer,
+        sku_id -> Integer,
+        from_location_id -> Integer,
+        to_location_id -> Integer,
+        quantity -> Integer,
+        note -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     variants (id) {
         id -> Integer,
@@ -23,11 +268,61 @@ table! {
     }
 }
 
+joinable!(inventory_movements -> locations (location_id));
+joinable!(inventory_movements -> skus (sku_id));
+joinable!(inventory_movements -> transfers (transfer_id));
+joinable!(location_stocks -> locations (location_id));
+joinable!(location_stocks -> skus (sku_id));
+joinable!(order_events -> orders (order_id));
+joinable!(order_line_options -> order_lines (order_line_id));
+joinable!(order_lines -> orders (order_id));
+joinable!(order_lines -> products (product_id));
+joinable!(order_lines -> skus (sku_id));
+joinable!(orders -> promo_codes (promo_code_id));
+joinable!(payments -> orders (order_id));
+joinable!(product_price_changes -> products (product_id));
+joinable!(product_prices -> products (product_id));
+joinable!(product_sales -> products (product_id));
 joinable!(products_variants -> products (product_id));
 joinable!(products_variants -> variants (variant_id));
+joinable!(promo_code_products -> products (product_id));
+joinable!(promo_code_products -> promo_codes (promo_code_id));
+joinable!(promo_code_variant_rules -> promo_codes (promo_code_id));
+joinable!(refunds -> payments (payment_id));
+joinable!(return_lines -> order_lines (order_line_id));
+joinable!(return_lines -> return_requests (return_request_id));
+joinable!(return_requests -> orders (order_id));
+joinable!(sku_values -> products_variants (product_variant_id));
+joinable!(sku_values -> skus (sku_id));
+joinable!(skus -> products (product_id));
+joinable!(synonyms -> synonym_groups (synonym_group_id));
+joinable!(transfers -> skus (sku_id));
 
 allow_tables_to_appear_in_same_query!(
+    exchange_rates,
+    inventory_movements,
+    location_stocks,
+    locations,
+    order_events,
+    order_line_options,
+    order_lines,
+    orders,
+    payments,
+    product_price_changes,
+    product_prices,
+    product_sales,
     products,
     products_variants,
+    promo_code_products,
+    promo_code_variant_rules,
+    promo_codes,
+    refunds,
+    return_lines,
+    return_requests,
+    sku_values,
+    skus,
+    synonym_groups,
+    synonyms,
+    transfers,
     variants,
 );
@@ -0,0 +1,116 @@
+use actix_web::{
+    error::BlockingError, http::StatusCode, HttpResponse, ResponseError,
+};
+use diesel::r2d2::PoolError;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use serde::{Deserialize, Serialize};
+
+/// Errors surfaced by the data access layer and the HTTP handlers.
+///
+/// Every variant maps to an HTTP status code and a stable machine-readable
+/// `code`, so clients always receive the same JSON error body.
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    #[error("{0} not found")]
+    NotFound(String),
+    #[error("{0}")]
+    Validation(String),
+    #[error("request payload is invalid")]
+    InvalidFields(Vec<FieldError>),
+    #[error("{0}")]
+    Conflict(String),
+    #[error("no database connection available")]
+    PoolExhausted,
+    #[error("database error: {0}")]
+    Database(String),
+    #[error("internal error: {0}")]
+    Internal(String),
+}
+
+/// JSON body returned for every error response.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct ErrorBody {
+    pub code: String,
+    pub message: String,
+    /// Every invalid field of the payload, for `validation_failed` errors
+    /// raised by the payload validation.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+}
+
+/// An invalid field, addressed by a JSON pointer into the request payload.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FieldError {
+    pub pointer: String,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Validation(_) | ApiError::InvalidFields(_) => "validation_failed",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::PoolExhausted => "pool_exhausted",
+            ApiError::Database(_) => "database_error",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Validation(_) | ApiError::InvalidFields(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::PoolExhausted => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Database(_) | ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+        // Don't leak SQL or driver details to clients, only to the logs.
+        let message = if status.is_server_error() {
+            log::error!("{}", self);
+            status.canonical_reason().unwrap_or("error").to_string()
+        } else {
+            self.to_string()
+        };
+        HttpResponse::build(status).json(ErrorBody {
+            code: self.code().to_string(),
+            message,
+            errors: match self {
+                ApiError::InvalidFields(fields) => fields.clone(),
+                _ => Vec::new(),
+            },
+        })
+    }
+}
+
+impl From<DieselError> for ApiError {
+    fn from(error: DieselError) -> Self {
+        match error {
+            DieselError::NotFound => ApiError::NotFound("record".to_string()),
+            DieselError::DatabaseError(
+                DatabaseErrorKind::UniqueViolation | DatabaseErrorKind::ForeignKeyViolation,
+                info,
+            ) => ApiError::Conflict(info.message().to_string()),
+            error => ApiError::Database(error.to_string()),
+        }
+    }
+}
+
+impl From<PoolError> for ApiError {
+    fn from(error: PoolError) -> Self {
+        log::warn!("{}", error);
+        ApiError::PoolExhausted
+    }
+}
+
+impl From<BlockingError> for ApiError {
+    fn from(error: BlockingError) -> Self {
+        ApiError::Internal(error.to_string())
+    }
+}
@@ -1,3 +1,5 @@
+// Diesel 1.x derives expand to impl blocks nested inside consts.
+#![allow(non_local_definitions)]
 #[macro_use]
 extern crate diesel;
 extern crate diesel_migrations;
@@ -6,3 +8,6 @@ extern crate serde;
 extern crate serde_json;
 pub mod db;
 pub mod actions;
+pub mod errors;
+pub mod payments;
+pub mod validation;
@@ -9,26 +9,54 @@ use std::env;
 use env_logger::Env;
 use shoe_store::{
     db::connect::establish_connection,
+    errors::ApiError,
+    payments,
     actions
 };
 
 #[actix_web::main] // or #[tokio::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
+    env_logger::init_from_env(Env::default().default_filter_or("info"));
     let port = env::var("PORT").unwrap_or("8080".to_owned()).parse::<u16>().unwrap();
     let address = "127.0.0.1";
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
     let conn = establish_connection(&database_url);
-    env_logger::init_from_env(Env::default().default_filter_or("info"));
+    let gateway_name = env::var("PAYMENT_GATEWAY").expect("PAYMENT_GATEWAY must be set");
+    if gateway_name == "mock" {
+        log::warn!("PAYMENT_GATEWAY is mock, no payment is actually collected");
+    }
+    let webhook_secret = env::var("PAYMENT_WEBHOOK_SECRET").unwrap_or_default();
+    if webhook_secret.is_empty() {
+        log::warn!("PAYMENT_WEBHOOK_SECRET is not set, every webhook of the payment gateway is rejected");
+    }
+    let gateway = payments::gateway(&gateway_name, &webhook_secret)
+        .unwrap_or_else(|| panic!("PAYMENT_GATEWAY '{}' is not a payment gateway", gateway_name));
+    // Shared by every worker and kept across restarts, or a session cookie,
+    // and the cart in it, would only be readable by the worker that issued it
+    // until the next restart
+    let secret_key = match env::var("SESSION_KEY") {
+        Ok(key) => base64::decode(key.trim())
+            .ok()
+            .filter(|key| key.len() >= 64)
+            .map(|key| Key::from(&key))
+            .expect("SESSION_KEY must be at least 64 bytes encoded in base64"),
+        Err(_) if cfg!(debug_assertions) => {
+            log::warn!("SESSION_KEY is not set, sessions won't survive a restart");
+            Key::generate()
+        }
+        Err(_) => panic!("SESSION_KEY must be set"),
+    };
     log::info!("starting HTTP server at {address}:{port}");
     HttpServer::new(move || {
         let cors_mw = Cors::default()
             .allowed_origin(env::var("CORS_ALLOWED_ORIGINS").unwrap_or("https://my_domain.com/".to_owned()).as_ref())
-            .allowed_methods(vec!["GET", "POST"])
+            .allowed_methods(vec!["GET", "POST", "PUT", "DELETE"])
             .allowed_headers(vec![http::header::AUTHORIZATION, http::header::ACCEPT])
             .allowed_header(http::header::CONTENT_TYPE)
-            .max_age(3600);
-        let secret_key = Key::generate();
+            .expose_headers(vec![http::header::LOCATION])
+            .max_age(3600)
+            .supports_credentials();
         let session_mw = SessionMiddleware::builder(CookieSessionStore::default(), secret_key.clone())
         .cookie_secure(false)
         .build();
@@ -36,15 +64,87 @@ async fn main() -> std::io::Result<()> {
             .wrap(session_mw)
             .wrap(cors_mw)
             .app_data(web::Data::new(conn.clone()))
+            .app_data(web::Data::from(gateway.clone()))
+            .app_data(web::JsonConfig::default().error_handler(|err, _req| {
+                ApiError::Validation(err.to_string()).into()
+            }))
+            .app_data(web::QueryConfig::default().error_handler(|err, _req| {
+                ApiError::Validation(err.to_string()).into()
+            }))
+            .app_data(web::PathConfig::default().error_handler(|err, _req| {
+                ApiError::Validation(err.to_string()).into()
+            }))
             .wrap(Logger::default())
             .wrap(Logger::new("%a %{User-Agent}i"))
             .route("/hello", web::get().to(|| async { "Hello World!" }))
             .service(actions::product_create)
             .service(actions::product_list)
             .service(actions::product_search)
+            .service(actions::product_suggest)
+            .service(actions::product_facets)
             .service(actions::product_show)
             .service(actions::product_update)
             .service(actions::product_delete)
+            .service(actions::prices::product_price_list)
+            .service(actions::prices::product_price_set)
+            .service(actions::prices::product_price_delete)
+            .service(actions::prices::product_price_history)
+            .service(actions::prices::product_sale_list)
+            .service(actions::prices::product_sale_create)
+            .service(actions::prices::product_sale_delete)
+            .service(actions::prices::exchange_rate_list)
+            .service(actions::prices::exchange_rate_set)
+            .service(actions::prices::exchange_rate_delete)
+            .service(actions::skus::sku_create)
+            .service(actions::skus::sku_matrix_generate)
+            .service(actions::skus::sku_list)
+            .service(actions::skus::sku_show)
+            .service(actions::skus::sku_update)
+            .service(actions::skus::sku_delete)
+            .service(actions::inventory::inventory_adjust)
+            .service(actions::inventory::inventory_list)
+            .service(actions::inventory::transfer_create)
+            .service(actions::inventory::transfer_list)
+            .service(actions::locations::location_create)
+            .service(actions::locations::location_list)
+            .service(actions::locations::location_show)
+            .service(actions::locations::location_update)
+            .service(actions::locations::location_delete)
+            .service(actions::locations::sku_stock)
+            .service(actions::carts::cart_show)
+            .service(actions::carts::cart_line_add)
+            .service(actions::carts::cart_line_update)
+            .service(actions::carts::cart_line_remove)
+            .service(actions::carts::cart_promo_code_apply)
+            .service(actions::carts::cart_promo_code_remove)
+            .service(actions::carts::cart_clear)
+            .service(actions::orders::checkout)
+            .service(actions::orders::order_show)
+            // `order_transition` moves stock and money, it isn't mounted
+            // until there is an admin guard
+            .service(actions::orders::order_event_list)
+            .service(actions::payments::payment_create)
+            .service(actions::payments::payment_list)
+            .service(actions::payments::payment_webhook)
+            .service(actions::payments::payment_show)
+            // `payment_refund` moves money, it isn't mounted until there is
+            // an admin guard
+            .service(actions::payments::payment_refund_list)
+            .service(actions::returns::return_create)
+            .service(actions::returns::return_list)
+            .service(actions::returns::return_show)
+            // Approving, rejecting, receiving and refunding returns is up to
+            // the store, it isn't mounted until there is an admin guard
+            .service(actions::promotions::promo_code_create)
+            .service(actions::promotions::promo_code_list)
+            .service(actions::promotions::promo_code_show)
+            .service(actions::promotions::promo_code_update)
+            .service(actions::promotions::promo_code_delete)
+            .service(actions::synonyms::synonym_group_create)
+            .service(actions::synonyms::synonym_group_list)
+            .service(actions::synonyms::synonym_group_show)
+            .service(actions::synonyms::synonym_group_update)
+            .service(actions::synonyms::synonym_group_delete)
     })
     .bind((address, port))?
     .run()
@@ -0,0 +1,62 @@
+//! Payment gateways, behind the [`PaymentGateway`] trait so that the provider
+//! is a matter of configuration. See [`mock::MockGateway`] for one that works
+//! offline.
+
+use crate::db::models::PaymentStatus;
+use crate::db::money::Money;
+use crate::errors::ApiError;
+use std::sync::Arc;
+
+pub mod mock;
+
+pub type Result<T> = std::result::Result<T, ApiError>;
+
+/// What a gateway answered to an authorization.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Authorization {
+    /// Id of the payment at the gateway.
+    pub reference: String,
+    /// Either authorized, declined, or requiring action from the customer.
+    pub status: PaymentStatus,
+}
+
+/// The outcome of a payment a gateway notified us about through a webhook.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebhookEvent {
+    pub reference: String,
+    /// Either authorized or declined.
+    pub status: PaymentStatus,
+}
+
+/// A payment service provider.
+///
+/// Declines aren't errors: they are reported in the status of the
+/// [`Authorization`]. Errors are for requests the gateway couldn't handle.
+pub trait PaymentGateway: Send + Sync {
+    /// Name stored along with the payments made through the gateway.
+    fn name(&self) -> &'static str;
+
+    /// Reserves `amount` on the payment method `source` stands for.
+    fn authorize(&self, amount: Money, currency: &str, source: &str) -> Result<Authorization>;
+
+    /// Collects `amount` of an authorized payment.
+    fn capture(&self, reference: &str, amount: Money, currency: &str) -> Result<()>;
+
+    /// Releases an authorized payment without collecting it.
+    fn void(&self, reference: &str) -> Result<()>;
+
+    /// Gives `amount` of a captured payment back.
+    fn refund(&self, reference: &str, amount: Money, currency: &str) -> Result<()>;
+
+    /// Checks that `payload` was sent by the gateway and parses it.
+    fn verify_webhook(&self, payload: &[u8], signature: &str) -> Result<WebhookEvent>;
+}
+
+/// The gateway called `name`, `None` when there is none by that name.
+/// `webhook_secret` is shared with the gateway to sign webhooks.
+pub fn gateway(name: &str, webhook_secret: &str) -> Option<Arc<dyn PaymentGateway>> {
+    match name {
+        "mock" => Some(Arc::new(mock::MockGateway::new(webhook_secret))),
+        _ => None,
+    }
+}
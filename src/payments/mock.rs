@@ -0,0 +1,93 @@
+use super::{Authorization, PaymentGateway, Result, WebhookEvent};
+use crate::db::models::PaymentStatus;
+use crate::db::money::Money;
+use crate::errors::ApiError;
+use chrono::Utc;
+use serde::Deserialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Source of a payment that is authorized.
+pub const SOURCE_SUCCESS: &str = "mock_success";
+/// Source of a payment that is declined.
+pub const SOURCE_DECLINE: &str = "mock_decline";
+/// Source of a payment that requires a 3-D Secure challenge, its outcome
+/// comes through a webhook.
+pub const SOURCE_3DS: &str = "mock_3ds";
+
+/// A gateway that moves no money, for development and tests. The outcome of
+/// an authorization depends on its source, see [`SOURCE_SUCCESS`],
+/// [`SOURCE_DECLINE`] and [`SOURCE_3DS`].
+///
+/// Its webhooks aren't actually signed: one is genuine when its signature is
+/// the webhook secret. The payload is JSON such as
+/// `{"reference": "mock_1", "status": "authorized"}`.
+pub struct MockGateway {
+    webhook_secret: String,
+    next_reference: AtomicU64,
+}
+
+#[derive(Deserialize)]
+struct MockWebhook {
+    reference: String,
+    status: PaymentStatus,
+}
+
+impl MockGateway {
+    pub fn new(webhook_secret: &str) -> Self {
+        MockGateway {
+            webhook_secret: webhook_secret.to_string(),
+            next_reference: AtomicU64::new(1),
+        }
+    }
+}
+
+impl PaymentGateway for MockGateway {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn authorize(&self, amount: Money, _currency: &str, source: &str) -> Result<Authorization> {
+        if !amount.is_positive() {
+            return Err(ApiError::Validation("a payment must be of a positive amount".to_string()));
+        }
+        let status = match source {
+            SOURCE_SUCCESS => PaymentStatus::Authorized,
+            SOURCE_DECLINE => PaymentStatus::Declined,
+            SOURCE_3DS => PaymentStatus::RequiresAction,
+            other => return Err(ApiError::Validation(format!("unknown mock payment source '{}'", other))),
+        };
+        // The timestamp keeps references unique across restarts
+        let number = self.next_reference.fetch_add(1, Ordering::Relaxed);
+        Ok(Authorization {
+            reference: format!("mock_{}_{}", Utc::now().timestamp_millis(), number),
+            status,
+        })
+    }
+
+    fn capture(&self, _reference: &str, _amount: Money, _currency: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn void(&self, _reference: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn refund(&self, _reference: &str, _amount: Money, _currency: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn verify_webhook(&self, payload: &[u8], signature: &str) -> Result<WebhookEvent> {
+        if self.webhook_secret.is_empty() || signature != self.webhook_secret {
+            return Err(ApiError::Validation("invalid webhook signature".to_string()));
+        }
+        let webhook = serde_json::from_slice::<MockWebhook>(payload)
+            .map_err(|error| ApiError::Validation(format!("invalid webhook payload: {}", error)))?;
+        match webhook.status {
+            PaymentStatus::Authorized | PaymentStatus::Declined => Ok(WebhookEvent {
+                reference: webhook.reference,
+                status: webhook.status,
+            }),
+            status => Err(ApiError::Validation(format!("a webhook can't report a payment {}", status.as_str()))),
+        }
+    }
+}
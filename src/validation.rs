@@ -0,0 +1,177 @@
+//! Declarative validation of the request payloads, see the `Validate` derives
+//! in [`crate::db::models`].
+
+use crate::db::models::{
+    FormInventoryAdjustment, FormProduct, FormProductSale, FormPromoCode, FormTransfer, FormVariantRule, MovementReason,
+};
+use crate::db::money::{Money, Rate};
+use crate::errors::{ApiError, FieldError};
+use std::borrow::Cow;
+use validator::{Validate, ValidationError, ValidationErrors, ValidationErrorsKind};
+
+/// Validates `payload`, reporting every invalid field at once.
+pub fn validate<T: Validate>(payload: &T) -> Result<(), ApiError> {
+    payload.validate().map_err(|errors| {
+        let mut fields = Vec::new();
+        collect_field_errors("", &errors, &mut fields);
+        fields.sort_by(|a, b| a.pointer.cmp(&b.pointer));
+        ApiError::InvalidFields(fields)
+    })
+}
+
+/// Flattens nested validation errors, addressing each field with a JSON
+/// pointer into the payload such as `/variants/0/values`.
+fn collect_field_errors(path: &str, errors: &ValidationErrors, fields: &mut Vec<FieldError>) {
+    for (field, kind) in errors.errors() {
+        // Struct level errors are reported against the struct itself
+        let pointer = match *field {
+            "__all__" => path.to_string(),
+            field => format!("{}/{}", path, field),
+        };
+        match kind {
+            ValidationErrorsKind::Field(field_errors) => {
+                fields.extend(field_errors.iter().map(|error| FieldError {
+                    pointer: pointer.clone(),
+                    message: error
+                        .message
+                        .as_ref()
+                        .map(ToString::to_string)
+                        .unwrap_or_else(|| error.code.to_string()),
+                }));
+            }
+            ValidationErrorsKind::Struct(errors) => collect_field_errors(&pointer, errors, fields),
+            ValidationErrorsKind::List(items) => {
+                for (index, errors) in items {
+                    collect_field_errors(&format!("{}/{}", pointer, index), errors, fields);
+                }
+            }
+        }
+    }
+}
+
+fn error(code: &'static str, message: &'static str) -> ValidationError {
+    let mut error = ValidationError::new(code);
+    error.message = Some(Cow::from(message));
+    error
+}
+
+pub fn not_blank(value: &str) -> Result<(), ValidationError> {
+    if value.trim().is_empty() {
+        return Err(error("blank", "must not be blank"));
+    }
+    Ok(())
+}
+
+pub fn non_negative(amount: &Money) -> Result<(), ValidationError> {
+    if amount.is_negative() {
+        return Err(error("negative", "must not be negative"));
+    }
+    Ok(())
+}
+
+pub fn positive_amount(amount: &Money) -> Result<(), ValidationError> {
+    if !amount.is_positive() {
+        return Err(error("not_positive", "must be greater than zero"));
+    }
+    Ok(())
+}
+
+pub fn positive_rate(rate: &Rate) -> Result<(), ValidationError> {
+    if !rate.is_positive() {
+        return Err(error("not_positive", "must be greater than zero"));
+    }
+    Ok(())
+}
+
+/// Accepts ISO 4217 alphabetic codes such as `EUR`.
+pub fn currency_code(code: &str) -> Result<(), ValidationError> {
+    if code.len() != 3 || !code.bytes().all(|byte| byte.is_ascii_uppercase()) {
+        return Err(error("invalid_currency", "must be a three-letter uppercase currency code"));
+    }
+    Ok(())
+}
+
+pub fn unique_values(values: &[Option<String>]) -> Result<(), ValidationError> {
+    for (index, value) in values.iter().enumerate() {
+        if values[..index].contains(value) {
+            return Err(error("duplicate_value", "must not contain duplicate values"));
+        }
+    }
+    Ok(())
+}
+
+/// Rejects a product update giving the same value twice for a variant, new
+/// variants being told apart by name.
+pub fn unique_product_variants(product: &FormProduct) -> Result<(), ValidationError> {
+    let values = product
+        .variants
+        .iter()
+        .map(|variant| {
+            let product_variant = &variant.product_variant;
+            let name = match product_variant.variant_id {
+                Some(_) => None,
+                None => variant.variant.as_ref().map(|variant| variant.name.trim()),
+            };
+            (product_variant.variant_id, name, &product_variant.value)
+        })
+        .collect::<Vec<_>>();
+    for (index, value) in values.iter().enumerate() {
+        if values[..index].contains(value) {
+            return Err(error("duplicate_value", "variants must not contain duplicate values"));
+        }
+    }
+    Ok(())
+}
+
+pub fn sale_period(sale: &FormProductSale) -> Result<(), ValidationError> {
+    if sale.starts_at >= sale.ends_at {
+        return Err(error("empty_period", "a sale must start before it ends"));
+    }
+    Ok(())
+}
+
+pub fn adjustment_quantity(adjustment: &FormInventoryAdjustment) -> Result<(), ValidationError> {
+    let valid = match adjustment.reason {
+        MovementReason::Receipt | MovementReason::Return => adjustment.quantity > 0,
+        MovementReason::Sale | MovementReason::Damage => adjustment.quantity < 0,
+        MovementReason::Correction => adjustment.quantity != 0,
+        MovementReason::Transfer => {
+            return Err(error("transfer", "transfers are posted as transfers, not as adjustments"));
+        }
+    };
+    if !valid {
+        return Err(error("invalid_quantity", "quantity doesn't match the reason of the movement"));
+    }
+    Ok(())
+}
+
+pub fn distinct_locations(transfer: &FormTransfer) -> Result<(), ValidationError> {
+    if transfer.from_location_id == transfer.to_location_id {
+        return Err(error("same_location", "a transfer needs two distinct locations"));
+    }
+    Ok(())
+}
+
+pub fn promo_discount(promo: &FormPromoCode) -> Result<(), ValidationError> {
+    if promo.percent_off.is_some() == promo.amount_off.is_some() {
+        return Err(error("discount", "a promo code needs either a percent_off or an amount_off"));
+    }
+    if let (Some(starts_at), Some(ends_at)) = (promo.starts_at, promo.ends_at) {
+        if starts_at >= ends_at {
+            return Err(error("empty_period", "a promo code must start before it ends"));
+        }
+    }
+    Ok(())
+}
+
+pub fn variant_rule_condition(rule: &FormVariantRule) -> Result<(), ValidationError> {
+    let range = rule.min.is_some() || rule.max.is_some();
+    if rule.value.is_some() == range {
+        return Err(error("condition", "a variant rule needs either a value or a min and/or max"));
+    }
+    let finite = [rule.min, rule.max].iter().flatten().all(|bound| bound.is_finite());
+    if !finite || matches!((rule.min, rule.max), (Some(min), Some(max)) if min > max) {
+        return Err(error("invalid_range", "a variant rule needs a min no greater than its max"));
+    }
+    Ok(())
+}
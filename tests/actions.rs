@@ -1,6 +1,11 @@
-use actix_web::{test, web, App};
+use actix_web::{http, test, web, App};
 use shoe_store::{
-    actions, 
+    actions,
+    errors::ErrorBody,
+    db::dal::search::SearchHit,
+    db::money::Money,
+    db::pagination::{Cursor, Page},
+    db::responses::{CurrentPrices, ProductResponse, SkuResponse, VariantValue, VariantValues},
     db::models::{
         NewCompleteProduct, 
         NewProduct, 
@@ -12,16 +17,21 @@ use shoe_store::{
         FormProductVariant, 
         FormVariant, 
         FormProductVariantComplete, 
-        FormProduct
+        FormProduct,
+        FormSynonymGroup,
+        ProductPriceChange,
+        InventoryMovement,
+        MovementReason,
+        SynonymGroupTerms
     }
 };
 mod helpers;
-use helpers::establish_connection_test;
+use helpers::{assert_stock_consistent, establish_connection_test};
 
 #[actix_web::test]
 async fn test_product_creation_is_ok() {
     let pool = establish_connection_test();
-    let mut app = test::init_service(
+    let app = test::init_service(
         App::new()
             .app_data(web::Data::new(pool.clone()))
             .service(actions::product_create),
@@ -31,8 +41,10 @@ async fn test_product_creation_is_ok() {
     let body = NewCompleteProduct {
         product: NewProduct {
             name: "boots".to_string(),
-            cost: 13.23,
+            cost: Money::from_minor(1323),
+            currency: "EUR".to_string(),
             active: true,
+            compare_at_cost: None,
         },
         variants: vec![NewVariantValue {
             variant: NewVariant {
@@ -45,20 +57,48 @@ async fn test_product_creation_is_ok() {
                 Some(18.to_string()),
             ],
         }],
+        sku_matrix: None,
     };
 
     let req = test::TestRequest::post()
         .set_json(&body)
         .uri("/products")
         .to_request();
-    let resp = test::call_service(&mut app, req).await;
-    assert!(resp.status().is_success());
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    assert_eq!(
+        resp.headers().get(http::header::LOCATION).unwrap(),
+        "/products/1"
+    );
+    let created: ProductResponse = test::read_body_json(resp).await;
+    assert_eq!(
+        created,
+        ProductResponse {
+            id: 1,
+            name: "boots".to_string(),
+            cost: Money::from_minor(1323),
+            currency: "EUR".to_string(),
+            compare_at_cost: None,
+            effective_cost: Money::from_minor(1323),
+            lowest_30_day_cost: Money::from_minor(1323),
+            active: true,
+            variants: vec![VariantValues {
+                variant_id: 1,
+                name: "size".to_string(),
+                values: (1..=4)
+                    .zip(["12", "14", "16", "18"])
+                    .map(|(id, value)| VariantValue { id, value: Some(value.to_string()) })
+                    .collect(),
+            }],
+            availability: None,
+        }
+    );
 }
 
 #[actix_web::test]
 async fn test_product_list() {
     let pool = establish_connection_test();
-    let mut app = test::init_service(
+    let app = test::init_service(
         App::new()
             .app_data(web::Data::new(pool.clone()))
             .service(actions::product_create)
@@ -67,20 +107,22 @@ async fn test_product_list() {
     .await;
 
     let shoes = vec![
-        ("Boots", 14.00),
-        ("High Heels", 19.23),
-        ("Running Shoes", 21.90),
-        ("Tennis Shoes", 15.67),
-        ("Hiking Boots", 18.72),
-        ("Flip Flops", 10.5),
+        ("Boots", 1400),
+        ("High Heels", 1923),
+        ("Running Shoes", 2190),
+        ("Tennis Shoes", 1567),
+        ("Hiking Boots", 1872),
+        ("Flip Flops", 1050),
     ];
 
     for shoe in shoes {
         let body = NewCompleteProduct {
             product: NewProduct {
                 name: shoe.0.to_string(),
-                cost: shoe.1,
+                cost: Money::from_minor(shoe.1),
+                currency: "EUR".to_string(),
                 active: true,
+                compare_at_cost: None,
             },
             variants: vec![NewVariantValue {
                 variant: NewVariant {
@@ -93,13 +135,14 @@ async fn test_product_list() {
                     Some(18.to_string()),
                 ],
             }],
+            sku_matrix: None,
         };
 
         let req = test::TestRequest::post()
             .set_json(&body)
             .uri("/products")
             .to_request();
-        let resp = test::call_service(&mut app, req).await;
+        let resp = test::call_service(&app, req).await;
 
         assert!(resp.status().is_success());
     }
@@ -107,10 +150,10 @@ async fn test_product_list() {
     let req = test::TestRequest::get()
         .uri("/products?limit=5")
         .to_request();
-    let result = test::call_and_read_body(&mut app, req).await;
+    let result = test::call_and_read_body(&app, req).await;
 
     assert_eq!(
-        web::Bytes::from_static(b"[[{\"id\":1,\"name\":\"Boots\",\"cost\":14.0,\"active\":true},[[{\"id\":1,\"product_id\":1,\"variant_id\":1,\"value\":\"12\"},{\"id\":1,\"name\":\"size\"}],[{\"id\":2,\"product_id\":1,\"variant_id\":1,\"value\":\"14\"},{\"id\":1,\"name\":\"size\"}],[{\"id\":3,\"product_id\":1,\"variant_id\":1,\"value\":\"16\"},{\"id\":1,\"name\":\"size\"}],[{\"id\":4,\"product_id\":1,\"variant_id\":1,\"value\":\"18\"},{\"id\":1,\"name\":\"size\"}]]],[{\"id\":2,\"name\":\"High Heels\",\"cost\":19.23,\"active\":true},[[{\"id\":5,\"product_id\":2,\"variant_id\":1,\"value\":\"12\"},{\"id\":1,\"name\":\"size\"}],[{\"id\":6,\"product_id\":2,\"variant_id\":1,\"value\":\"14\"},{\"id\":1,\"name\":\"size\"}],[{\"id\":7,\"product_id\":2,\"variant_id\":1,\"value\":\"16\"},{\"id\":1,\"name\":\"size\"}],[{\"id\":8,\"product_id\":2,\"variant_id\":1,\"value\":\"18\"},{\"id\":1,\"name\":\"size\"}]]],[{\"id\":3,\"name\":\"Running Shoes\",\"cost\":21.9,\"active\":true},[[{\"id\":9,\"product_id\":3,\"variant_id\":1,\"value\":\"12\"},{\"id\":1,\"name\":\"size\"}],[{\"id\":10,\"product_id\":3,\"variant_id\":1,\"value\":\"14\"},{\"id\":1,\"name\":\"size\"}],[{\"id\":11,\"product_id\":3,\"variant_id\":1,\"value\":\"16\"},{\"id\":1,\"name\":\"size\"}],[{\"id\":12,\"product_id\":3,\"variant_id\":1,\"value\":\"18\"},{\"id\":1,\"name\":\"size\"}]]],[{\"id\":4,\"name\":\"Tennis Shoes\",\"cost\":15.67,\"active\":true},[[{\"id\":13,\"product_id\":4,\"variant_id\":1,\"value\":\"12\"},{\"id\":1,\"name\":\"size\"}],[{\"id\":14,\"product_id\":4,\"variant_id\":1,\"value\":\"14\"},{\"id\":1,\"name\":\"size\"}],[{\"id\":15,\"product_id\":4,\"variant_id\":1,\"value\":\"16\"},{\"id\":1,\"name\":\"size\"}],[{\"id\":16,\"product_id\":4,\"variant_id\":1,\"value\":\"18\"},{\"id\":1,\"name\":\"size\"}]]],[{\"id\":5,\"name\":\"Hiking Boots\",\"cost\":18.72,\"active\":true},[[{\"id\":17,\"product_id\":5,\"variant_id\":1,\"value\":\"12\"},{\"id\":1,\"name\":\"size\"}],[{\"id\":18,\"product_id\":5,\"variant_id\":1,\"value\":\"14\"},{\"id\":1,\"name\":\"size\"}],[{\"id\":19,\"product_id\":5,\"variant_id\":1,\"value\":\"16\"},{\"id\":1,\"name\":\"size\"}],[{\"id\":20,\"product_id\":5,\"variant_id\":1,\"value\":\"18\"},{\"id\":1,\"name\":\"size\"}]]]]"),
+        web::Bytes::from_static(b"{\"items\":[{\"id\":1,\"name\":\"Boots\",\"cost\":\"14.00\",\"currency\":\"EUR\",\"compare_at_cost\":null,\"effective_cost\":\"14.00\",\"lowest_30_day_cost\":\"14.00\",\"active\":true,\"variants\":[{\"variant_id\":1,\"name\":\"size\",\"values\":[{\"id\":1,\"value\":\"12\"},{\"id\":2,\"value\":\"14\"},{\"id\":3,\"value\":\"16\"},{\"id\":4,\"value\":\"18\"}]}]},{\"id\":2,\"name\":\"High Heels\",\"cost\":\"19.23\",\"currency\":\"EUR\",\"compare_at_cost\":null,\"effective_cost\":\"19.23\",\"lowest_30_day_cost\":\"19.23\",\"active\":true,\"variants\":[{\"variant_id\":1,\"name\":\"size\",\"values\":[{\"id\":5,\"value\":\"12\"},{\"id\":6,\"value\":\"14\"},{\"id\":7,\"value\":\"16\"},{\"id\":8,\"value\":\"18\"}]}]},{\"id\":3,\"name\":\"Running Shoes\",\"cost\":\"21.90\",\"currency\":\"EUR\",\"compare_at_cost\":null,\"effective_cost\":\"21.90\",\"lowest_30_day_cost\":\"21.90\",\"active\":true,\"variants\":[{\"variant_id\":1,\"name\":\"size\",\"values\":[{\"id\":9,\"value\":\"12\"},{\"id\":10,\"value\":\"14\"},{\"id\":11,\"value\":\"16\"},{\"id\":12,\"value\":\"18\"}]}]},{\"id\":4,\"name\":\"Tennis Shoes\",\"cost\":\"15.67\",\"currency\":\"EUR\",\"compare_at_cost\":null,\"effective_cost\":\"15.67\",\"lowest_30_day_cost\":\"15.67\",\"active\":true,\"variants\":[{\"variant_id\":1,\"name\":\"size\",\"values\":[{\"id\":13,\"value\":\"12\"},{\"id\":14,\"value\":\"14\"},{\"id\":15,\"value\":\"16\"},{\"id\":16,\"value\":\"18\"}]}]},{\"id\":5,\"name\":\"Hiking Boots\",\"cost\":\"18.72\",\"currency\":\"EUR\",\"compare_at_cost\":null,\"effective_cost\":\"18.72\",\"lowest_30_day_cost\":\"18.72\",\"active\":true,\"variants\":[{\"variant_id\":1,\"name\":\"size\",\"values\":[{\"id\":17,\"value\":\"12\"},{\"id\":18,\"value\":\"14\"},{\"id\":19,\"value\":\"16\"},{\"id\":20,\"value\":\"18\"}]}]}],\"next_cursor\":\"eyJpZCI6NX0\",\"total\":6}"),
        result,
       );
 }
@@ -118,7 +161,7 @@ async fn test_product_list() {
 #[actix_web::test]
 async fn test_product_show() {
     let pool = establish_connection_test();
-    let mut app = test::init_service(
+    let app = test::init_service(
         App::new()
             .app_data(web::Data::new(pool.clone()))
             .service(actions::product_create)
@@ -129,8 +172,10 @@ async fn test_product_show() {
     let body = NewCompleteProduct {
         product: NewProduct {
             name: "Boots".to_string(),
-            cost: 15.69,
+            cost: Money::from_minor(1569),
+            currency: "EUR".to_string(),
             active: true,
+            compare_at_cost: None,
         },
         variants: vec![NewVariantValue {
             variant: NewVariant {
@@ -143,22 +188,23 @@ async fn test_product_show() {
                 Some(18.to_string()),
             ],
         }],
+        sku_matrix: None,
     };
 
     let req = test::TestRequest::post()
         .set_json(&body)
         .uri("/products")
         .to_request();
-    let resp = test::call_service(&mut app, req).await;
+    let resp = test::call_service(&app, req).await;
 
     assert!(resp.status().is_success());
 
     let req = test::TestRequest::get().uri("/products/1").to_request();
-    let resp = test::call_and_read_body(&mut app, req).await;
+    let resp = test::call_and_read_body(&app, req).await;
 
     assert_eq!(
             web::Bytes::from_static(
-                b"[{\"id\":1,\"name\":\"Boots\",\"cost\":15.69,\"active\":true},[[{\"id\":1,\"product_id\":1,\"variant_id\":1,\"value\":\"12\"},{\"id\":1,\"name\":\"size\"}],[{\"id\":2,\"product_id\":1,\"variant_id\":1,\"value\":\"14\"},{\"id\":1,\"name\":\"size\"}],[{\"id\":3,\"product_id\":1,\"variant_id\":1,\"value\":\"16\"},{\"id\":1,\"name\":\"size\"}],[{\"id\":4,\"product_id\":1,\"variant_id\":1,\"value\":\"18\"},{\"id\":1,\"name\":\"size\"}]]]"
+                b"{\"id\":1,\"name\":\"Boots\",\"cost\":\"15.69\",\"currency\":\"EUR\",\"compare_at_cost\":null,\"effective_cost\":\"15.69\",\"lowest_30_day_cost\":\"15.69\",\"active\":true,\"variants\":[{\"variant_id\":1,\"name\":\"size\",\"values\":[{\"id\":1,\"value\":\"12\"},{\"id\":2,\"value\":\"14\"},{\"id\":3,\"value\":\"16\"},{\"id\":4,\"value\":\"18\"}]}]}"
             ),
             resp
         );
@@ -168,7 +214,7 @@ async fn test_product_show() {
 #[actix_web::test]
 async fn test_product_search() {
     let pool = establish_connection_test();
-    let mut app = test::init_service(
+    let app = test::init_service(
         App::new()
             .app_data(web::Data::new(pool.clone()))
             .service(actions::product_create)
@@ -179,8 +225,10 @@ async fn test_product_search() {
 			NewCompleteProduct {
 				product: NewProduct {
 					name: "Boots".to_string(),
-					cost: 13.23,
-					active: true
+					cost: Money::from_minor(1323),
+					currency: "EUR".to_string(),
+					active: true,
+					compare_at_cost: None
 				},
 				variants: vec![
 					NewVariantValue {
@@ -194,11 +242,12 @@ async fn test_product_search() {
 							Some(18.to_string())
 						]
 					}
-				]
+				],
+				sku_matrix: None,
 			};
 
 		let req = test::TestRequest::post().set_json(&body).uri("/products").to_request();
-		let resp = test::call_service(&mut app, req).await;
+		let resp = test::call_service(&app, req).await;
 
 		assert!(resp.status().is_success());
 
@@ -206,8 +255,10 @@ async fn test_product_search() {
 			NewCompleteProduct {
 				product: NewProduct {
 					name: "Sandals".to_string(),
-					cost: 15.00,
-					active: true
+					cost: Money::from_minor(1500),
+					currency: "EUR".to_string(),
+					active: true,
+					compare_at_cost: None
 				},
 				variants: vec![
 					NewVariantValue {
@@ -218,22 +269,25 @@ async fn test_product_search() {
 							Some(20.to_string()),
 						]
 					}
-				]
+				],
+				sku_matrix: None,
 			};
 
 		let req = test::TestRequest::post().set_json(&body).uri("/products").to_request();
-		let resp = test::call_service(&mut app, req).await;
+		let resp = test::call_service(&app, req).await;
 
 		assert!(resp.status().is_success());
 
         let req = test::TestRequest::get().uri("/products/search?search=Sandals").to_request();
-        let resp = test::call_and_read_body(&mut app, req).await;
+        let resp = test::call_and_read_body(&app, req).await;
 
-        let result = vec![(Product {
+        let expected = ProductResponse::new(Product {
             id: 2,
 			name: "Sandals".to_string(),
-			cost: 15.00,
-            active: true
+			cost: Money::from_minor(1500),
+			currency: "EUR".to_string(),
+            active: true,
+            compare_at_cost: None
         }, vec![
             (ProductVariant {
                 id: 5,
@@ -245,19 +299,22 @@ async fn test_product_search() {
                 id: 1,
                 name: "size".to_string()
             })
-        ])];
+        ], CurrentPrices::default());
+        let result: Page<SearchHit> = serde_json::from_slice(&resp).unwrap();
 
-        assert_eq!(
-            serde_json::to_string(&result).unwrap().as_bytes(),
-            resp
-        );
+        assert_eq!(result.total, 1);
+        assert_eq!(result.next_cursor, None);
+        assert_eq!(result.items.len(), 1);
+        let hit = &result.items[0];
+        assert_eq!(hit.product, expected);
+        assert_eq!(hit.highlight, "<mark>Sandals</mark>");
 }
 
 
 #[actix_web::test]
 async fn test_product_update() {
         let pool = establish_connection_test();
-    let mut app = test::init_service(
+    let app = test::init_service(
         App::new()
             .app_data(web::Data::new(pool.clone()))
             .service(actions::product_create)
@@ -270,8 +327,10 @@ async fn test_product_update() {
 			NewCompleteProduct {
 				product: NewProduct {
 					name: "boots".to_string(),
-					cost: 13.23,
-					active: true
+					cost: Money::from_minor(1323),
+					currency: "EUR".to_string(),
+					active: true,
+					compare_at_cost: None
 				},
 				variants: vec![
 					NewVariantValue {
@@ -285,11 +344,12 @@ async fn test_product_update() {
 							Some(18.to_string())
 						]
 					}
-				]
+				],
+				sku_matrix: None,
 			};
 
 		let req = test::TestRequest::post().set_json(&body).uri("/products").to_request();
-		let resp = test::call_service(&mut app, req).await;
+		let resp = test::call_service(&app, req).await;
 
 		assert!(resp.status().is_success());
 
@@ -297,8 +357,10 @@ async fn test_product_update() {
             FormProduct {
                 product: NewProduct {
                     name: "high heels".to_string(),
-                    cost: 15.00,
-                    active: true
+                    cost: Money::from_minor(1500),
+                    currency: "EUR".to_string(),
+                    active: true,
+                    compare_at_cost: None
                 },
                 variants: vec![
                     FormProductVariantComplete {
@@ -316,18 +378,22 @@ async fn test_product_update() {
                 ]
             };
         let req = test::TestRequest::put().set_json(&body).uri("/products/1").to_request();
-		let resp = test::call_service(&mut app, req).await;
+		let resp = test::call_service(&app, req).await;
 
 		assert!(resp.status().is_success());
+        let updated = test::read_body(resp).await;
 
         let req = test::TestRequest::get().uri("/products/1").to_request();
-        let resp = test::call_and_read_body(&mut app, req).await;
+        let resp = test::call_and_read_body(&app, req).await;
+        assert_eq!(updated, resp);
 
-        let result = (Product {
+        let result = ProductResponse::new(Product {
             id: 1,
             name: "high heels".to_string(),
-            cost: 15.00,
-            active: true
+            cost: Money::from_minor(1500),
+            currency: "EUR".to_string(),
+            active: true,
+            compare_at_cost: None
         }, vec![
             (ProductVariant {
                 id: 1,
@@ -369,7 +435,11 @@ async fn test_product_update() {
                 id: 1,
                 name: "size".to_string()
             })
-        ]);
+        ], CurrentPrices {
+            sale_cost: None,
+            // The cost before the update
+            lowest_30_day_cost: Some(Money::from_minor(1323)),
+        });
           assert_eq!(
             serde_json::to_string(&result).unwrap().as_bytes(),
             resp
@@ -379,7 +449,7 @@ async fn test_product_update() {
 #[actix_web::test]
 async fn test_product_delete_is_ok() {
     let pool = establish_connection_test();
-    let mut app = test::init_service(
+    let app = test::init_service(
         App::new()
             .app_data(web::Data::new(pool.clone()))
             .service(actions::product_create)
@@ -392,8 +462,10 @@ async fn test_product_delete_is_ok() {
 			NewCompleteProduct {
 				product: NewProduct {
 					name: "boots".to_string(),
-					cost: 13.23,
-					active: true
+					cost: Money::from_minor(1323),
+					currency: "EUR".to_string(),
+					active: true,
+					compare_at_cost: None
 				},
 				variants: vec![
 					NewVariantValue {
@@ -404,22 +476,25 @@ async fn test_product_delete_is_ok() {
 							Some(12.to_string()),
 						]
 					}
-				]
+				],
+				sku_matrix: None,
 			};
 
 		let req = test::TestRequest::post().set_json(&body).uri("/products").to_request();
-		let resp = test::call_service(&mut app, req).await;
+		let resp = test::call_service(&app, req).await;
 
 		assert!(resp.status().is_success());
 
         let req = test::TestRequest::get().uri("/products?limit=5").to_request();
-        let resp = test::call_and_read_body(&mut app, req).await;
+        let resp = test::call_and_read_body(&app, req).await;
 
-        let result = vec![(Product {
+        let items = vec![ProductResponse::new(Product {
             id: 1,
             name: "boots".to_string(),
-            cost: 13.23,
-            active: true
+            cost: Money::from_minor(1323),
+            currency: "EUR".to_string(),
+            active: true,
+            compare_at_cost: None
         }, vec![
             (ProductVariant {
                 id: 1,
@@ -431,7 +506,8 @@ async fn test_product_delete_is_ok() {
                 id: 1,
                 name: "size".to_string()
             })
-		])];
+		], CurrentPrices::default())];
+        let result = Page { items, next_cursor: None, total: 1 };
 
           assert_eq!(
             serde_json::to_string(&result).unwrap().as_bytes(),
@@ -439,17 +515,1423 @@ async fn test_product_delete_is_ok() {
         );
 
         let req = test::TestRequest::delete().uri("/products/1").to_request();
-        let resp = test::call_service(&mut app, req).await;
+        let resp = test::call_service(&app, req).await;
 
 		assert!(resp.status().is_success());
 
         let req = test::TestRequest::get().uri("/products?limit=5").to_request();
-        let resp = test::call_and_read_body(&mut app, req).await;
+        let resp = test::call_and_read_body(&app, req).await;
 
-        let result: Vec<Product> = vec![];
+        let result: Page<Product> = Page { items: vec![], next_cursor: None, total: 0 };
 
         assert_eq!(
             serde_json::to_string(&result).unwrap().as_bytes(),
             resp
         );
-    }
\ No newline at end of file
+    }
+#[actix_web::test]
+async fn test_product_show_not_found() {
+    let pool = establish_connection_test();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .service(actions::product_show)
+            .service(actions::product_delete),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/products/42").to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+    let body: ErrorBody = test::read_body_json(resp).await;
+    assert_eq!(
+        body,
+        ErrorBody {
+            code: "not_found".to_string(),
+            message: "product 42 not found".to_string(),
+            errors: vec![],
+        }
+    );
+
+    let req = test::TestRequest::delete().uri("/products/42").to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn test_product_payload_validation() {
+    let pool = establish_connection_test();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .service(actions::product_create)
+            .service(actions::product_list)
+            .service(actions::product_update),
+    )
+    .await;
+
+    let body = serde_json::json!({
+        "product": { "name": "  ", "cost": "-1.50", "active": true },
+        "variants": [
+            { "variant": { "name": "size" }, "values": ["42", "43"] },
+            { "variant": { "name": "" }, "values": ["black", "black"] }
+        ]
+    });
+    let req = test::TestRequest::post().set_json(&body).uri("/products").to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+    let body: ErrorBody = test::read_body_json(resp).await;
+    assert_eq!(body.code, "validation_failed");
+    let pointers = body.errors.iter().map(|error| error.pointer.as_str()).collect::<Vec<_>>();
+    assert_eq!(
+        pointers,
+        vec!["/product/cost", "/product/name", "/variants/1/values", "/variants/1/variant/name"]
+    );
+
+    let req = test::TestRequest::get().uri("/products").to_request();
+    let page: Page<ProductResponse> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(page.total, 0);
+
+    let body = serde_json::json!({
+        "product": { "name": "boots", "cost": "13.23", "active": true },
+        "variants": [{
+            "variant": { "id": null, "name": " " },
+            "product_variant": { "id": null, "variant_id": null, "product_id": 1, "value": "black" }
+        }]
+    });
+    let req = test::TestRequest::put().set_json(&body).uri("/products/1").to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+    let body: ErrorBody = test::read_body_json(resp).await;
+    assert_eq!(body.errors.len(), 1);
+    assert_eq!(body.errors[0].pointer, "/variants/0/variant/name");
+    assert_eq!(body.errors[0].message, "must not be blank");
+
+    // Variant values must be values of the product, each given once
+    for (name, value) in [("boots", "42"), ("sandals", "36")] {
+        let body = serde_json::json!({
+            "product": { "name": name, "cost": "13.23", "active": true },
+            "variants": [{ "variant": { "name": "size" }, "values": [value] }]
+        });
+        let req = test::TestRequest::post().set_json(&body).uri("/products").to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+    }
+    let update = |product_variants: serde_json::Value| {
+        let body = serde_json::json!({
+            "product": { "name": "boots", "cost": "13.23", "active": true },
+            "variants": product_variants
+        });
+        test::TestRequest::put().set_json(&body).uri("/products/1").to_request()
+    };
+    let black = serde_json::json!({
+        "variant": { "id": null, "name": "colour" },
+        "product_variant": { "id": null, "variant_id": null, "product_id": 1, "value": "black" }
+    });
+    let resp = test::call_service(&app, update(serde_json::json!([black, black]))).await;
+    assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+    let body: ErrorBody = test::read_body_json(resp).await;
+    assert_eq!(body.errors[0].pointer, "");
+    assert_eq!(body.errors[0].message, "variants must not contain duplicate values");
+
+    let size = |id: Option<i32>, product_id: i32, value: &str| {
+        serde_json::json!([{
+            "variant": null,
+            "product_variant": { "id": id, "variant_id": 1, "product_id": product_id, "value": value }
+        }])
+    };
+    let resp = test::call_service(&app, update(size(None, 2, "44"))).await;
+    assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+    let resp = test::call_service(&app, update(size(Some(2), 1, "44"))).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+    let resp = test::call_service(&app, update(size(None, 1, "42"))).await;
+    assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+    let resp = test::call_service(&app, update(size(Some(1), 1, "43"))).await;
+    assert!(resp.status().is_success());
+}
+
+#[actix_web::test]
+async fn test_product_list_pagination() {
+    let pool = establish_connection_test();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .service(actions::product_create)
+            .service(actions::product_list)
+            .service(actions::product_search),
+    )
+    .await;
+
+    for name in ["Boots", "High Heels", "Running Shoes", "Tennis Shoes", "Hiking Boots"] {
+        let body = NewCompleteProduct {
+            product: NewProduct {
+                name: name.to_string(),
+                cost: Money::from_minor(1000),
+                currency: "EUR".to_string(),
+                active: true,
+                compare_at_cost: None,
+            },
+            variants: vec![],
+            sku_matrix: None,
+        };
+        let req = test::TestRequest::post().set_json(&body).uri("/products").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+    }
+
+    let req = test::TestRequest::get().uri("/products?limit=2").to_request();
+    let page: Page<ProductResponse> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(page.total, 5);
+    assert_eq!(page.items.iter().map(|p| p.id).collect::<Vec<_>>(), vec![1, 2]);
+    assert_eq!(page.next_cursor, Some(Cursor { id: 2, key: None }.encode()));
+
+    let uri = format!("/products?limit=2&cursor={}", page.next_cursor.unwrap());
+    let req = test::TestRequest::get().uri(&uri).to_request();
+    let page: Page<ProductResponse> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(page.items.iter().map(|p| p.id).collect::<Vec<_>>(), vec![3, 4]);
+
+    let req = test::TestRequest::get().uri("/products?limit=2&offset=4").to_request();
+    let page: Page<ProductResponse> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(page.items.iter().map(|p| p.id).collect::<Vec<_>>(), vec![5]);
+    assert_eq!(page.next_cursor, None);
+
+    let req = test::TestRequest::get().uri("/products/search?search=Boots&limit=1").to_request();
+    let page: Page<SearchHit> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(page.total, 2);
+    assert_eq!(page.items.iter().map(|hit| hit.product.id).collect::<Vec<_>>(), vec![1]);
+    let uri = format!("/products/search?search=Boots&limit=1&cursor={}", page.next_cursor.unwrap());
+    let req = test::TestRequest::get().uri(&uri).to_request();
+    let page: Page<SearchHit> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(page.items.iter().map(|hit| hit.product.id).collect::<Vec<_>>(), vec![5]);
+    assert_eq!(page.next_cursor, None);
+
+    let req = test::TestRequest::get().uri("/products?cursor=not-a-cursor").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[actix_web::test]
+async fn test_product_list_filter_and_sort() {
+    let pool = establish_connection_test();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .service(actions::product_create)
+            .service(actions::product_list),
+    )
+    .await;
+
+    let shoes = vec![
+        ("Boots", 1400, true),
+        ("High Heels", 1923, true),
+        ("Running Shoes", 2190, false),
+        ("Hiking Boots", 1872, true),
+        ("Flip Flops", 1050, true),
+    ];
+    for (name, cost, active) in shoes {
+        let body = NewCompleteProduct {
+            product: NewProduct {
+                name: name.to_string(),
+                cost: Money::from_minor(cost),
+                currency: "EUR".to_string(),
+                active,
+                compare_at_cost: None,
+            },
+            variants: vec![],
+            sku_matrix: None,
+        };
+        let req = test::TestRequest::post().set_json(&body).uri("/products").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+    }
+
+    let names = |page: &Page<ProductResponse>| {
+        page.items.iter().map(|p| p.name.clone()).collect::<Vec<_>>()
+    };
+
+    let req = test::TestRequest::get()
+        .uri("/products?active=true&min_cost=11&max_cost=20&sort=cost&order=desc")
+        .to_request();
+    let page: Page<ProductResponse> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(page.total, 3);
+    assert_eq!(names(&page), vec!["High Heels", "Hiking Boots", "Boots"]);
+
+    let req = test::TestRequest::get().uri("/products?name_prefix=h&sort=name").to_request();
+    let page: Page<ProductResponse> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(names(&page), vec!["High Heels", "Hiking Boots"]);
+
+    let req = test::TestRequest::get().uri("/products?sort=name&limit=2").to_request();
+    let page: Page<ProductResponse> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(names(&page), vec!["Boots", "Flip Flops"]);
+    let uri = format!("/products?sort=name&limit=2&cursor={}", page.next_cursor.unwrap());
+    let req = test::TestRequest::get().uri(&uri).to_request();
+    let page: Page<ProductResponse> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(names(&page), vec!["High Heels", "Hiking Boots"]);
+
+    let req = test::TestRequest::get().uri("/products?sort=color").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+    let body: ErrorBody = test::read_body_json(resp).await;
+    assert_eq!(body.code, "validation_failed");
+}
+
+#[actix_web::test]
+async fn test_product_list_variant_filters() {
+    let pool = establish_connection_test();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .service(actions::product_create)
+            .service(actions::product_list),
+    )
+    .await;
+
+    for (name, size, color) in [("Boots", "42", "black"), ("Sneakers", "42", "white"), ("Sandals", "44", "black")] {
+        let body = NewCompleteProduct {
+            product: NewProduct {
+                name: name.to_string(),
+                cost: Money::from_minor(1000),
+                currency: "EUR".to_string(),
+                active: true,
+                compare_at_cost: None,
+            },
+            variants: vec![
+                NewVariantValue {
+                    variant: NewVariant { name: "size".to_string() },
+                    values: vec![Some(size.to_string())],
+                },
+                NewVariantValue {
+                    variant: NewVariant { name: "color".to_string() },
+                    values: vec![Some(color.to_string())],
+                },
+            ],
+            sku_matrix: None,
+        };
+        let req = test::TestRequest::post().set_json(&body).uri("/products").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+    }
+
+    let req = test::TestRequest::get()
+        .uri("/products?variant.size=42&variant.color=black&variant.color=brown")
+        .to_request();
+    let page: Page<ProductResponse> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(page.total, 1);
+    assert_eq!(page.items[0].name, "Boots");
+
+    let req = test::TestRequest::get().uri("/products?variant.=42").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[actix_web::test]
+async fn test_product_facets() {
+    let pool = establish_connection_test();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .service(actions::product_create)
+            .service(actions::product_facets),
+    )
+    .await;
+
+    for (name, size) in [("Boots", "42"), ("Sneakers", "42"), ("Sandals", "44")] {
+        let body = NewCompleteProduct {
+            product: NewProduct {
+                name: name.to_string(),
+                cost: Money::from_minor(1000),
+                currency: "EUR".to_string(),
+                active: true,
+                compare_at_cost: None,
+            },
+            variants: vec![NewVariantValue {
+                variant: NewVariant { name: "size".to_string() },
+                values: vec![Some(size.to_string())],
+            }],
+            sku_matrix: None,
+        };
+        let req = test::TestRequest::post().set_json(&body).uri("/products").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+    }
+
+    let req = test::TestRequest::get().uri("/products/facets?variant.size=42").to_request();
+    let resp = test::call_and_read_body(&app, req).await;
+
+    assert_eq!(
+        web::Bytes::from_static(
+            b"[{\"variant_id\":1,\"name\":\"size\",\"values\":[{\"value\":\"42\",\"count\":2},{\"value\":\"44\",\"count\":1}]}]"
+        ),
+        resp
+    );
+}
+
+#[actix_web::test]
+async fn test_product_fuzzy_search_and_suggest() {
+    let pool = establish_connection_test();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .service(actions::product_create)
+            .service(actions::product_search)
+            .service(actions::product_suggest),
+    )
+    .await;
+
+    for name in ["Canvas Sneakers", "Running Shoes"] {
+        let body = NewCompleteProduct {
+            product: NewProduct {
+                name: name.to_string(),
+                cost: Money::from_minor(1000),
+                currency: "EUR".to_string(),
+                active: true,
+                compare_at_cost: None,
+            },
+            variants: vec![],
+            sku_matrix: None,
+        };
+        let req = test::TestRequest::post().set_json(&body).uri("/products").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+    }
+
+    let req = test::TestRequest::get().uri("/products/search?search=snaekers&fuzzy=true").to_request();
+    let page: Page<SearchHit> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(page.total, 1);
+    assert_eq!(page.items[0].product.name, "Canvas Sneakers");
+
+    let req = test::TestRequest::get().uri("/products/suggest?q=run").to_request();
+    let resp = test::call_and_read_body(&app, req).await;
+    assert_eq!(
+        web::Bytes::from_static(b"[{\"product_id\":2,\"name\":\"Running Shoes\"}]"),
+        resp
+    );
+}
+
+#[actix_web::test]
+async fn test_synonyms_crud_and_search() {
+    let pool = establish_connection_test();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .service(actions::product_create)
+            .service(actions::product_search)
+            .service(actions::synonyms::synonym_group_create)
+            .service(actions::synonyms::synonym_group_list)
+            .service(actions::synonyms::synonym_group_show)
+            .service(actions::synonyms::synonym_group_update)
+            .service(actions::synonyms::synonym_group_delete),
+    )
+    .await;
+
+    let body = NewCompleteProduct {
+        product: NewProduct {
+            name: "Leather Trainers".to_string(),
+            cost: Money::from_minor(1000),
+            currency: "EUR".to_string(),
+            active: true,
+            compare_at_cost: None,
+        },
+        variants: vec![],
+        sku_matrix: None,
+    };
+    let req = test::TestRequest::post().set_json(&body).uri("/products").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    let req = test::TestRequest::post()
+        .set_json(&FormSynonymGroup { terms: vec!["trainers".to_string(), "sneakers".to_string()] })
+        .uri("/synonyms")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let group: SynonymGroupTerms = test::read_body_json(resp).await;
+    assert_eq!(group.terms, vec!["trainers", "sneakers"]);
+
+    let req = test::TestRequest::get().uri("/products/search?search=sneakers").to_request();
+    let page: Page<SearchHit> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(page.items[0].product.name, "Leather Trainers");
+
+    let req = test::TestRequest::post()
+        .set_json(&FormSynonymGroup { terms: vec!["sneakers".to_string(), "kicks".to_string()] })
+        .uri("/synonyms")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CONFLICT);
+
+    let req = test::TestRequest::put()
+        .set_json(&FormSynonymGroup { terms: vec!["trainers".to_string(), "kicks".to_string()] })
+        .uri(&format!("/synonyms/{}", group.id))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    let req = test::TestRequest::get().uri("/synonyms").to_request();
+    let groups: Vec<SynonymGroupTerms> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(
+        groups,
+        vec![SynonymGroupTerms { id: group.id, terms: vec!["kicks".to_string(), "trainers".to_string()] }]
+    );
+
+    let req = test::TestRequest::delete().uri(&format!("/synonyms/{}", group.id)).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    let req = test::TestRequest::get().uri(&format!("/synonyms/{}", group.id)).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn test_product_prices_in_currency() {
+    let pool = establish_connection_test();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .service(actions::product_create)
+            .service(actions::product_list)
+            .service(actions::product_show)
+            .service(actions::prices::product_price_list)
+            .service(actions::prices::product_price_set)
+            .service(actions::prices::exchange_rate_list)
+            .service(actions::prices::exchange_rate_set)
+            .service(actions::prices::exchange_rate_delete),
+    )
+    .await;
+
+    let body = NewCompleteProduct {
+        product: NewProduct {
+            name: "Boots".to_string(),
+            cost: Money::from_minor(2000),
+            currency: "EUR".to_string(),
+            active: true,
+            compare_at_cost: None,
+        },
+        variants: vec![],
+        sku_matrix: None,
+    };
+    let req = test::TestRequest::post().set_json(&body).uri("/products").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    let req = test::TestRequest::get().uri("/products/1?currency=GBP").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+
+    let req = test::TestRequest::put()
+        .set_json(serde_json::json!({ "rate": "0.8563" }))
+        .uri("/exchange-rates/EUR/GBP")
+        .to_request();
+    let resp = test::call_and_read_body(&app, req).await;
+    assert_eq!(
+        web::Bytes::from_static(b"{\"id\":1,\"base_currency\":\"EUR\",\"quote_currency\":\"GBP\",\"rate\":\"0.856300\"}"),
+        resp
+    );
+
+    let req = test::TestRequest::get().uri("/products/1?currency=gbp").to_request();
+    let product: ProductResponse = test::call_and_read_body_json(&app, req).await;
+    assert_eq!((product.cost, product.currency.as_str()), (Money::from_minor(1713), "GBP"));
+
+    let req = test::TestRequest::put()
+        .set_json(serde_json::json!({ "amount": "17.99" }))
+        .uri("/products/1/prices/GBP")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    let req = test::TestRequest::get().uri("/products?currency=GBP").to_request();
+    let page: Page<ProductResponse> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(page.items[0].cost, Money::from_minor(1799));
+    let req = test::TestRequest::get().uri("/products").to_request();
+    let page: Page<ProductResponse> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!((page.items[0].cost, page.items[0].currency.as_str()), (Money::from_minor(2000), "EUR"));
+
+    let req = test::TestRequest::put()
+        .set_json(serde_json::json!({ "rate": "0" }))
+        .uri("/exchange-rates/EUR/USD")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+
+    let req = test::TestRequest::get().uri("/products?currency=euro").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[actix_web::test]
+async fn test_product_sales() {
+    let pool = establish_connection_test();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .service(actions::product_create)
+            .service(actions::product_search)
+            .service(actions::prices::product_sale_create)
+            .service(actions::prices::product_sale_list)
+            .service(actions::prices::product_price_history),
+    )
+    .await;
+
+    let body = NewCompleteProduct {
+        product: NewProduct {
+            name: "Boots".to_string(),
+            cost: Money::from_minor(2000),
+            currency: "EUR".to_string(),
+            active: true,
+            compare_at_cost: Some(Money::from_minor(2500)),
+        },
+        variants: vec![],
+        sku_matrix: None,
+    };
+    let req = test::TestRequest::post().set_json(&body).uri("/products").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({
+            "cost": "15.00",
+            "starts_at": "2000-01-01T00:00:00",
+            "ends_at": "2999-01-01T00:00:00"
+        }))
+        .uri("/products/1/sales")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+
+    let req = test::TestRequest::get().uri("/products/search?search=boots").to_request();
+    let page: Page<SearchHit> = test::call_and_read_body_json(&app, req).await;
+    let product = &page.items[0].product;
+    assert_eq!(product.effective_cost, Money::from_minor(1500));
+    assert_eq!(product.compare_at_cost, Some(Money::from_minor(2500)));
+
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({
+            "cost": "15.00",
+            "starts_at": "2030-01-01T00:00:00",
+            "ends_at": "2020-01-01T00:00:00"
+        }))
+        .uri("/products/1/sales")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+    let body: ErrorBody = test::read_body_json(resp).await;
+    assert_eq!(body.errors[0].pointer, "");
+
+    let req = test::TestRequest::get().uri("/products/1/sales").to_request();
+    let resp = test::call_and_read_body(&app, req).await;
+    assert_eq!(
+        web::Bytes::from_static(
+            b"[{\"id\":1,\"product_id\":1,\"cost\":\"15.00\",\"starts_at\":\"2000-01-01T00:00:00\",\"ends_at\":\"2999-01-01T00:00:00\"}]"
+        ),
+        resp
+    );
+
+    let req = test::TestRequest::get().uri("/products/1/price-history").to_request();
+    let history: Vec<ProductPriceChange> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(history.len(), 1);
+    assert_eq!((history[0].cost, history[0].currency.as_str()), (Some(Money::from_minor(2000)), "EUR"));
+}
+
+#[actix_web::test]
+async fn test_skus_crud() {
+    let pool = establish_connection_test();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .service(actions::product_create)
+            .service(actions::skus::sku_create)
+            .service(actions::skus::sku_list)
+            .service(actions::skus::sku_show)
+            .service(actions::skus::sku_update)
+            .service(actions::skus::sku_delete),
+    )
+    .await;
+
+    let body = NewCompleteProduct {
+        product: NewProduct {
+            name: "Boots".to_string(),
+            cost: Money::from_minor(2000),
+            currency: "EUR".to_string(),
+            active: true,
+            compare_at_cost: None,
+        },
+        variants: vec![NewVariantValue {
+            variant: NewVariant { name: "size".to_string() },
+            values: vec![Some("42".to_string()), Some("43".to_string())],
+        }],
+        sku_matrix: None,
+    };
+    let req = test::TestRequest::post().set_json(&body).uri("/products").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({ "code": "BOOT-42", "stock": 3, "product_variant_ids": [1] }))
+        .uri("/products/1/skus")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let resp = test::read_body(resp).await;
+    assert_eq!(
+        web::Bytes::from_static(b"{\"id\":1,\"product_id\":1,\"code\":\"BOOT-42\",\"stock\":3,\"allow_backorder\":false,\"price\":null,\"options\":[{\"product_variant_id\":1,\"variant_id\":1,\"name\":\"size\",\"value\":\"42\"}]}"),
+        resp
+    );
+
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({ "code": "BOOT-42", "stock": 1, "product_variant_ids": [2] }))
+        .uri("/products/1/skus")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CONFLICT);
+
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({ "code": " ", "stock": -1, "product_variant_ids": [2] }))
+        .uri("/products/1/skus")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+    let body: ErrorBody = test::read_body_json(resp).await;
+    let pointers = body.errors.iter().map(|error| error.pointer.as_str()).collect::<Vec<_>>();
+    assert_eq!(pointers, vec!["/code", "/stock"]);
+
+    // The stock moves at a location instead
+    let req = test::TestRequest::put()
+        .set_json(serde_json::json!({ "code": "BOOT-42", "stock": 0, "product_variant_ids": [1] }))
+        .uri("/skus/1")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+
+    let req = test::TestRequest::put()
+        .set_json(serde_json::json!({ "code": "BOOT-42", "price": "18.00", "product_variant_ids": [1] }))
+        .uri("/skus/1")
+        .to_request();
+    let sku: SkuResponse = test::call_and_read_body_json(&app, req).await;
+    assert_eq!((sku.stock, sku.price), (3, Some(Money::from_minor(1800))));
+
+    let req = test::TestRequest::get().uri("/products/1/skus").to_request();
+    let skus: Vec<SkuResponse> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(skus, vec![sku]);
+
+    // Its ledger is kept for good
+    let req = test::TestRequest::delete().uri("/skus/1").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CONFLICT);
+
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({ "code": "BOOT-43", "stock": 0, "product_variant_ids": [2] }))
+        .uri("/products/1/skus")
+        .to_request();
+    let sku: SkuResponse = test::call_and_read_body_json(&app, req).await;
+    let req = test::TestRequest::delete().uri(&format!("/skus/{}", sku.id)).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let req = test::TestRequest::get().uri(&format!("/skus/{}", sku.id)).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+    assert_stock_consistent(&pool.get().unwrap());
+}
+
+#[actix_web::test]
+async fn test_sku_matrix() {
+    let pool = establish_connection_test();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .service(actions::product_create)
+            .service(actions::product_show)
+            .service(actions::skus::sku_matrix_generate)
+            .service(actions::skus::sku_list),
+    )
+    .await;
+
+    let body = serde_json::json!({
+        "product": { "name": "Boots", "cost": "20.00", "active": true },
+        "variants": [
+            { "variant": { "name": "size" }, "values": ["42", "43"] },
+            { "variant": { "name": "color" }, "values": ["black"] }
+        ],
+        "sku_matrix": { "code_prefix": "BOOT", "stock": 2, "exclude": [{ "size": "43" }] }
+    });
+    let req = test::TestRequest::post().set_json(body.clone()).uri("/products?preview=true").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let resp = test::read_body(resp).await;
+    assert_eq!(
+        web::Bytes::from_static(b"[{\"code\":\"BOOT-42-BLACK\",\"options\":[{\"name\":\"size\",\"value\":\"42\"},{\"name\":\"color\",\"value\":\"black\"}]}]"),
+        resp
+    );
+    let req = test::TestRequest::get().uri("/products/1").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+
+    let req = test::TestRequest::post().set_json(body).uri("/products").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let req = test::TestRequest::get().uri("/products/1/skus").to_request();
+    let skus: Vec<SkuResponse> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(skus.iter().map(|sku| sku.code.as_str()).collect::<Vec<_>>(), vec!["BOOT-42-BLACK"]);
+
+    let matrix = serde_json::json!({ "code_prefix": "BOOT", "price": "25.00" });
+    let req = test::TestRequest::post()
+        .set_json(matrix.clone())
+        .uri("/products/1/skus/matrix?preview=true")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let req = test::TestRequest::post().set_json(matrix).uri("/products/1/skus/matrix").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let skus: Vec<SkuResponse> = test::read_body_json(resp).await;
+    assert_eq!(
+        skus.iter().map(|sku| (sku.code.as_str(), sku.price)).collect::<Vec<_>>(),
+        vec![("BOOT-43-BLACK", Some(Money::from_minor(2500)))]
+    );
+
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({ "code_prefix": "BOOT", "exclude": [{ "width": "wide" }] }))
+        .uri("/products/1/skus/matrix")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[actix_web::test]
+async fn test_inventory_ledger() {
+    let pool = establish_connection_test();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .service(actions::product_create)
+            .service(actions::skus::sku_create)
+            .service(actions::inventory::inventory_adjust)
+            .service(actions::inventory::inventory_list),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({ "product": { "name": "Boots", "cost": "20.00", "active": true }, "variants": [] }))
+        .uri("/products")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({ "code": "BOOT", "stock": 2, "product_variant_ids": [] }))
+        .uri("/products/1/skus")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({ "reason": "receipt", "quantity": 3, "note": "delivery 42" }))
+        .uri("/skus/1/inventory")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let movement: InventoryMovement = test::read_body_json(resp).await;
+    assert_eq!((movement.balance, movement.note.as_deref()), (5, Some("delivery 42")));
+
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({ "reason": "sale", "quantity": 3 }))
+        .uri("/skus/1/inventory")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({ "reason": "sale", "quantity": -6 }))
+        .uri("/skus/1/inventory")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CONFLICT);
+
+    let req = test::TestRequest::get().uri("/skus/1/inventory").to_request();
+    let movements: Vec<InventoryMovement> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(
+        movements.iter().map(|movement| (movement.reason, movement.quantity)).collect::<Vec<_>>(),
+        vec![(MovementReason::Correction, 2), (MovementReason::Receipt, 3)]
+    );
+}
+
+#[actix_web::test]
+async fn test_locations_and_availability() {
+    let pool = establish_connection_test();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .service(actions::product_create)
+            .service(actions::product_show)
+            .service(actions::skus::sku_create)
+            .service(actions::inventory::transfer_create)
+            .service(actions::locations::location_create)
+            .service(actions::locations::location_list)
+            .service(actions::locations::location_delete)
+            .service(actions::locations::sku_stock),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({ "name": "Main street shop", "kind": "store" }))
+        .uri("/locations")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let req = test::TestRequest::get().uri("/locations").to_request();
+    let resp = test::call_and_read_body(&app, req).await;
+    assert_eq!(
+        web::Bytes::from_static(
+            b"[{\"id\":1,\"name\":\"Central warehouse\",\"kind\":\"warehouse\"},{\"id\":2,\"name\":\"Main street shop\",\"kind\":\"store\"}]"
+        ),
+        resp
+    );
+
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({ "product": { "name": "Boots", "cost": "20.00", "active": true }, "variants": [] }))
+        .uri("/products")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({ "code": "BOOT", "stock": 4, "product_variant_ids": [] }))
+        .uri("/products/1/skus")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({ "from_location_id": 1, "to_location_id": 1, "quantity": 1 }))
+        .uri("/skus/1/transfers")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({ "from_location_id": 1, "to_location_id": 2, "quantity": 1 }))
+        .uri("/skus/1/transfers")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+
+    let req = test::TestRequest::get().uri("/products/1?availability=total").to_request();
+    let product: ProductResponse = test::call_and_read_body_json(&app, req).await;
+    let availability = product.availability.unwrap();
+    assert_eq!((availability.stock, availability.locations), (4, None));
+    let req = test::TestRequest::get().uri("/skus/1/stock").to_request();
+    let resp = test::call_and_read_body(&app, req).await;
+    assert_eq!(
+        web::Bytes::from_static(
+            b"[{\"location_id\":1,\"name\":\"Central warehouse\",\"stock\":3},{\"location_id\":2,\"name\":\"Main street shop\",\"stock\":1}]"
+        ),
+        resp
+    );
+    let req = test::TestRequest::get().uri("/products/1?availability=shop").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+
+    let req = test::TestRequest::delete().uri("/locations/2").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CONFLICT);
+}
+
+#[actix_web::test]
+async fn test_cart() {
+    use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+    use actix_web::cookie::Key;
+    use shoe_store::db::responses::CartResponse;
+
+    let pool = establish_connection_test();
+    let app = test::init_service(
+        App::new()
+            .wrap(SessionMiddleware::new(CookieSessionStore::default(), Key::generate()))
+            .app_data(web::Data::new(pool.clone()))
+            .service(actions::product_create)
+            .service(actions::carts::cart_show)
+            .service(actions::carts::cart_line_add)
+            .service(actions::carts::cart_line_update)
+            .service(actions::carts::cart_line_remove)
+            .service(actions::carts::cart_clear),
+    )
+    .await;
+
+    for (name, active) in [("Boots", true), ("Sandals", false)] {
+        let req = test::TestRequest::post()
+            .set_json(serde_json::json!({ "product": { "name": name, "cost": "20.00", "active": active }, "variants": [] }))
+            .uri("/products")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+    }
+
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({ "product_id": 1, "quantity": 2 }))
+        .uri("/cart/lines")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let cookie = resp.response().cookies().next().expect("no session cookie").into_owned();
+    let cart: CartResponse = test::read_body_json(resp).await;
+    assert_eq!((cart.item_count, cart.total), (2, Money::from_minor(4000)));
+
+    let req = test::TestRequest::post()
+        .cookie(cookie.clone())
+        .set_json(serde_json::json!({ "product_id": 2, "quantity": 1 }))
+        .uri("/cart/lines")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+
+    let req = test::TestRequest::put()
+        .cookie(cookie.clone())
+        .set_json(serde_json::json!({ "quantity": 3 }))
+        .uri("/cart/lines/1")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let cookie = resp.response().cookies().next().expect("no session cookie").into_owned();
+    let req = test::TestRequest::get().cookie(cookie.clone()).uri("/cart").to_request();
+    let resp = test::call_and_read_body(&app, req).await;
+    assert_eq!(
+        web::Bytes::from_static(
+            b"{\"currency\":\"EUR\",\"lines\":[{\"id\":1,\"product_id\":1,\"name\":\"Boots\",\"sku_id\":null,\"options\":[],\"quantity\":3,\"unit_price\":\"20.00\",\"total\":\"60.00\",\"discount\":\"0.00\",\"available\":true}],\"item_count\":3,\"subtotal\":\"60.00\",\"promo_code\":null,\"discount\":\"0.00\",\"total\":\"60.00\"}"
+        ),
+        resp
+    );
+
+    let req = test::TestRequest::delete().cookie(cookie.clone()).uri("/cart/lines/7").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+    let req = test::TestRequest::delete().cookie(cookie).uri("/cart").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let cookie = resp.response().cookies().next().expect("no session cookie").into_owned();
+    let req = test::TestRequest::get().cookie(cookie).uri("/cart").to_request();
+    let cart: CartResponse = test::call_and_read_body_json(&app, req).await;
+    assert!(cart.lines.is_empty());
+}
+
+#[actix_web::test]
+async fn test_checkout() {
+    use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+    use actix_web::cookie::Key;
+    use shoe_store::db::models::OrderEvent;
+    use shoe_store::db::order_status::OrderStatus;
+    use shoe_store::db::responses::{CartResponse, OrderResponse};
+    use shoe_store::payments::{mock::MockGateway, PaymentGateway};
+    use std::sync::Arc;
+
+    let pool = establish_connection_test();
+    let gateway: Arc<dyn PaymentGateway> = Arc::new(MockGateway::new("secret"));
+    let app = test::init_service(
+        App::new()
+            .wrap(SessionMiddleware::new(CookieSessionStore::default(), Key::generate()))
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::from(gateway))
+            .service(actions::product_create)
+            .service(actions::carts::cart_show)
+            .service(actions::carts::cart_line_add)
+            .service(actions::orders::checkout)
+            .service(actions::orders::order_show)
+            .service(actions::orders::order_transition)
+            .service(actions::orders::order_event_list),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({
+            "product": { "name": "Boots", "cost": "20.00", "active": true },
+            "variants": [{ "variant": { "name": "size" }, "values": ["42"] }]
+        }))
+        .uri("/products")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({ "product_id": 1, "product_variant_ids": [1], "quantity": 2 }))
+        .uri("/cart/lines")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let cookie = resp.response().cookies().next().expect("no session cookie").into_owned();
+
+    let req = test::TestRequest::post().cookie(cookie).uri("/checkout").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    assert_eq!(resp.headers().get(http::header::LOCATION).unwrap(), "/orders/1");
+    let cookie = resp.response().cookies().next().expect("no session cookie").into_owned();
+    let order: OrderResponse = test::read_body_json(resp).await;
+    assert_eq!((order.item_count, order.total), (2, Money::from_minor(4000)));
+
+    let req = test::TestRequest::get().cookie(cookie.clone()).uri("/orders/1").to_request();
+    let shown: OrderResponse = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(shown, order);
+    // Only the session that placed the order can read it
+    let req = test::TestRequest::get().uri("/orders/1").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+    let req = test::TestRequest::get().uri("/orders/1/events").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+    let req = test::TestRequest::get().cookie(cookie.clone()).uri("/cart").to_request();
+    let cart: CartResponse = test::call_and_read_body_json(&app, req).await;
+    assert!(cart.lines.is_empty());
+    let req = test::TestRequest::post().cookie(cookie.clone()).uri("/checkout").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({ "transition": "ship" }))
+        .uri("/orders/1/transitions")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CONFLICT);
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({ "transition": "pay" }))
+        .uri("/orders/1/transitions")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({ "transition": "cancel", "note": "changed my mind" }))
+        .uri("/orders/1/transitions")
+        .to_request();
+    let cancelled: OrderResponse = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(cancelled.status, OrderStatus::Cancelled);
+    let req = test::TestRequest::get().cookie(cookie).uri("/orders/1/events").to_request();
+    let events: Vec<OrderEvent> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(
+        events.iter().map(|event| (event.to_status, event.note.as_deref())).collect::<Vec<_>>(),
+        vec![(OrderStatus::Pending, None), (OrderStatus::Cancelled, Some("changed my mind"))]
+    );
+}
+
+#[actix_web::test]
+async fn test_payments() {
+    use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+    use actix_web::cookie::Key;
+    use shoe_store::db::models::{Payment, PaymentStatus, Refund, RefundStatus};
+    use shoe_store::db::order_status::OrderStatus;
+    use shoe_store::db::responses::OrderResponse;
+    use shoe_store::payments::{mock::MockGateway, PaymentGateway};
+    use std::sync::Arc;
+
+    let pool = establish_connection_test();
+    let gateway: Arc<dyn PaymentGateway> = Arc::new(MockGateway::new("secret"));
+    let app = test::init_service(
+        App::new()
+            .wrap(SessionMiddleware::new(CookieSessionStore::default(), Key::generate()))
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::from(gateway))
+            .service(actions::product_create)
+            .service(actions::carts::cart_line_add)
+            .service(actions::orders::checkout)
+            .service(actions::orders::order_show)
+            .service(actions::payments::payment_create)
+            .service(actions::payments::payment_list)
+            .service(actions::payments::payment_webhook)
+            .service(actions::payments::payment_show)
+            .service(actions::payments::payment_refund)
+            .service(actions::payments::payment_refund_list),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({ "product": { "name": "Laces", "cost": "5.00", "active": true }, "variants": [] }))
+        .uri("/products")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({ "product_id": 1, "product_variant_ids": [], "quantity": 1 }))
+        .uri("/cart/lines")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let cookie = resp.response().cookies().next().expect("no session cookie").into_owned();
+    let req = test::TestRequest::post().cookie(cookie).uri("/checkout").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let cookie = resp.response().cookies().next().expect("no session cookie").into_owned();
+
+    let req = test::TestRequest::post()
+        .cookie(cookie.clone())
+        .set_json(serde_json::json!({ "source": " " }))
+        .uri("/orders/1/payments")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+    let req = test::TestRequest::post()
+        .cookie(cookie.clone())
+        .set_json(serde_json::json!({ "source": "mock_3ds" }))
+        .uri("/orders/1/payments")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    assert_eq!(resp.headers().get(http::header::LOCATION).unwrap(), "/payments/1");
+    let payment: Payment = test::read_body_json(resp).await;
+    assert_eq!(payment.status, PaymentStatus::RequiresAction);
+
+    let webhook = serde_json::json!({ "reference": payment.reference, "status": "authorized" });
+    let req = test::TestRequest::post().set_json(webhook.clone()).uri("/payments/webhook").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+    let req = test::TestRequest::post()
+        .insert_header((actions::payments::SIGNATURE_HEADER, "secret"))
+        .set_json(webhook)
+        .uri("/payments/webhook")
+        .to_request();
+    let payment: Payment = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(payment.status, PaymentStatus::Captured);
+    let req = test::TestRequest::get().cookie(cookie.clone()).uri("/orders/1").to_request();
+    let order: OrderResponse = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(order.status, OrderStatus::Paid);
+
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({ "amount": "1.50" }))
+        .uri("/payments/1/refunds")
+        .to_request();
+    let payment: Payment = test::call_and_read_body_json(&app, req).await;
+    assert_eq!((payment.status, payment.refunded), (PaymentStatus::Captured, Money::from_minor(150)));
+    let req = test::TestRequest::post().set_json(serde_json::json!({})).uri("/payments/1/refunds").to_request();
+    let payment: Payment = test::call_and_read_body_json(&app, req).await;
+    assert_eq!((payment.status, payment.refunded), (PaymentStatus::Refunded, Money::from_minor(500)));
+    let req = test::TestRequest::get().cookie(cookie.clone()).uri("/payments/1").to_request();
+    let shown: Payment = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(shown, payment);
+    let req = test::TestRequest::get().cookie(cookie.clone()).uri("/orders/1/payments").to_request();
+    let payments: Vec<Payment> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(payments, vec![payment]);
+    // Only the session that placed the order can see its payments
+    for uri in ["/payments/1", "/orders/1/payments", "/payments/1/refunds"] {
+        let req = test::TestRequest::get().uri(uri).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+    }
+    let req = test::TestRequest::get().cookie(cookie).uri("/payments/1/refunds").to_request();
+    let refunds: Vec<Refund> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(
+        refunds.iter().map(|refund| (refund.amount, refund.status)).collect::<Vec<_>>(),
+        vec![(Money::from_minor(150), RefundStatus::Succeeded), (Money::from_minor(350), RefundStatus::Succeeded)]
+    );
+}
+
+#[actix_web::test]
+async fn test_returns() {
+    use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+    use actix_web::cookie::Key;
+    use shoe_store::db::models::ReturnStatus;
+    use shoe_store::db::responses::{OrderResponse, ReturnResponse};
+    use shoe_store::payments::{mock::MockGateway, PaymentGateway};
+    use std::sync::Arc;
+
+    let pool = establish_connection_test();
+    let gateway: Arc<dyn PaymentGateway> = Arc::new(MockGateway::new("secret"));
+    let app = test::init_service(
+        App::new()
+            .wrap(SessionMiddleware::new(CookieSessionStore::default(), Key::generate()))
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::from(gateway))
+            .service(actions::product_create)
+            .service(actions::carts::cart_line_add)
+            .service(actions::orders::checkout)
+            .service(actions::orders::order_transition)
+            .service(actions::payments::payment_create)
+            .service(actions::returns::return_create)
+            .service(actions::returns::return_list)
+            .service(actions::returns::return_show)
+            .service(actions::returns::return_approve)
+            .service(actions::returns::return_reject)
+            .service(actions::returns::return_receive)
+            .service(actions::returns::return_refund),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({ "product": { "name": "Boots", "cost": "20.00", "active": true }, "variants": [] }))
+        .uri("/products")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({ "product_id": 1, "product_variant_ids": [], "quantity": 2 }))
+        .uri("/cart/lines")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let cookie = resp.response().cookies().next().expect("no session cookie").into_owned();
+    let req = test::TestRequest::post().cookie(cookie).uri("/checkout").to_request();
+    let resp = test::call_service(&app, req).await;
+    let cookie = resp.response().cookies().next().expect("no session cookie").into_owned();
+    let order: OrderResponse = test::read_body_json(resp).await;
+    let req = test::TestRequest::post()
+        .cookie(cookie.clone())
+        .set_json(serde_json::json!({ "source": "mock_success" }))
+        .uri("/orders/1/payments")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    for transition in ["pack", "ship"] {
+        let req = test::TestRequest::post()
+            .set_json(serde_json::json!({ "transition": transition }))
+            .uri("/orders/1/transitions")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+    }
+
+    let req = test::TestRequest::post()
+        .cookie(cookie.clone())
+        .set_json(serde_json::json!({ "lines": [] }))
+        .uri("/orders/1/returns")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+    let lines = serde_json::json!({
+        "lines": [{ "order_line_id": order.lines[0].id, "quantity": 1, "reason": "too_large" }],
+        "note": "half a size up"
+    });
+    let req = test::TestRequest::post().set_json(&lines).uri("/orders/1/returns").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+    let req = test::TestRequest::post()
+        .cookie(cookie.clone())
+        .set_json(&lines)
+        .uri("/orders/1/returns")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    assert_eq!(resp.headers().get(http::header::LOCATION).unwrap(), "/returns/1");
+    let request: ReturnResponse = test::read_body_json(resp).await;
+    assert_eq!((request.refund, request.currency.as_str()), (Money::from_minor(2000), "EUR"));
+    assert_eq!(request.lines[0].product_name, "Boots");
+
+    let req = test::TestRequest::post().uri("/returns/1/refund").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CONFLICT);
+    let req = test::TestRequest::post().uri("/returns/1/approve").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let req = test::TestRequest::post().set_json(serde_json::json!({})).uri("/returns/1/receive").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let req = test::TestRequest::post().uri("/returns/1/refund").to_request();
+    let refunded: ReturnResponse = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(refunded.status, ReturnStatus::Refunded);
+    let req = test::TestRequest::post().uri("/returns/1/reject").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CONFLICT);
+
+    for uri in ["/returns/1", "/orders/1/returns"] {
+        let req = test::TestRequest::get().uri(uri).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+    }
+    let req = test::TestRequest::get().cookie(cookie.clone()).uri("/returns/1").to_request();
+    let shown: ReturnResponse = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(shown, refunded);
+    let req = test::TestRequest::get().cookie(cookie).uri("/orders/1/returns").to_request();
+    let requests: Vec<ReturnResponse> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(requests, vec![refunded]);
+}
+
+#[actix_web::test]
+async fn test_promo_codes() {
+    use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+    use actix_web::cookie::Key;
+    use shoe_store::db::responses::{CartResponse, OrderResponse, PromoCodeResponse};
+
+    let pool = establish_connection_test();
+    let app = test::init_service(
+        App::new()
+            .wrap(SessionMiddleware::new(CookieSessionStore::default(), Key::generate()))
+            .app_data(web::Data::new(pool.clone()))
+            .service(actions::product_create)
+            .service(actions::carts::cart_line_add)
+            .service(actions::carts::cart_promo_code_apply)
+            .service(actions::carts::cart_promo_code_remove)
+            .service(actions::orders::checkout)
+            .service(actions::promotions::promo_code_create)
+            .service(actions::promotions::promo_code_list)
+            .service(actions::promotions::promo_code_show)
+            .service(actions::promotions::promo_code_update)
+            .service(actions::promotions::promo_code_delete),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({
+            "product": { "name": "Boots", "cost": "40.00", "active": true },
+            "variants": [{ "variant": { "name": "size" }, "values": ["46"] }]
+        }))
+        .uri("/products")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({ "code": "both", "percent_off": 10, "amount_off": "5.00" }))
+        .uri("/promo-codes")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({
+            "code": "big25",
+            "percent_off": 25,
+            "per_customer_limit": 1,
+            "variant_rules": [{ "variant": "size", "min": 46 }]
+        }))
+        .uri("/promo-codes")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    assert_eq!(resp.headers().get(http::header::LOCATION).unwrap(), "/promo-codes/1");
+    let promo_code: PromoCodeResponse = test::read_body_json(resp).await;
+    assert_eq!((promo_code.code.as_str(), promo_code.uses), ("BIG25", 0));
+
+    let req = test::TestRequest::post()
+        .set_json(serde_json::json!({ "product_id": 1, "product_variant_ids": [1], "quantity": 1 }))
+        .uri("/cart/lines")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let cookie = resp.response().cookies().next().expect("no session cookie").into_owned();
+    let req = test::TestRequest::put()
+        .cookie(cookie.clone())
+        .set_json(serde_json::json!({ "code": "summer" }))
+        .uri("/cart/promo-code")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+    let body: ErrorBody = test::read_body_json(resp).await;
+    assert_eq!(body.message, "promo code SUMMER doesn't exist");
+    let req = test::TestRequest::put()
+        .cookie(cookie)
+        .set_json(serde_json::json!({ "code": "big25" }))
+        .uri("/cart/promo-code")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let cookie = resp.response().cookies().next().expect("no session cookie").into_owned();
+    let cart: CartResponse = test::read_body_json(resp).await;
+    assert_eq!((cart.promo_code.as_deref(), cart.discount), (Some("BIG25"), Money::from_minor(1000)));
+
+    let req = test::TestRequest::post().cookie(cookie.clone()).uri("/checkout?email=nobody").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+    let req = test::TestRequest::post().cookie(cookie).uri("/checkout?email=ann@example.com").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::CREATED);
+    let cookie = resp.response().cookies().next().expect("no session cookie").into_owned();
+    let order: OrderResponse = test::read_body_json(resp).await;
+    assert_eq!((order.discount, order.total), (Money::from_minor(1000), Money::from_minor(3000)));
+    let req = test::TestRequest::delete().cookie(cookie).uri("/cart/promo-code").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+
+    let req = test::TestRequest::get().uri("/promo-codes").to_request();
+    let promo_codes: Vec<PromoCodeResponse> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(promo_codes[0].uses, 1);
+    let req = test::TestRequest::delete().uri("/promo-codes/1").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let req = test::TestRequest::get().uri("/promo-codes/1").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
+}
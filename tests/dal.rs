@@ -1,5 +1,12 @@
 use diesel::Connection;
-use shoe_store::db::{dal, models};
+use shoe_store::db::{
+    dal,
+    filters::{ProductFilter, ProductSort},
+    models,
+    money::Money,
+    pagination::PageParams,
+    responses::{CurrentPrices, ProductResponse},
+};
 mod helpers;
 
 #[test]
@@ -32,10 +39,13 @@ fn create_product_test() {
             NewCompleteProduct {
                 product: NewProduct {
                     name: "boots".to_string(),
-                    cost: 13.23,
+                    cost: Money::from_minor(1323),
+                    currency: "EUR".to_string(),
                     active: true,
+                    compare_at_cost: None,
                 },
                 variants: variants.clone(),
+                sku_matrix: None,
             },
             &connection,
         )
@@ -44,10 +54,13 @@ fn create_product_test() {
             NewCompleteProduct {
                 product: NewProduct {
                     name: "high heels".to_string(),
-                    cost: 20.99,
+                    cost: Money::from_minor(2099),
+                    currency: "EUR".to_string(),
                     active: true,
+                    compare_at_cost: None,
                 },
                 variants: variants.clone(),
+                sku_matrix: None,
             },
             &connection,
         )
@@ -56,10 +69,13 @@ fn create_product_test() {
             NewCompleteProduct {
                 product: NewProduct {
                     name: "running shoes".to_string(),
-                    cost: 10.99,
+                    cost: Money::from_minor(1099),
+                    currency: "EUR".to_string(),
                     active: true,
+                    compare_at_cost: None,
                 },
                 variants: variants.clone(),
+                sku_matrix: None,
             },
             &connection,
         )
@@ -119,34 +135,48 @@ fn create_product_test() {
         };
 
         assert_eq!(
-            serde_json::to_string(&list_products(None, &connection).unwrap()).unwrap(),
+            serde_json::to_string(&list_products(
+                ProductFilter::default(),
+                ProductSort::default(),
+                PageParams::default(),
+                &connection
+            ).unwrap().items).unwrap(),
             serde_json::to_string(&vec![
-                (
+                ProductResponse::new(
                     Product {
                         id: 1,
                         name: "boots".to_string(),
-                        cost: 13.23,
-                        active: true
+                        cost: Money::from_minor(1323),
+                        currency: "EUR".to_string(),
+                        active: true,
+                        compare_at_cost: None
                     },
-                    variants_result(0 * variant_values.len() as i32, 1)
+                    variants_result(0, 1),
+                    CurrentPrices::default()
                 ),
-                (
+                ProductResponse::new(
                     Product {
                         id: 2,
                         name: "high heels".to_string(),
-                        cost: 20.99,
-                        active: true
+                        cost: Money::from_minor(2099),
+                        currency: "EUR".to_string(),
+                        active: true,
+                        compare_at_cost: None
                     },
-                    variants_result(1 * variant_values.len() as i32, 2)
+                    variants_result(variant_values.len() as i32, 2),
+                    CurrentPrices::default()
                 ),
-                (
+                ProductResponse::new(
                     Product {
                         id: 3,
                         name: "running shoes".to_string(),
-                        cost: 10.99,
-                        active: true
+                        cost: Money::from_minor(1099),
+                        currency: "EUR".to_string(),
+                        active: true,
+                        compare_at_cost: None
                     },
-                    variants_result(2 * variant_values.len() as i32, 3)
+                    variants_result(2 * variant_values.len() as i32, 3),
+                    CurrentPrices::default()
                 )
             ])
             .unwrap()
@@ -172,8 +202,10 @@ fn show_product_test() {
             NewCompleteProduct {
                 product: NewProduct {
                     name: "boots".to_string(),
-                    cost: 13.23,
+                    cost: Money::from_minor(1323),
+                    currency: "EUR".to_string(),
                     active: true,
+                    compare_at_cost: None,
                 },
                 variants: vec![NewVariantValue {
                     variant: NewVariant {
@@ -186,6 +218,7 @@ fn show_product_test() {
                         Some(18.to_string()),
                     ],
                 }],
+                sku_matrix: None,
             },
             &connection,
         )
@@ -193,12 +226,14 @@ fn show_product_test() {
 
         assert_eq!(
             serde_json::to_string(&show_product(product_id, &connection).unwrap()).unwrap(),
-            serde_json::to_string(&(
+            serde_json::to_string(&ProductResponse::new(
                 Product {
                     id: 1,
                     name: "boots".to_string(),
-                    cost: 13.23,
-                    active: true
+                    cost: Money::from_minor(1323),
+                    currency: "EUR".to_string(),
+                    active: true,
+                    compare_at_cost: None
                 },
                 vec![
                     (
@@ -249,7 +284,8 @@ fn show_product_test() {
                             name: "size".to_string()
                         }
                     )
-                ]
+                ],
+                CurrentPrices::default()
             ))
             .unwrap()
         );
@@ -281,10 +317,13 @@ fn search_products_test() {
             NewCompleteProduct {
                 product: NewProduct {
                     name: "boots".to_string(),
-                    cost: 13.23,
+                    cost: Money::from_minor(1323),
+                    currency: "EUR".to_string(),
                     active: true,
+                    compare_at_cost: None,
                 },
                 variants: variants.clone(),
+                sku_matrix: None,
             },
             &connection,
         )
@@ -293,10 +332,13 @@ fn search_products_test() {
             NewCompleteProduct {
                 product: NewProduct {
                     name: "high heels".to_string(),
-                    cost: 20.99,
+                    cost: Money::from_minor(2099),
+                    currency: "EUR".to_string(),
                     active: true,
+                    compare_at_cost: None,
                 },
                 variants: variants.clone(),
+                sku_matrix: None,
             },
             &connection,
         )
@@ -305,24 +347,36 @@ fn search_products_test() {
             NewCompleteProduct {
                 product: NewProduct {
                     name: "running shoes".to_string(),
-                    cost: 10.99,
+                    cost: Money::from_minor(1099),
+                    currency: "EUR".to_string(),
                     active: true,
+                    compare_at_cost: None,
                 },
                 variants: variants.clone(),
+                sku_matrix: None,
             },
             &connection,
         )
         .unwrap();
 
         assert_eq!(
-            serde_json::to_string(&search_products("shoes".to_string(), &connection).unwrap())
-                .unwrap(),
-            serde_json::to_string(&vec![(
+            serde_json::to_string(
+                &search_products("shoes".to_string(), false, PageParams::default(), &connection)
+                    .unwrap()
+                    .items
+                    .into_iter()
+                    .map(|hit| hit.product)
+                    .collect::<Vec<_>>()
+            )
+            .unwrap(),
+            serde_json::to_string(&vec![ProductResponse::new(
                 Product {
                     id: 3,
                     name: "running shoes".to_string(),
-                    cost: 10.99,
-                    active: true
+                    cost: Money::from_minor(1099),
+                    currency: "EUR".to_string(),
+                    active: true,
+                    compare_at_cost: None
                 },
                 vec![(
                     ProductVariant {
@@ -335,7 +389,8 @@ fn search_products_test() {
                         id: 1,
                         name: "size".to_string(),
                     }
-                )]
+                )],
+                CurrentPrices::default()
             )])
             .unwrap()
         );
@@ -361,8 +416,10 @@ fn update_product_test() {
             NewCompleteProduct {
                 product: NewProduct {
                     name: "boots".to_string(),
-                    cost: 13.23,
+                    cost: Money::from_minor(1323),
+                    currency: "EUR".to_string(),
                     active: true,
+                    compare_at_cost: None,
                 },
                 variants: vec![NewVariantValue {
                     variant: NewVariant {
@@ -375,21 +432,24 @@ fn update_product_test() {
                         Some(18.to_string()),
                     ],
                 }],
+                sku_matrix: None,
             },
             &connection,
         )
         .unwrap();
 
-        let (_, current_product_variants) = show_product(created_product_id, &connection).unwrap();
-        let product_variant = &current_product_variants[0].0;
+        let current_product = show_product(created_product_id, &connection).unwrap();
+        let product_variant = &current_product.variants[0];
 
         update_product(
             created_product_id,
             FormProduct {
                 product: NewProduct {
                     name: "high heels".to_string(),
-                    cost: 14.25,
+                    cost: Money::from_minor(1425),
+                    currency: "EUR".to_string(),
                     active: false,
+                    compare_at_cost: None,
                 },
                 variants: vec![
                     FormProductVariantComplete {
@@ -407,7 +467,7 @@ fn update_product_test() {
                     FormProductVariantComplete {
                         variant: None,
                         product_variant: FormProductVariant {
-                            id: Some(product_variant.id),
+                            id: Some(product_variant.values[0].id),
                             product_id: created_product_id,
                             variant_id: Some(product_variant.variant_id),
                             value: Some(50.to_string()),
@@ -421,12 +481,14 @@ fn update_product_test() {
 
         assert_eq!(
             serde_json::to_string(&show_product(created_product_id, &connection).unwrap()).unwrap(),
-            serde_json::to_string(&(
+            serde_json::to_string(&ProductResponse::new(
                 Product {
                     id: 1,
                     name: "high heels".to_string(),
-                    cost: 14.25,
-                    active: false
+                    cost: Money::from_minor(1425),
+                    currency: "EUR".to_string(),
+                    active: false,
+                    compare_at_cost: None
                 },
                 vec![
                     (
@@ -489,7 +551,11 @@ fn update_product_test() {
                             name: "color".to_string(),
                         }
                     )
-                ]
+                ],
+                CurrentPrices {
+                    sale_cost: None,
+                    lowest_30_day_cost: Some(Money::from_minor(1323)),
+                }
             ))
             .unwrap()
         );
@@ -504,20 +570,22 @@ fn update_product_test() {
 fn delete_product_test() {
     use dal::{create_product, show_product, delete_product};
     use diesel::result::Error;
+    use diesel::{dsl::sql, sql_types::BigInt, RunQueryDsl};
     use helpers::establish_connection_test;
     use models::{
         NewCompleteProduct, NewProduct, NewVariant, NewVariantValue
     };
     let pool = establish_connection_test();
     let connection = pool.get().expect("failed to get db connection");
-    connection.execute("PRAGMA foreign_keys = ON").unwrap();
     connection.test_transaction::<_, Error, _>(|| {
         let created_product_id =
             create_product(NewCompleteProduct {
                 product: NewProduct {
                     name: "boots".to_string(),
-                    cost: 13.23,
-                    active: true
+                    cost: Money::from_minor(1323),
+                    currency: "EUR".to_string(),
+                    active: true,
+                    compare_at_cost: None
                 },
                 variants: vec![
                     NewVariantValue {
@@ -531,13 +599,1838 @@ fn delete_product_test() {
                             Some(18.to_string())
                         ]
                     }
-                ]
+                ],
+                sku_matrix: None,
             }, 
             &connection).unwrap();
         
         delete_product(created_product_id, &connection).unwrap();
+        // the variant values go along with the product
+        let count = format!("(SELECT COUNT(*) FROM products_variants WHERE product_id = {})", created_product_id);
+        let values = diesel::select(sql::<BigInt>(&count)).get_result::<i64>(&connection).unwrap();
+        assert_eq!(values, 0);
         show_product(created_product_id, &connection).unwrap();
 
         Ok(())
     });
-}
\ No newline at end of file
+}
+#[test]
+fn update_missing_product_test() {
+    use dal::update_product;
+    use helpers::establish_connection_test;
+    use models::{FormProduct, NewProduct};
+    use shoe_store::errors::ApiError;
+    let pool = establish_connection_test();
+    let connection = pool.get().expect("failed to get db connection");
+
+    let result = update_product(
+        42,
+        FormProduct {
+            product: NewProduct {
+                name: "boots".to_string(),
+                cost: Money::from_minor(1323),
+                currency: "EUR".to_string(),
+                active: true,
+                compare_at_cost: None,
+            },
+            variants: vec![],
+        },
+        &connection,
+    );
+
+    assert!(matches!(result, Err(ApiError::NotFound(_))));
+}
+
+#[test]
+fn list_products_by_variant_values_test() {
+    use dal::{create_product, list_products};
+    use diesel::result::Error;
+    use helpers::establish_connection_test;
+    use models::{NewCompleteProduct, NewProduct, NewVariant, NewVariantValue};
+    let pool = establish_connection_test();
+    let connection = pool.get().expect("failed to get db connection");
+    connection.test_transaction::<_, Error, _>(|| {
+        let shoes = vec![
+            ("boots", vec!["42", "43"], vec!["black"]),
+            ("sneakers", vec!["42"], vec!["white"]),
+            ("sandals", vec!["44"], vec!["black", "brown"]),
+        ];
+        for (name, sizes, colors) in shoes {
+            let values = |values: Vec<&str>| values.into_iter().map(|v| Some(v.to_string())).collect();
+            create_product(
+                NewCompleteProduct {
+                    product: NewProduct {
+                        name: name.to_string(),
+                        cost: Money::from_minor(1000),
+                        currency: "EUR".to_string(),
+                        active: true,
+                        compare_at_cost: None,
+                    },
+                    variants: vec![
+                        NewVariantValue {
+                            variant: NewVariant { name: "size".to_string() },
+                            values: values(sizes),
+                        },
+                        NewVariantValue {
+                            variant: NewVariant { name: "color".to_string() },
+                            values: values(colors),
+                        },
+                    ],
+                    sku_matrix: None,
+                },
+                &connection,
+            )
+            .unwrap();
+        }
+
+        let list_names = |params: &[(&str, &str)]| {
+            let params = params
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<Vec<_>>();
+            let filter = ProductFilter {
+                variants: ProductFilter::variants_from_params(&params).unwrap(),
+                ..ProductFilter::default()
+            };
+            list_products(filter, ProductSort::default(), PageParams::default(), &connection)
+                .unwrap()
+                .items
+                .into_iter()
+                .map(|product| product.name)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(list_names(&[("variant.size", "42")]), vec!["boots", "sneakers"]);
+        assert_eq!(
+            list_names(&[("variant.size", "42"), ("variant.color", "black")]),
+            vec!["boots"]
+        );
+        assert_eq!(
+            list_names(&[("variant.size", "42"), ("variant.size", "44"), ("variant.color", "black")]),
+            vec!["boots", "sandals"]
+        );
+        assert!(list_names(&[("variant.width", "wide")]).is_empty());
+
+        Ok(())
+    });
+}
+
+#[test]
+fn product_facets_test() {
+    use dal::create_product;
+    use dal::facets::{product_facets, Facet, FacetValue};
+    use diesel::result::Error;
+    use helpers::establish_connection_test;
+    use models::{NewCompleteProduct, NewProduct, NewVariant, NewVariantValue};
+    let pool = establish_connection_test();
+    let connection = pool.get().expect("failed to get db connection");
+    connection.test_transaction::<_, Error, _>(|| {
+        let shoes = vec![
+            ("boots", vec!["42", "43"], vec!["black"]),
+            ("sneakers", vec!["42"], vec!["white"]),
+            ("sandals", vec!["44"], vec!["black"]),
+        ];
+        for (name, sizes, colors) in shoes {
+            let values = |values: Vec<&str>| values.into_iter().map(|v| Some(v.to_string())).collect();
+            create_product(
+                NewCompleteProduct {
+                    product: NewProduct {
+                        name: name.to_string(),
+                        cost: Money::from_minor(1000),
+                        currency: "EUR".to_string(),
+                        active: true,
+                        compare_at_cost: None,
+                    },
+                    variants: vec![
+                        NewVariantValue {
+                            variant: NewVariant { name: "size".to_string() },
+                            values: values(sizes),
+                        },
+                        NewVariantValue {
+                            variant: NewVariant { name: "color".to_string() },
+                            values: values(colors),
+                        },
+                    ],
+                    sku_matrix: None,
+                },
+                &connection,
+            )
+            .unwrap();
+        }
+        let value = |value: &str, count| FacetValue { value: value.to_string(), count };
+
+        assert_eq!(
+            product_facets(ProductFilter::default(), &connection).unwrap(),
+            vec![
+                Facet {
+                    variant_id: 2,
+                    name: "color".to_string(),
+                    values: vec![value("black", 2), value("white", 1)],
+                },
+                Facet {
+                    variant_id: 1,
+                    name: "size".to_string(),
+                    values: vec![value("42", 2), value("43", 1), value("44", 1)],
+                },
+            ]
+        );
+
+        let mut filter = ProductFilter::default();
+        filter.variants.insert("color".to_string(), vec!["black".to_string()]);
+        assert_eq!(
+            product_facets(filter, &connection).unwrap(),
+            vec![
+                Facet {
+                    variant_id: 2,
+                    name: "color".to_string(),
+                    values: vec![value("black", 2), value("white", 1)],
+                },
+                Facet {
+                    variant_id: 1,
+                    name: "size".to_string(),
+                    values: vec![value("42", 1), value("43", 1), value("44", 1)],
+                },
+            ]
+        );
+
+        let filter = ProductFilter {
+            search: Some("sneakers".to_string()),
+            ..ProductFilter::default()
+        };
+        assert_eq!(
+            product_facets(filter, &connection).unwrap(),
+            vec![
+                Facet {
+                    variant_id: 2,
+                    name: "color".to_string(),
+                    values: vec![value("white", 1)],
+                },
+                Facet {
+                    variant_id: 1,
+                    name: "size".to_string(),
+                    values: vec![value("42", 1)],
+                },
+            ]
+        );
+
+        Ok(())
+    });
+}
+
+#[test]
+fn full_text_search_test() {
+    use dal::search::{fts_query, search_products};
+    use dal::{create_product, delete_product, update_product};
+    use diesel::result::Error;
+    use helpers::establish_connection_test;
+    use models::{
+        FormProduct, FormProductVariant, FormProductVariantComplete, NewCompleteProduct, NewProduct,
+        NewVariant, NewVariantValue,
+    };
+    let pool = establish_connection_test();
+    let connection = pool.get().expect("failed to get db connection");
+    connection.test_transaction::<_, Error, _>(|| {
+        for (name, colors) in [
+            ("running shoes", vec!["black", "white"]),
+            ("trail running shoes", vec!["green"]),
+            ("leather boots", vec!["brown"]),
+            ("shoe polish", vec![]),
+            ("<b>wax</b> & co", vec![]),
+        ] {
+            create_product(
+                NewCompleteProduct {
+                    product: NewProduct {
+                        name: name.to_string(),
+                        cost: Money::from_minor(1000),
+                        currency: "EUR".to_string(),
+                        active: true,
+                        compare_at_cost: None,
+                    },
+                    variants: vec![NewVariantValue {
+                        variant: NewVariant { name: "color".to_string() },
+                        values: colors.into_iter().map(|c| Some(c.to_string())).collect(),
+                    }],
+                    sku_matrix: None,
+                },
+                &connection,
+            )
+            .unwrap();
+        }
+        let search = |term: &str| {
+            search_products(term.to_string(), false, PageParams::default(), &connection)
+                .unwrap()
+                .items
+                .into_iter()
+                .map(|hit| hit.product.name)
+                .collect::<Vec<_>>()
+        };
+
+        // ranked by relevance, stemmed, and matching variant values too
+        assert_eq!(search("shoe"), vec!["shoe polish", "running shoes", "trail running shoes"]);
+        assert_eq!(search("\"running shoes\""), vec!["running shoes", "trail running shoes"]);
+        assert_eq!(search("lea*"), vec!["leather boots"]);
+        assert_eq!(search("black"), vec!["running shoes"]);
+        assert!(search("AND OR NOT (").is_empty());
+
+        let hit = search_products("brown".to_string(), false, PageParams::default(), &connection)
+            .unwrap()
+            .items
+            .remove(0);
+        assert_eq!(hit.highlight, "leather boots");
+        assert_eq!(hit.snippet, "<mark>brown</mark>");
+        // catalog text is escaped, only the marks are markup
+        let hit = search_products("wax".to_string(), false, PageParams::default(), &connection)
+            .unwrap()
+            .items
+            .remove(0);
+        assert_eq!(hit.highlight, "&lt;b&gt;<mark>wax</mark>&lt;/b&gt; &amp; co");
+
+        // the index follows updates and deletions
+        update_product(
+            3,
+            FormProduct {
+                product: NewProduct {
+                    name: "suede boots".to_string(),
+                    cost: Money::from_minor(1000),
+                    currency: "EUR".to_string(),
+                    active: true,
+                    compare_at_cost: None,
+                },
+                variants: vec![FormProductVariantComplete {
+                    variant: None,
+                    product_variant: FormProductVariant {
+                        id: None,
+                        variant_id: Some(1),
+                        product_id: 3,
+                        value: Some("tan".to_string()),
+                    },
+                }],
+            },
+            &connection,
+        )
+        .unwrap();
+        assert_eq!(search("suede tan"), vec!["suede boots"]);
+        assert!(search("leather").is_empty());
+        delete_product(3, &connection).unwrap();
+        assert!(search("boots").is_empty());
+
+        assert_eq!(fts_query("  "), None);
+        assert_eq!(fts_query("run* \"trail shoes\"").unwrap(), "\"run\"* \"trail shoes\"");
+
+        Ok(())
+    });
+}
+
+#[test]
+fn fuzzy_search_and_suggest_test() {
+    use dal::create_product;
+    use dal::search::{search_products, suggest_products, Suggestion};
+    use diesel::result::Error;
+    use helpers::establish_connection_test;
+    use models::{NewCompleteProduct, NewProduct};
+    let pool = establish_connection_test();
+    let connection = pool.get().expect("failed to get db connection");
+    connection.test_transaction::<_, Error, _>(|| {
+        for name in ["canvas sneakers", "adidas superstar", "running shoes", "rubber boots"] {
+            create_product(
+                NewCompleteProduct {
+                    product: NewProduct {
+                        name: name.to_string(),
+                        cost: Money::from_minor(1000),
+                        currency: "EUR".to_string(),
+                        active: true,
+                        compare_at_cost: None,
+                    },
+                    variants: vec![],
+                    sku_matrix: None,
+                },
+                &connection,
+            )
+            .unwrap();
+        }
+        let search = |term: &str, fuzzy: bool| {
+            search_products(term.to_string(), fuzzy, PageParams::default(), &connection)
+                .unwrap()
+                .items
+                .into_iter()
+                .map(|hit| hit.product.name)
+                .collect::<Vec<_>>()
+        };
+
+        assert!(search("snaekers", false).is_empty());
+        assert_eq!(search("snaekers", true), vec!["canvas sneakers"]);
+        assert_eq!(search("adidsa", true), vec!["adidas superstar"]);
+        assert_eq!(search("runnign shoes", true), vec!["running shoes"]);
+        assert!(search("xyz", true).is_empty());
+        // candidates are only looked up under the same first letter
+        assert!(search("xneakers", true).is_empty());
+
+        let suggest = |input: &str| suggest_products(input.to_string(), None, &connection).unwrap();
+        assert_eq!(
+            suggest("ru"),
+            vec![
+                Suggestion { product_id: 3, name: "running shoes".to_string() },
+                Suggestion { product_id: 4, name: "rubber boots".to_string() },
+            ]
+        );
+        assert_eq!(
+            suggest("running sh"),
+            vec![Suggestion { product_id: 3, name: "running shoes".to_string() }]
+        );
+        assert_eq!(
+            suggest("snaekers"),
+            vec![Suggestion { product_id: 1, name: "canvas sneakers".to_string() }]
+        );
+        assert_eq!(suggest_products("rubber".to_string(), Some(1), &connection).unwrap().len(), 1);
+        assert!(suggest("  ").is_empty());
+
+        Ok(())
+    });
+}
+
+#[test]
+fn synonyms_test() {
+    use dal::create_product;
+    use dal::search::search_products;
+    use dal::synonyms::{
+        create_synonym_group, delete_synonym_group, list_synonym_groups, update_synonym_group,
+    };
+    use helpers::establish_connection_test;
+    use models::{FormSynonymGroup, NewCompleteProduct, NewProduct, SynonymGroupTerms};
+    use shoe_store::errors::ApiError;
+    let pool = establish_connection_test();
+    let connection = pool.get().expect("failed to get db connection");
+
+    for name in ["leather trainers", "flip flops", "canvas sneakers"] {
+        create_product(
+            NewCompleteProduct {
+                product: NewProduct {
+                    name: name.to_string(),
+                    cost: Money::from_minor(1000),
+                    currency: "EUR".to_string(),
+                    active: true,
+                    compare_at_cost: None,
+                },
+                variants: vec![],
+                sku_matrix: None,
+            },
+            &connection,
+        )
+        .unwrap();
+    }
+    let search = |term: &str| {
+        search_products(term.to_string(), false, PageParams::default(), &connection)
+            .unwrap()
+            .items
+            .into_iter()
+            .map(|hit| hit.product.name)
+            .collect::<Vec<_>>()
+    };
+    let group = |terms: &[&str]| FormSynonymGroup {
+        terms: terms.iter().map(|term| term.to_string()).collect(),
+    };
+
+    assert_eq!(search("sneakers"), vec!["canvas sneakers"]);
+
+    let created = create_synonym_group(group(&["Sneakers", "trainers", " sneakers "]), &connection).unwrap();
+    assert_eq!(
+        created,
+        SynonymGroupTerms {
+            id: 1,
+            terms: vec!["sneakers".to_string(), "trainers".to_string()],
+        }
+    );
+    assert_eq!(search("sneakers"), vec!["leather trainers", "canvas sneakers"]);
+    assert_eq!(search("trainers"), vec!["leather trainers", "canvas sneakers"]);
+    assert_eq!(search("leather sneakers"), vec!["leather trainers"]);
+
+    create_synonym_group(group(&["sandals", "flip flops"]), &connection).unwrap();
+    assert_eq!(search("sandals"), vec!["flip flops"]);
+
+    assert!(matches!(
+        create_synonym_group(group(&["trainers", "kicks"]), &connection),
+        Err(ApiError::Conflict(_))
+    ));
+    assert!(matches!(
+        create_synonym_group(group(&["kicks", "KICKS"]), &connection),
+        Err(ApiError::Validation(_))
+    ));
+
+    update_synonym_group(1, group(&["sneakers", "kicks"]), &connection).unwrap();
+    assert_eq!(search("trainers"), vec!["leather trainers"]);
+    assert_eq!(list_synonym_groups(&connection).unwrap().len(), 2);
+
+    delete_synonym_group(2, &connection).unwrap();
+    assert!(search("sandals").is_empty());
+    assert!(matches!(delete_synonym_group(2, &connection), Err(ApiError::NotFound(_))));
+}
+
+#[test]
+fn money_test() {
+    let money = "13.23".parse::<Money>().unwrap();
+    assert_eq!(money, Money::from_minor(1323));
+    assert_eq!(money.to_string(), "13.23");
+    assert_eq!("13.2".parse::<Money>().unwrap(), Money::from_minor(1320));
+    assert_eq!("13".parse::<Money>().unwrap().to_string(), "13.00");
+    assert_eq!("-0.05".parse::<Money>().unwrap().to_string(), "-0.05");
+    for invalid in ["", ".5", "13.", "13.234", "1e3", "13,23", "--1", "99999999999999999999"] {
+        assert!(invalid.parse::<Money>().is_err(), "{:?} should not parse", invalid);
+    }
+
+    assert_eq!(serde_json::to_string(&money).unwrap(), "\"13.23\"");
+    assert_eq!(serde_json::from_str::<Money>("\"13.23\"").unwrap(), money);
+    // Numbers are read from the decimal they were written as
+    assert_eq!(serde_json::from_str::<Money>("13.23").unwrap(), money);
+    assert_eq!(serde_json::from_str::<Money>("0.07").unwrap(), Money::from_minor(7));
+    assert_eq!(serde_json::from_str::<Money>("-2").unwrap(), Money::from_minor(-200));
+    for invalid in ["13.234", "1e30", "true", "null"] {
+        assert!(serde_json::from_str::<Money>(invalid).is_err(), "{} should not deserialize", invalid);
+    }
+}
+
+#[test]
+fn prices_in_currency_test() {
+    use dal::prices::{delete_product_price, price_products, set_exchange_rate, set_product_price};
+    use dal::{create_product, show_product};
+    use models::{FormExchangeRate, FormProductPrice, NewCompleteProduct, NewProduct};
+    use shoe_store::db::money::Rate;
+    use shoe_store::errors::ApiError;
+    use helpers::establish_connection_test;
+    let pool = establish_connection_test();
+    let connection = pool.get().expect("failed to get db connection");
+
+    let product_id = create_product(
+        NewCompleteProduct {
+            product: NewProduct {
+                name: "boots".to_string(),
+                cost: Money::from_minor(1005),
+                currency: "EUR".to_string(),
+                active: true,
+                compare_at_cost: None,
+            },
+            variants: vec![],
+            sku_matrix: None,
+        },
+        &connection,
+    )
+    .unwrap();
+    let price_in = |currency: &str| {
+        let mut product = show_product(product_id, &connection).unwrap();
+        price_products(Some(&mut product), currency, &connection).map(|()| (product.cost.to_string(), product.currency))
+    };
+
+    assert_eq!(price_in("EUR").unwrap(), ("10.05".to_string(), "EUR".to_string()));
+    assert!(matches!(price_in("GBP"), Err(ApiError::Validation(_))));
+
+    let rate = FormExchangeRate { rate: "0.5".parse::<Rate>().unwrap() };
+    set_exchange_rate("eur", "GBP", rate, &connection).unwrap();
+    // 5.025 is rounded half away from zero
+    assert_eq!(price_in("GBP").unwrap(), ("5.03".to_string(), "GBP".to_string()));
+
+    let price = FormProductPrice { amount: Money::from_minor(499) };
+    set_product_price(product_id, "GBP", price.clone(), &connection).unwrap();
+    assert_eq!(price_in("GBP").unwrap().0, "4.99");
+    assert!(matches!(
+        set_product_price(product_id, "EUR", price, &connection),
+        Err(ApiError::Validation(_))
+    ));
+
+    delete_product_price(product_id, "gbp", &connection).unwrap();
+    assert_eq!(price_in("GBP").unwrap().0, "5.03");
+    assert!(matches!(
+        delete_product_price(product_id, "GBP", &connection),
+        Err(ApiError::NotFound(_))
+    ));
+
+    assert_eq!(Money::from_minor(-1005).convert("0.5".parse().unwrap()), Some(Money::from_minor(-503)));
+    assert_eq!(Money::from_minor(1004).convert("0.5".parse().unwrap()), Some(Money::from_minor(502)));
+
+    // Other prices follow the explicit price, with or without a rate
+    let product_id = create_product(
+        NewCompleteProduct {
+            product: NewProduct {
+                name: "sandals".to_string(),
+                cost: Money::from_minor(9000),
+                currency: "EUR".to_string(),
+                active: true,
+                compare_at_cost: Some(Money::from_minor(12000)),
+            },
+            variants: vec![],
+            sku_matrix: None,
+        },
+        &connection,
+    )
+    .unwrap();
+    for currency in ["GBP", "USD"] {
+        let price = FormProductPrice { amount: Money::from_minor(7000) };
+        set_product_price(product_id, currency, price, &connection).unwrap();
+        let mut product = show_product(product_id, &connection).unwrap();
+        price_products(Some(&mut product), currency, &connection).unwrap();
+        assert_eq!(
+            (product.cost, product.effective_cost, product.compare_at_cost),
+            (Money::from_minor(7000), Money::from_minor(7000), Some(Money::from_minor(9333)))
+        );
+    }
+    let eighth = Money::from_minor(-100).checked_scale(Money::from_minor(1), Money::from_minor(8));
+    assert_eq!(eighth, Some(Money::from_minor(-13)));
+    assert_eq!(Money::from_minor(100).checked_scale(Money::from_minor(1), Money::default()), None);
+}
+
+#[test]
+fn sale_pricing_test() {
+    use chrono::{Duration, Utc};
+    use dal::sales::{create_sale, delete_sale, list_sales};
+    use dal::{create_product, list_products, show_product};
+    use models::{FormProductSale, NewCompleteProduct, NewProduct};
+    use helpers::establish_connection_test;
+    let pool = establish_connection_test();
+    let connection = pool.get().expect("failed to get db connection");
+
+    let product_id = create_product(
+        NewCompleteProduct {
+            product: NewProduct {
+                name: "boots".to_string(),
+                cost: Money::from_minor(2000),
+                currency: "EUR".to_string(),
+                active: true,
+                compare_at_cost: None,
+            },
+            variants: vec![],
+            sku_matrix: None,
+        },
+        &connection,
+    )
+    .unwrap();
+    let product = show_product(product_id, &connection).unwrap();
+    assert_eq!((product.effective_cost, product.compare_at_cost), (Money::from_minor(2000), None));
+
+    let now = Utc::now().naive_utc();
+    let sale = |cost: i64, starts_in_days: i64, ends_in_days: i64| FormProductSale {
+        cost: Money::from_minor(cost),
+        starts_at: now + Duration::days(starts_in_days),
+        ends_at: now + Duration::days(ends_in_days),
+    };
+    create_sale(product_id, sale(1000, -10, -5), &connection).unwrap();
+    create_sale(product_id, sale(1500, -1, 1), &connection).unwrap();
+    let cheapest = create_sale(product_id, sale(1200, -1, 2), &connection).unwrap();
+    create_sale(product_id, sale(900, 1, 2), &connection).unwrap();
+    assert_eq!(list_sales(product_id, &connection).unwrap().len(), 4);
+
+    let product = show_product(product_id, &connection).unwrap();
+    assert_eq!(product.cost, Money::from_minor(2000));
+    assert_eq!(product.effective_cost, Money::from_minor(1200));
+    assert_eq!(product.compare_at_cost, Some(Money::from_minor(2000)));
+    let listed = list_products(ProductFilter::default(), ProductSort::default(), PageParams::default(), &connection)
+        .unwrap()
+        .items;
+    assert_eq!(listed, vec![product]);
+
+    delete_sale(product_id, cheapest.id, &connection).unwrap();
+    assert_eq!(show_product(product_id, &connection).unwrap().effective_cost, Money::from_minor(1500));
+    assert!(delete_sale(product_id + 1, cheapest.id, &connection).is_err());
+}
+
+#[test]
+fn price_history_test() {
+    use chrono::{Duration, Utc};
+    use dal::price_history::list_price_history;
+    use dal::prices::{delete_product_price, price_products, set_product_price};
+    use dal::sales::create_sale;
+    use dal::{create_product, show_product, update_product};
+    use diesel::RunQueryDsl;
+    use models::{FormProduct, FormProductPrice, FormProductSale, NewCompleteProduct, NewProduct};
+    use helpers::establish_connection_test;
+    let pool = establish_connection_test();
+    let connection = pool.get().expect("failed to get db connection");
+
+    let product = |cost: i64| NewProduct {
+        name: "boots".to_string(),
+        cost: Money::from_minor(cost),
+        currency: "EUR".to_string(),
+        active: true,
+        compare_at_cost: None,
+    };
+    let product_id = create_product(NewCompleteProduct { product: product(2000), variants: vec![], sku_matrix: None }, &connection).unwrap();
+    for cost in [2500, 2500, 3000] {
+        update_product(product_id, FormProduct { product: product(cost), variants: vec![] }, &connection).unwrap();
+    }
+    let history = list_price_history(product_id, &connection).unwrap();
+    assert_eq!(
+        history.iter().map(|change| change.cost.unwrap().minor()).collect::<Vec<_>>(),
+        vec![2000, 2500, 3000]
+    );
+    assert_eq!(show_product(product_id, &connection).unwrap().lowest_30_day_cost, Money::from_minor(2000));
+
+    // 20.00 ended before the window, 25.00 was still the price when it started
+    let now = Utc::now().naive_utc();
+    let backdate = |id: i32, days_ago: i64| {
+        diesel::sql_query(format!(
+            "UPDATE product_price_changes SET changed_at = '{}' WHERE id = {}",
+            now - Duration::days(days_ago),
+            id
+        ))
+        .execute(&*connection)
+        .unwrap();
+    };
+    backdate(history[0].id, 60);
+    backdate(history[1].id, 40);
+    assert_eq!(show_product(product_id, &connection).unwrap().lowest_30_day_cost, Money::from_minor(2500));
+
+    let sale = |cost: i64, started_days_ago: i64, ended_days_ago: i64| FormProductSale {
+        cost: Money::from_minor(cost),
+        starts_at: now - Duration::days(started_days_ago),
+        ends_at: now - Duration::days(ended_days_ago),
+    };
+    create_sale(product_id, sale(1000, 50, 45), &connection).unwrap();
+    create_sale(product_id, sale(2200, 15, 10), &connection).unwrap();
+    let product = show_product(product_id, &connection).unwrap();
+    assert_eq!(product.effective_cost, Money::from_minor(3000));
+    assert_eq!(product.lowest_30_day_cost, Money::from_minor(2200));
+
+    // Explicit prices are recorded in their own currency
+    let gbp = |amount: i64| FormProductPrice { amount: Money::from_minor(amount) };
+    set_product_price(product_id, "GBP", gbp(2000), &connection).unwrap();
+    set_product_price(product_id, "GBP", gbp(2000), &connection).unwrap();
+    let history = list_price_history(product_id, &connection).unwrap();
+    let explicit = history.last().unwrap();
+    assert_eq!((explicit.currency.as_str(), explicit.cost), ("GBP", Some(Money::from_minor(2000))));
+    assert_eq!(history.len(), 4);
+    backdate(explicit.id, 35);
+
+    // A running sale is left out, the window ending when it started
+    create_sale(product_id, sale(1800, 2, -5), &connection).unwrap();
+    set_product_price(product_id, "GBP", gbp(1000), &connection).unwrap();
+    let mut product = show_product(product_id, &connection).unwrap();
+    assert_eq!(product.effective_cost, Money::from_minor(1800));
+    assert_eq!(product.lowest_30_day_cost, Money::from_minor(2200));
+    // 22.00 was the sale at a GBP 20.00 price for a 25.00 cost
+    price_products(Some(&mut product), "GBP", &connection).unwrap();
+    assert_eq!(product.lowest_30_day_cost, Money::from_minor(1760));
+
+    delete_product_price(product_id, "GBP", &connection).unwrap();
+    let history = list_price_history(product_id, &connection).unwrap();
+    let removed = history.last().unwrap();
+    assert_eq!((removed.currency.as_str(), removed.cost), ("GBP", None));
+
+    assert!(list_price_history(product_id + 1, &connection).is_err());
+}
+
+#[test]
+fn skus_test() {
+    use dal::skus::{create_sku, delete_sku, list_skus, update_sku};
+    use dal::{create_product, delete_product, show_product};
+    use models::{FormSku, NewCompleteProduct, NewProduct, NewVariant, NewVariantValue};
+    use shoe_store::errors::ApiError;
+    use helpers::establish_connection_test;
+    let pool = establish_connection_test();
+    let connection = pool.get().expect("failed to get db connection");
+
+    let variant = |name: &str, values: &[&str]| NewVariantValue {
+        variant: NewVariant { name: name.to_string() },
+        values: values.iter().map(|value| Some(value.to_string())).collect(),
+    };
+    let product_id = create_product(
+        NewCompleteProduct {
+            product: NewProduct {
+                name: "boots".to_string(),
+                cost: Money::from_minor(2000),
+                currency: "EUR".to_string(),
+                active: true,
+                compare_at_cost: None,
+            },
+            variants: vec![variant("size", &["42", "43"]), variant("color", &["black"])],
+            sku_matrix: None,
+        },
+        &connection,
+    )
+    .unwrap();
+    // Values 1 and 2 are the sizes, 3 is the color
+    assert_eq!(show_product(product_id, &connection).unwrap().variants.len(), 2);
+    let sku = |code: &str, stock: i32, product_variant_ids: Vec<i32>| FormSku {
+        code: code.to_string(),
+        stock: Some(stock),
+        price: None,
+        allow_backorder: false,
+        product_variant_ids,
+    };
+
+    let created = create_sku(product_id, sku("BOOT-42-BLK", 3, vec![3, 1]), &connection).unwrap();
+    assert_eq!(created.code, "BOOT-42-BLK");
+    assert_eq!(
+        created.options.iter().map(|option| (option.name.as_str(), option.value.as_deref())).collect::<Vec<_>>(),
+        vec![("size", Some("42")), ("color", Some("black"))]
+    );
+
+    let error = |result: Result<_, ApiError>| match result {
+        Err(ApiError::Validation(_)) => "validation",
+        Err(ApiError::Conflict(_)) => "conflict",
+        _ => "other",
+    };
+    assert_eq!(error(create_sku(product_id, sku("BOOT-42-43", 1, vec![1, 2]), &connection)), "validation");
+    assert_eq!(error(create_sku(product_id, sku("BOOT-X", 1, vec![1, 1]), &connection)), "validation");
+    assert_eq!(error(create_sku(product_id, sku("BOOT-X", 1, vec![99]), &connection)), "validation");
+    assert_eq!(error(create_sku(product_id, sku("BOOT-X", 1, vec![1, 3]), &connection)), "conflict");
+    assert_eq!(error(create_sku(product_id, sku("BOOT-42-BLK", 1, vec![2, 3]), &connection)), "conflict");
+    assert!(matches!(
+        create_sku(product_id + 1, sku("BOOT-X", 1, vec![]), &connection),
+        Err(ApiError::NotFound(_))
+    ));
+
+    let other = create_sku(product_id, sku("BOOT-43-BLK", 0, vec![2, 3]), &connection).unwrap();
+    let mut form = sku("BOOT-43-BLACK", 0, vec![2, 3]);
+    form.price = Some(Money::from_minor(2200));
+    let updated = update_sku(other.id, form.clone(), &connection).unwrap();
+    assert_eq!((updated.stock, updated.price), (0, Some(Money::from_minor(2200))));
+    // The stock moves at a location instead
+    form.stock = Some(5);
+    assert_eq!(error(update_sku(other.id, form, &connection)), "validation");
+    assert_eq!(error(update_sku(other.id, sku("BOOT-43-BLACK", 0, vec![1, 3]), &connection)), "conflict");
+    assert_eq!(list_skus(product_id, &connection).unwrap().len(), 2);
+
+    // SKUs that had stock keep their ledger, and so does their product
+    assert!(matches!(delete_sku(created.id, &connection), Err(ApiError::Conflict(_))));
+    assert!(matches!(delete_product(product_id, &connection), Err(ApiError::Conflict(_))));
+    let unstocked = create_sku(product_id, sku("BOOT-43", 0, vec![2]), &connection).unwrap();
+    delete_sku(unstocked.id, &connection).unwrap();
+    assert!(matches!(delete_sku(unstocked.id, &connection), Err(ApiError::NotFound(_))));
+    assert_eq!(list_skus(product_id, &connection).unwrap().len(), 2);
+}
+
+#[test]
+fn sku_matrix_test() {
+    use dal::sku_matrix::{generate_sku_matrix, preview_new_product, preview_sku_matrix};
+    use dal::skus::{list_skus, update_sku};
+    use dal::create_product;
+    use models::{FormSku, NewCompleteProduct, NewProduct, NewVariant, NewVariantValue, SkuMatrix};
+    use shoe_store::errors::ApiError;
+    use std::collections::BTreeMap;
+    use helpers::establish_connection_test;
+    let pool = establish_connection_test();
+    let connection = pool.get().expect("failed to get db connection");
+
+    let variant = |name: &str, values: &[&str]| NewVariantValue {
+        variant: NewVariant { name: name.to_string() },
+        values: values.iter().map(|value| Some(value.to_string())).collect(),
+    };
+    let exclusion = |name: &str, value: &str| BTreeMap::from([(name.to_string(), value.to_string())]);
+    let matrix = SkuMatrix {
+        code_prefix: "BOOT".to_string(),
+        stock: 4,
+        price: None,
+        exclude: vec![exclusion("color", "light blue")],
+    };
+    let new_product = NewCompleteProduct {
+        product: NewProduct {
+            name: "boots".to_string(),
+            cost: Money::from_minor(2000),
+            currency: "EUR".to_string(),
+            active: true,
+            compare_at_cost: None,
+        },
+        variants: vec![variant("size", &["42", "43"]), variant("color", &["black", "light blue"])],
+        sku_matrix: Some(matrix.clone()),
+    };
+
+    let plans = preview_new_product(&new_product).unwrap();
+    assert_eq!(
+        plans.iter().map(|plan| plan.code.as_str()).collect::<Vec<_>>(),
+        vec!["BOOT-42-BLACK", "BOOT-43-BLACK"]
+    );
+    let product_id = create_product(new_product, &connection).unwrap();
+    let skus = list_skus(product_id, &connection).unwrap();
+    assert_eq!(
+        skus.iter().map(|sku| (sku.code.as_str(), sku.stock)).collect::<Vec<_>>(),
+        vec![("BOOT-42-BLACK", 4), ("BOOT-43-BLACK", 4)]
+    );
+
+    // Existing combinations are skipped, whatever their code
+    let mut matrix = SkuMatrix { exclude: vec![], ..matrix };
+    let form = FormSku {
+        code: "BOOT-42-BLK".to_string(),
+        stock: None,
+        price: None,
+        allow_backorder: false,
+        product_variant_ids: skus[0].options.iter().map(|option| option.product_variant_id).collect(),
+    };
+    update_sku(skus[0].id, form, &connection).unwrap();
+    let plans = preview_sku_matrix(product_id, &matrix, &connection).unwrap();
+    assert_eq!(
+        plans.iter().map(|plan| plan.code.as_str()).collect::<Vec<_>>(),
+        vec!["BOOT-42-LIGHT-BLUE", "BOOT-43-LIGHT-BLUE"]
+    );
+    assert_eq!(list_skus(product_id, &connection).unwrap().len(), 2);
+    assert_eq!(generate_sku_matrix(product_id, &matrix, &connection).unwrap().len(), 2);
+    assert!(generate_sku_matrix(product_id, &matrix, &connection).unwrap().is_empty());
+
+    matrix.exclude = vec![exclusion("width", "wide")];
+    assert!(matches!(preview_sku_matrix(product_id, &matrix, &connection), Err(ApiError::Validation(_))));
+    matrix.exclude = vec![exclusion("color", "red")];
+    assert!(matches!(preview_sku_matrix(product_id, &matrix, &connection), Err(ApiError::Validation(_))));
+    assert!(matches!(
+        preview_sku_matrix(product_id + 1, &matrix, &connection),
+        Err(ApiError::NotFound(_))
+    ));
+}
+
+#[test]
+fn inventory_test() {
+    use dal::inventory::{adjust_stock, list_movements};
+    use dal::skus::{create_sku, show_sku, update_sku};
+    use dal::create_product;
+    use models::{FormInventoryAdjustment, FormSku, MovementReason, NewCompleteProduct, NewProduct, DEFAULT_LOCATION_ID};
+    use shoe_store::errors::ApiError;
+    use helpers::{assert_stock_consistent, establish_connection_test};
+    let pool = establish_connection_test();
+    let connection = pool.get().expect("failed to get db connection");
+
+    let product_id = create_product(
+        NewCompleteProduct {
+            product: NewProduct {
+                name: "boots".to_string(),
+                cost: Money::from_minor(2000),
+                currency: "EUR".to_string(),
+                active: true,
+                compare_at_cost: None,
+            },
+            variants: vec![],
+            sku_matrix: None,
+        },
+        &connection,
+    )
+    .unwrap();
+    let mut form = FormSku {
+        code: "BOOT".to_string(),
+        stock: Some(5),
+        price: None,
+        allow_backorder: false,
+        product_variant_ids: vec![],
+    };
+    let sku_id = create_sku(product_id, form.clone(), &connection).unwrap().id;
+    let adjustment = |reason: MovementReason, quantity: i32| FormInventoryAdjustment {
+        location_id: DEFAULT_LOCATION_ID,
+        reason,
+        quantity,
+        note: None,
+    };
+
+    adjust_stock(sku_id, adjustment(MovementReason::Receipt, 10), &connection).unwrap();
+    let sale = adjust_stock(sku_id, adjustment(MovementReason::Sale, -12), &connection).unwrap();
+    assert_eq!((sale.reason, sale.balance), (MovementReason::Sale, 3));
+    assert!(matches!(
+        adjust_stock(sku_id, adjustment(MovementReason::Damage, -4), &connection),
+        Err(ApiError::Conflict(_))
+    ));
+    assert_eq!(show_sku(sku_id, &connection).unwrap().stock, 3);
+
+    adjust_stock(sku_id, adjustment(MovementReason::Correction, -2), &connection).unwrap();
+    form.stock = None;
+    form.allow_backorder = true;
+    update_sku(sku_id, form, &connection).unwrap();
+    let backorder = adjust_stock(sku_id, adjustment(MovementReason::Sale, -3), &connection).unwrap();
+    assert_eq!(backorder.balance, -2);
+    assert_eq!(show_sku(sku_id, &connection).unwrap().stock, -2);
+
+    let movements = list_movements(sku_id, &connection).unwrap();
+    assert_eq!(
+        movements.iter().map(|movement| (movement.quantity, movement.balance)).collect::<Vec<_>>(),
+        vec![(5, 5), (10, 15), (-12, 3), (-2, 1), (-3, -2)]
+    );
+    assert_stock_consistent(&connection);
+    assert!(matches!(
+        adjust_stock(sku_id + 1, adjustment(MovementReason::Receipt, 1), &connection),
+        Err(ApiError::NotFound(_))
+    ));
+}
+
+#[test]
+fn locations_test() {
+    use dal::inventory::{adjust_stock, list_movements, transfer_stock};
+    use dal::locations::{add_availability, create_location, delete_location, sku_stock, AvailabilityView};
+    use dal::skus::create_sku;
+    use dal::{create_product, show_product};
+    use models::{
+        FormInventoryAdjustment, FormLocation, FormSku, FormTransfer, LocationKind, MovementReason, NewCompleteProduct,
+        NewProduct, NewVariant, NewVariantValue, DEFAULT_LOCATION_ID,
+    };
+    use shoe_store::errors::ApiError;
+    use helpers::{assert_stock_consistent, establish_connection_test};
+    let pool = establish_connection_test();
+    let connection = pool.get().expect("failed to get db connection");
+
+    let shop = create_location(
+        FormLocation {
+            name: "Shop".to_string(),
+            kind: LocationKind::Store,
+        },
+        &connection,
+    )
+    .unwrap();
+    let product_id = create_product(
+        NewCompleteProduct {
+            product: NewProduct {
+                name: "boots".to_string(),
+                cost: Money::from_minor(2000),
+                currency: "EUR".to_string(),
+                active: true,
+                compare_at_cost: None,
+            },
+            variants: vec![NewVariantValue {
+                variant: NewVariant { name: "size".to_string() },
+                values: vec![Some("42".to_string()), Some("43".to_string())],
+            }],
+            sku_matrix: None,
+        },
+        &connection,
+    )
+    .unwrap();
+    let sku = |code: &str, stock: i32, allow_backorder: bool, size: i32| FormSku {
+        code: code.to_string(),
+        stock: Some(stock),
+        price: None,
+        allow_backorder,
+        product_variant_ids: vec![size],
+    };
+    let boots = create_sku(product_id, sku("BOOT-42", 5, false, 1), &connection).unwrap().id;
+    let transfer = |from_location_id: i32, to_location_id: i32, quantity: i32| FormTransfer {
+        from_location_id,
+        to_location_id,
+        quantity,
+        note: None,
+    };
+
+    let moved = transfer_stock(boots, transfer(DEFAULT_LOCATION_ID, shop.id, 2), &connection).unwrap();
+    let movements = list_movements(boots, &connection).unwrap();
+    assert_eq!(
+        movements[1..]
+            .iter()
+            .map(|movement| (movement.location_id, movement.quantity, movement.balance, movement.transfer_id))
+            .collect::<Vec<_>>(),
+        vec![(DEFAULT_LOCATION_ID, -2, 3, Some(moved.id)), (shop.id, 2, 2, Some(moved.id))]
+    );
+    assert!(matches!(
+        transfer_stock(boots, transfer(shop.id, DEFAULT_LOCATION_ID, 3), &connection),
+        Err(ApiError::Conflict(_))
+    ));
+    assert!(matches!(
+        transfer_stock(boots, transfer(shop.id, shop.id + 1, 1), &connection),
+        Err(ApiError::NotFound(_))
+    ));
+    assert_eq!(
+        sku_stock(boots, &connection).unwrap().iter().map(|stock| stock.stock).collect::<Vec<_>>(),
+        vec![3, 2]
+    );
+
+    // Backorders at the shop don't eat into the stock of other SKUs
+    let other = create_sku(product_id, sku("BOOT-43", 0, true, 2), &connection).unwrap().id;
+    let sale = FormInventoryAdjustment {
+        location_id: shop.id,
+        reason: MovementReason::Sale,
+        quantity: -1,
+        note: None,
+    };
+    assert_eq!(adjust_stock(other, sale, &connection).unwrap().balance, -1);
+    let mut product = show_product(product_id, &connection).unwrap();
+    add_availability(Some(&mut product), AvailabilityView::PerLocation, &connection).unwrap();
+    let availability = product.availability.unwrap();
+    assert_eq!(availability.stock, 5);
+    assert_eq!(
+        availability.locations.unwrap().iter().map(|stock| (stock.location_id, stock.stock)).collect::<Vec<_>>(),
+        vec![(DEFAULT_LOCATION_ID, 3), (shop.id, 2)]
+    );
+    assert_stock_consistent(&connection);
+
+    assert!(matches!(delete_location(shop.id, &connection), Err(ApiError::Conflict(_))));
+    assert!(matches!(delete_location(DEFAULT_LOCATION_ID, &connection), Err(ApiError::Conflict(_))));
+}
+
+#[test]
+fn carts_test() {
+    use chrono::{Duration, Utc};
+    use dal::carts::{add_cart_line, price_cart, remove_cart_line, update_cart_line};
+    use dal::sales::{create_sale, delete_sale};
+    use dal::skus::create_sku;
+    use dal::{create_product, delete_product, update_product};
+    use models::{
+        Cart, FormCartLine, FormProduct, FormProductSale, FormSku, NewCompleteProduct, NewProduct, NewVariant,
+        NewVariantValue,
+    };
+    use shoe_store::errors::ApiError;
+    use helpers::establish_connection_test;
+    let pool = establish_connection_test();
+    let connection = pool.get().expect("failed to get db connection");
+
+    let product = |name: &str, cost: i64| NewProduct {
+        name: name.to_string(),
+        cost: Money::from_minor(cost),
+        currency: "EUR".to_string(),
+        active: true,
+        compare_at_cost: None,
+    };
+    let boots_id = create_product(
+        NewCompleteProduct {
+            product: product("boots", 2000),
+            variants: vec![NewVariantValue {
+                variant: NewVariant { name: "size".to_string() },
+                values: vec![Some("42".to_string()), Some("43".to_string())],
+            }],
+            sku_matrix: None,
+        },
+        &connection,
+    )
+    .unwrap();
+    let laces_id = create_product(
+        NewCompleteProduct { product: product("laces", 300), variants: vec![], sku_matrix: None },
+        &connection,
+    )
+    .unwrap();
+    let sku = FormSku {
+        code: "BOOT-43".to_string(),
+        stock: Some(1),
+        price: Some(Money::from_minor(2500)),
+        allow_backorder: false,
+        product_variant_ids: vec![2],
+    };
+    let sku_id = create_sku(boots_id, sku, &connection).unwrap().id;
+
+    let line = |product_id: i32, product_variant_ids: Vec<i32>, quantity: i32| FormCartLine {
+        product_id,
+        product_variant_ids,
+        quantity,
+    };
+    let mut cart = Cart::default();
+    add_cart_line(&mut cart, line(boots_id, vec![1], 1), &connection).unwrap();
+    add_cart_line(&mut cart, line(boots_id, vec![2], 1), &connection).unwrap();
+    add_cart_line(&mut cart, line(boots_id, vec![2], 1), &connection).unwrap();
+    add_cart_line(&mut cart, line(laces_id, vec![], 3), &connection).unwrap();
+    assert!(matches!(
+        add_cart_line(&mut cart, line(boots_id, vec![1, 2], 1), &connection),
+        Err(ApiError::Validation(_))
+    ));
+
+    let priced = price_cart(&mut cart, "EUR", &connection).unwrap();
+    assert_eq!(
+        priced
+            .lines
+            .iter()
+            .map(|line| (line.id, line.sku_id, line.quantity, line.unit_price.minor(), line.total.minor()))
+            .collect::<Vec<_>>(),
+        vec![(1, None, 1, 2000, 2000), (2, Some(sku_id), 2, 2500, 5000), (3, None, 3, 300, 900)]
+    );
+    assert_eq!((priced.item_count, priced.total), (6, Money::from_minor(7900)));
+
+    // A sale takes as much off the SKU as off its product
+    let now = Utc::now().naive_utc();
+    let sale = FormProductSale {
+        cost: Money::from_minor(1600),
+        starts_at: now - Duration::days(1),
+        ends_at: now + Duration::days(1),
+    };
+    let sale_id = create_sale(boots_id, sale, &connection).unwrap().id;
+    let priced = price_cart(&mut cart, "EUR", &connection).unwrap();
+    assert_eq!(
+        priced.lines.iter().map(|line| line.unit_price.minor()).collect::<Vec<_>>(),
+        vec![1600, 2000, 300]
+    );
+    delete_sale(boots_id, sale_id, &connection).unwrap();
+
+    // Totals follow the prices in the database, not those at add time
+    let mut laces = product("laces", 350);
+    laces.active = false;
+    update_product(laces_id, FormProduct { product: laces, variants: vec![] }, &connection).unwrap();
+    assert!(matches!(
+        add_cart_line(&mut cart, line(laces_id, vec![], 1), &connection),
+        Err(ApiError::Validation(_))
+    ));
+    update_cart_line(&mut cart, 1, 2).unwrap();
+    let priced = price_cart(&mut cart, "EUR", &connection).unwrap();
+    assert_eq!(priced.lines[2].total, Money::from_minor(1050));
+    assert!(!priced.lines[2].available);
+    assert_eq!((priced.item_count, priced.total), (4, Money::from_minor(9000)));
+
+    remove_cart_line(&mut cart, 2).unwrap();
+    assert!(matches!(remove_cart_line(&mut cart, 2), Err(ApiError::NotFound(_))));
+    assert!(matches!(update_cart_line(&mut cart, 2, 1), Err(ApiError::NotFound(_))));
+    delete_product(laces_id, &connection).unwrap();
+    let priced = price_cart(&mut cart, "EUR", &connection).unwrap();
+    assert_eq!(priced.lines.len(), 1);
+    assert_eq!(cart.lines.len(), 1);
+}
+
+#[test]
+fn orders_test() {
+    use dal::carts::add_cart_line;
+    use dal::orders::{checkout, show_order};
+    use dal::skus::{create_sku, show_sku};
+    use dal::{create_product, delete_product};
+    use models::{Cart, FormCartLine, FormSku, NewCompleteProduct, NewProduct, NewVariant, NewVariantValue};
+    use shoe_store::errors::ApiError;
+    use helpers::establish_connection_test;
+    let pool = establish_connection_test();
+    let connection = pool.get().expect("failed to get db connection");
+
+    let product = |name: &str, cost: i64| NewProduct {
+        name: name.to_string(),
+        cost: Money::from_minor(cost),
+        currency: "EUR".to_string(),
+        active: true,
+        compare_at_cost: None,
+    };
+    let boots_id = create_product(
+        NewCompleteProduct {
+            product: product("boots", 2000),
+            variants: vec![NewVariantValue {
+                variant: NewVariant { name: "size".to_string() },
+                values: vec![Some("42".to_string()), Some("43".to_string())],
+            }],
+            sku_matrix: None,
+        },
+        &connection,
+    )
+    .unwrap();
+    let laces_id = create_product(
+        NewCompleteProduct { product: product("laces", 300), variants: vec![], sku_matrix: None },
+        &connection,
+    )
+    .unwrap();
+    let sku = FormSku {
+        code: "BOOT-42".to_string(),
+        stock: Some(3),
+        price: None,
+        allow_backorder: false,
+        product_variant_ids: vec![1],
+    };
+    let sku_id = create_sku(boots_id, sku, &connection).unwrap().id;
+    let line = |product_id: i32, product_variant_ids: Vec<i32>, quantity: i32| FormCartLine {
+        product_id,
+        product_variant_ids,
+        quantity,
+    };
+
+    let mut cart = Cart::default();
+    assert!(matches!(checkout(&mut cart, "EUR", None, &connection), Err(ApiError::Validation(_))));
+    add_cart_line(&mut cart, line(boots_id, vec![2], 1), &connection).unwrap();
+    assert!(matches!(checkout(&mut cart, "EUR", None, &connection), Err(ApiError::Validation(_))));
+
+    let mut cart = Cart::default();
+    add_cart_line(&mut cart, line(boots_id, vec![1], 2), &connection).unwrap();
+    add_cart_line(&mut cart, line(laces_id, vec![], 1), &connection).unwrap();
+    let order = checkout(&mut cart, "EUR", None, &connection).unwrap();
+    assert!(cart.lines.is_empty());
+    assert_eq!((order.item_count, order.total), (3, Money::from_minor(4300)));
+    assert_eq!(
+        order.lines.iter().map(|line| (line.product_name.as_str(), line.sku_id, line.total.minor())).collect::<Vec<_>>(),
+        vec![("boots", Some(sku_id), 4000), ("laces", None, 300)]
+    );
+    assert_eq!(order.lines[0].options[0].value.as_deref(), Some("42"));
+    assert_eq!(show_sku(sku_id, &connection).unwrap().stock, 1);
+
+    // Nothing is ordered when a SKU runs short
+    add_cart_line(&mut cart, line(laces_id, vec![], 1), &connection).unwrap();
+    add_cart_line(&mut cart, line(boots_id, vec![1], 2), &connection).unwrap();
+    assert!(matches!(checkout(&mut cart, "EUR", None, &connection), Err(ApiError::Conflict(_))));
+    assert_eq!(cart.lines.len(), 2);
+    assert!(matches!(show_order(order.id + 1, &connection), Err(ApiError::NotFound(_))));
+
+    assert!(matches!(delete_product(boots_id, &connection), Err(ApiError::Conflict(_))));
+    delete_product(laces_id, &connection).unwrap();
+    let order = show_order(order.id, &connection).unwrap();
+    assert_eq!((order.lines[1].product_id, order.lines[1].sku_id), (None, None));
+    assert_eq!(order.lines[1].product_name, "laces");
+}
+
+#[test]
+fn order_transitions_test() {
+    use dal::carts::add_cart_line;
+    use dal::orders::{checkout, list_order_events, transition_order};
+    use dal::payments::{create_payment, list_payments};
+    use dal::skus::{create_sku, show_sku};
+    use dal::create_product;
+    use models::{
+        Cart, FormCartLine, FormOrderTransition, FormPayment, FormSku, NewCompleteProduct, NewProduct, PaymentStatus,
+    };
+    use shoe_store::db::order_status::{OrderStatus, OrderTransition};
+    use shoe_store::errors::ApiError;
+    use shoe_store::payments::mock::{MockGateway, SOURCE_SUCCESS};
+    use helpers::establish_connection_test;
+    let pool = establish_connection_test();
+    let connection = pool.get().expect("failed to get db connection");
+    let gateway = MockGateway::new("secret");
+
+    let product = NewProduct {
+        name: "boots".to_string(),
+        cost: Money::from_minor(2000),
+        currency: "EUR".to_string(),
+        active: true,
+        compare_at_cost: None,
+    };
+    let product_id =
+        create_product(NewCompleteProduct { product, variants: vec![], sku_matrix: None }, &connection).unwrap();
+    let sku = FormSku {
+        code: "BOOT".to_string(),
+        stock: Some(5),
+        price: None,
+        allow_backorder: false,
+        product_variant_ids: vec![],
+    };
+    let sku_id = create_sku(product_id, sku, &connection).unwrap().id;
+    let transition = |transition: OrderTransition| FormOrderTransition { transition, note: None };
+    let order_of = |quantity: i32| {
+        let mut cart = Cart::default();
+        let line = FormCartLine { product_id, product_variant_ids: vec![], quantity };
+        add_cart_line(&mut cart, line, &connection).unwrap();
+        checkout(&mut cart, "EUR", None, &connection).unwrap()
+    };
+
+    let shipped = order_of(2);
+    assert_eq!(shipped.status, OrderStatus::Pending);
+    assert!(matches!(
+        transition_order(shipped.id, transition(OrderTransition::Ship), &gateway, &connection),
+        Err(ApiError::Conflict(_))
+    ));
+    // Only a payment pays an order
+    assert!(matches!(
+        transition_order(shipped.id, transition(OrderTransition::Pay), &gateway, &connection),
+        Err(ApiError::Validation(_))
+    ));
+    let pay = || FormPayment { source: SOURCE_SUCCESS.to_string() };
+    create_payment(shipped.id, pay(), &gateway, &connection).unwrap();
+    for (step, status) in [(OrderTransition::Pack, OrderStatus::Packed), (OrderTransition::Ship, OrderStatus::Shipped)] {
+        assert_eq!(transition_order(shipped.id, transition(step), &gateway, &connection).unwrap().order.status, status);
+    }
+    assert!(matches!(
+        transition_order(shipped.id, transition(OrderTransition::Cancel), &gateway, &connection),
+        Err(ApiError::Conflict(_))
+    ));
+    let events = list_order_events(shipped.id, &connection).unwrap();
+    assert_eq!(
+        events.iter().map(|event| (event.from_status, event.to_status)).collect::<Vec<_>>(),
+        vec![
+            (None, OrderStatus::Pending),
+            (Some(OrderStatus::Pending), OrderStatus::Paid),
+            (Some(OrderStatus::Paid), OrderStatus::Packed),
+            (Some(OrderStatus::Packed), OrderStatus::Shipped),
+        ]
+    );
+
+    // Cancelling puts the stock back, shipping doesn't
+    let cancelled = order_of(1);
+    assert_eq!(show_sku(sku_id, &connection).unwrap().stock, 2);
+    let form = FormOrderTransition { transition: OrderTransition::Cancel, note: Some("changed my mind".to_string()) };
+    let order = transition_order(cancelled.id, form, &gateway, &connection).unwrap().order;
+    assert_eq!(order.status, OrderStatus::Cancelled);
+    assert_eq!(show_sku(sku_id, &connection).unwrap().stock, 3);
+    let events = list_order_events(cancelled.id, &connection).unwrap();
+    assert_eq!(events[1].note.as_deref(), Some("changed my mind"));
+    assert!(matches!(
+        transition_order(cancelled.id, transition(OrderTransition::Refund), &gateway, &connection),
+        Err(ApiError::Conflict(_))
+    ));
+    assert_eq!(show_sku(sku_id, &connection).unwrap().stock, 3);
+    assert!(matches!(list_order_events(cancelled.id + 1, &connection), Err(ApiError::NotFound(_))));
+
+    // Cancelling a paid order refunds it
+    let paid = order_of(1);
+    create_payment(paid.id, pay(), &gateway, &connection).unwrap();
+    let order = transition_order(paid.id, transition(OrderTransition::Cancel), &gateway, &connection).unwrap().order;
+    assert_eq!(order.status, OrderStatus::Cancelled);
+    let payment = &list_payments(paid.id, &connection).unwrap()[0];
+    assert_eq!((payment.status, payment.refunded), (PaymentStatus::Refunded, Money::from_minor(2000)));
+
+    // So does refunding a packed order, which is restocked as it never shipped
+    let packed = order_of(1);
+    create_payment(packed.id, pay(), &gateway, &connection).unwrap();
+    transition_order(packed.id, transition(OrderTransition::Pack), &gateway, &connection).unwrap();
+    assert_eq!(show_sku(sku_id, &connection).unwrap().stock, 2);
+    let order = transition_order(packed.id, transition(OrderTransition::Refund), &gateway, &connection).unwrap().order;
+    assert_eq!(order.status, OrderStatus::Refunded);
+    assert_eq!(show_sku(sku_id, &connection).unwrap().stock, 3);
+    let payment = &list_payments(packed.id, &connection).unwrap()[0];
+    assert_eq!(payment.status, PaymentStatus::Refunded);
+}
+
+#[test]
+fn payments_test() {
+    use dal::carts::add_cart_line;
+    use dal::orders::{checkout, show_order, transition_order};
+    use dal::payments::{create_payment, handle_webhook, list_payments, list_refunds, refund_payment, show_payment};
+    use dal::create_product;
+    use models::{
+        Cart, FormCartLine, FormOrderTransition, FormPayment, FormRefund, NewCompleteProduct, NewProduct, PaymentStatus,
+        RefundStatus,
+    };
+    use shoe_store::db::order_status::{OrderStatus, OrderTransition};
+    use shoe_store::errors::ApiError;
+    use shoe_store::payments::mock::{MockGateway, SOURCE_3DS, SOURCE_DECLINE, SOURCE_SUCCESS};
+    use shoe_store::payments::{Authorization, PaymentGateway, WebhookEvent};
+    use helpers::establish_connection_test;
+    let pool = establish_connection_test();
+    let connection = pool.get().expect("failed to get db connection");
+    let gateway = MockGateway::new("secret");
+
+    let product = NewProduct {
+        name: "laces".to_string(),
+        cost: Money::from_minor(500),
+        currency: "EUR".to_string(),
+        active: true,
+        compare_at_cost: None,
+    };
+    let product_id =
+        create_product(NewCompleteProduct { product, variants: vec![], sku_matrix: None }, &connection).unwrap();
+    let mut cart = Cart::default();
+    let line = FormCartLine { product_id, product_variant_ids: vec![], quantity: 2 };
+    add_cart_line(&mut cart, line, &connection).unwrap();
+    let order = checkout(&mut cart, "EUR", None, &connection).unwrap();
+    let pay = |source: &str| FormPayment { source: source.to_string() };
+
+    let declined = create_payment(order.id, pay(SOURCE_DECLINE), &gateway, &connection).unwrap();
+    assert_eq!((declined.status, declined.amount), (PaymentStatus::Declined, Money::from_minor(1000)));
+    assert_eq!(show_order(order.id, &connection).unwrap().status, OrderStatus::Pending);
+    assert!(matches!(create_payment(order.id, pay("card"), &gateway, &connection), Err(ApiError::Validation(_))));
+
+    let challenged = create_payment(order.id, pay(SOURCE_3DS), &gateway, &connection).unwrap();
+    assert_eq!(challenged.status, PaymentStatus::RequiresAction);
+    assert!(matches!(
+        create_payment(order.id, pay(SOURCE_SUCCESS), &gateway, &connection),
+        Err(ApiError::Conflict(_))
+    ));
+    let webhook = serde_json::json!({ "reference": challenged.reference, "status": "authorized" }).to_string();
+    assert!(matches!(
+        handle_webhook(webhook.as_bytes(), "forged", &gateway, &connection),
+        Err(ApiError::Validation(_))
+    ));
+    let captured = handle_webhook(webhook.as_bytes(), "secret", &gateway, &connection).unwrap();
+    assert_eq!(captured.status, PaymentStatus::Captured);
+    assert_eq!(handle_webhook(webhook.as_bytes(), "secret", &gateway, &connection).unwrap(), captured);
+    assert_eq!(show_order(order.id, &connection).unwrap().status, OrderStatus::Paid);
+    assert!(matches!(
+        create_payment(order.id, pay(SOURCE_SUCCESS), &gateway, &connection),
+        Err(ApiError::Conflict(_))
+    ));
+
+    let refund = |amount: Option<i64>| FormRefund { amount: amount.map(Money::from_minor) };
+    let partly = refund_payment(captured.id, refund(Some(300)), &gateway, &connection).unwrap();
+    assert_eq!((partly.status, partly.refunded), (PaymentStatus::Captured, Money::from_minor(300)));
+    assert_eq!(show_order(order.id, &connection).unwrap().status, OrderStatus::Paid);
+    assert!(matches!(
+        refund_payment(captured.id, refund(Some(800)), &gateway, &connection),
+        Err(ApiError::Validation(_))
+    ));
+    let refunded = refund_payment(captured.id, refund(None), &gateway, &connection).unwrap();
+    assert_eq!((refunded.status, refunded.refunded), (PaymentStatus::Refunded, Money::from_minor(1000)));
+    assert_eq!(show_order(order.id, &connection).unwrap().status, OrderStatus::Refunded);
+    assert!(matches!(refund_payment(declined.id, refund(None), &gateway, &connection), Err(ApiError::Conflict(_))));
+
+    let statuses = list_payments(order.id, &connection).unwrap().iter().map(|payment| payment.status).collect::<Vec<_>>();
+    assert_eq!(statuses, vec![PaymentStatus::Declined, PaymentStatus::Refunded]);
+
+    // A gateway that is down for one operation
+    struct Failing(MockGateway, &'static str);
+    impl Failing {
+        fn fail(&self, operation: &str) -> Result<(), ApiError> {
+            if operation == self.1 {
+                return Err(ApiError::Internal("the gateway is down".to_string()));
+            }
+            Ok(())
+        }
+    }
+    impl PaymentGateway for Failing {
+        fn name(&self) -> &'static str {
+            self.0.name()
+        }
+        fn authorize(&self, amount: Money, currency: &str, source: &str) -> Result<Authorization, ApiError> {
+            self.0.authorize(amount, currency, source)
+        }
+        fn capture(&self, reference: &str, amount: Money, currency: &str) -> Result<(), ApiError> {
+            self.fail("capture")?;
+            self.0.capture(reference, amount, currency)
+        }
+        fn void(&self, reference: &str) -> Result<(), ApiError> {
+            self.0.void(reference)
+        }
+        fn refund(&self, reference: &str, amount: Money, currency: &str) -> Result<(), ApiError> {
+            self.fail("refund")?;
+            self.0.refund(reference, amount, currency)
+        }
+        fn verify_webhook(&self, payload: &[u8], signature: &str) -> Result<WebhookEvent, ApiError> {
+            self.0.verify_webhook(payload, signature)
+        }
+    }
+    let order_of = |cart: &mut Cart| {
+        let line = FormCartLine { product_id, product_variant_ids: vec![], quantity: 2 };
+        add_cart_line(cart, line, &connection).unwrap();
+        checkout(cart, "EUR", None, &connection).unwrap()
+    };
+
+    // A capture the gateway fails leaves the payment as it was, to be
+    // captured again
+    let order = order_of(&mut cart);
+    let challenged = create_payment(order.id, pay(SOURCE_3DS), &gateway, &connection).unwrap();
+    let webhook = serde_json::json!({ "reference": challenged.reference, "status": "authorized" }).to_string();
+    let uncapturable = Failing(MockGateway::new("secret"), "capture");
+    assert!(matches!(
+        handle_webhook(webhook.as_bytes(), "secret", &uncapturable, &connection),
+        Err(ApiError::Internal(_))
+    ));
+    assert_eq!(show_payment(challenged.id, &connection).unwrap().status, PaymentStatus::RequiresAction);
+    assert_eq!(show_order(order.id, &connection).unwrap().status, OrderStatus::Pending);
+    let captured = handle_webhook(webhook.as_bytes(), "secret", &gateway, &connection).unwrap();
+    assert_eq!(captured.status, PaymentStatus::Captured);
+    assert_eq!(show_order(order.id, &connection).unwrap().status, OrderStatus::Paid);
+
+    // A refund the gateway fails is recorded and can be made again
+    let order = order_of(&mut cart);
+    let captured = create_payment(order.id, pay(SOURCE_SUCCESS), &gateway, &connection).unwrap();
+    assert!(matches!(
+        refund_payment(captured.id, refund(Some(200)), &Failing(MockGateway::new("secret"), "refund"), &connection),
+        Err(ApiError::Internal(_))
+    ));
+    let payment = show_payment(captured.id, &connection).unwrap();
+    assert_eq!((payment.status, payment.refunded), (PaymentStatus::Captured, Money::default()));
+    refund_payment(captured.id, refund(Some(200)), &gateway, &connection).unwrap();
+    let refunds = list_refunds(captured.id, &connection).unwrap();
+    assert_eq!(
+        refunds.iter().map(|refund| (refund.amount, refund.status)).collect::<Vec<_>>(),
+        vec![(Money::from_minor(200), RefundStatus::Failed), (Money::from_minor(200), RefundStatus::Succeeded)]
+    );
+
+    // An order is cancelled all the same, the refunds that failed are returned
+    let order = order_of(&mut cart);
+    let captured = create_payment(order.id, pay(SOURCE_SUCCESS), &gateway, &connection).unwrap();
+    let cancel = FormOrderTransition { transition: OrderTransition::Cancel, note: None };
+    let cancelled = transition_order(order.id, cancel, &Failing(MockGateway::new("secret"), "refund"), &connection).unwrap();
+    assert_eq!(cancelled.order.status, OrderStatus::Cancelled);
+    assert_eq!(
+        cancelled.failed_refunds.iter().map(|refund| (refund.payment_id, refund.status)).collect::<Vec<_>>(),
+        vec![(captured.id, RefundStatus::Failed)]
+    );
+    let payment = show_payment(captured.id, &connection).unwrap();
+    assert_eq!((payment.status, payment.refunded), (PaymentStatus::Captured, Money::default()));
+
+    // A challenge passed once the order is cancelled doesn't collect anything
+    let order = order_of(&mut cart);
+    let challenged = create_payment(order.id, pay(SOURCE_3DS), &gateway, &connection).unwrap();
+    let cancel = FormOrderTransition { transition: OrderTransition::Cancel, note: None };
+    transition_order(order.id, cancel, &gateway, &connection).unwrap();
+    let webhook = serde_json::json!({ "reference": challenged.reference, "status": "authorized" }).to_string();
+    let voided = handle_webhook(webhook.as_bytes(), "secret", &gateway, &connection).unwrap();
+    assert_eq!(voided.status, PaymentStatus::Voided);
+    assert_eq!(handle_webhook(webhook.as_bytes(), "secret", &gateway, &connection).unwrap(), voided);
+    assert_eq!(show_order(order.id, &connection).unwrap().status, OrderStatus::Cancelled);
+}
+
+#[test]
+fn returns_test() {
+    use dal::carts::add_cart_line;
+    use dal::orders::{checkout, show_order, transition_order};
+    use dal::carts::apply_promo_code;
+    use dal::payments::{create_payment, list_payments};
+    use dal::promotions::create_promo_code;
+    use dal::returns::{approve_return, create_return, list_returns, receive_return, refund_return, reject_return};
+    use dal::skus::{create_sku, show_sku};
+    use dal::create_product;
+    use models::{
+        Cart, FormCartLine, FormOrderTransition, FormPayment, FormPromoCode, FormReturn, FormReturnLine,
+        FormReturnReceipt, FormSku, NewCompleteProduct, NewProduct, PaymentStatus, ReturnReason, ReturnStatus,
+        DEFAULT_LOCATION_ID,
+    };
+    use shoe_store::db::order_status::{OrderStatus, OrderTransition};
+    use shoe_store::errors::ApiError;
+    use shoe_store::payments::mock::{MockGateway, SOURCE_SUCCESS};
+    use helpers::establish_connection_test;
+    let pool = establish_connection_test();
+    let connection = pool.get().expect("failed to get db connection");
+    let gateway = MockGateway::new("secret");
+
+    let product = |name: &str, cost: i64| NewProduct {
+        name: name.to_string(),
+        cost: Money::from_minor(cost),
+        currency: "EUR".to_string(),
+        active: true,
+        compare_at_cost: None,
+    };
+    let boots_id = create_product(
+        NewCompleteProduct { product: product("boots", 2000), variants: vec![], sku_matrix: None },
+        &connection,
+    )
+    .unwrap();
+    let laces_id = create_product(
+        NewCompleteProduct { product: product("laces", 300), variants: vec![], sku_matrix: None },
+        &connection,
+    )
+    .unwrap();
+    let sku = FormSku {
+        code: "BOOT".to_string(),
+        stock: Some(5),
+        price: None,
+        allow_backorder: false,
+        product_variant_ids: vec![],
+    };
+    let sku_id = create_sku(boots_id, sku, &connection).unwrap().id;
+    let mut cart = Cart::default();
+    for (product_id, quantity) in [(boots_id, 3), (laces_id, 1)] {
+        let line = FormCartLine { product_id, product_variant_ids: vec![], quantity };
+        add_cart_line(&mut cart, line, &connection).unwrap();
+    }
+    let order = checkout(&mut cart, "EUR", None, &connection).unwrap();
+    let (boots_line, laces_line) = (order.lines[0].id, order.lines[1].id);
+    let form = |lines: Vec<(i32, i32, ReturnReason)>| FormReturn {
+        lines: lines
+            .into_iter()
+            .map(|(order_line_id, quantity, reason)| FormReturnLine { order_line_id, quantity, reason })
+            .collect(),
+        note: None,
+    };
+
+    assert!(matches!(
+        create_return(order.id, form(vec![(boots_line, 1, ReturnReason::TooSmall)]), &connection),
+        Err(ApiError::Conflict(_))
+    ));
+    let source = FormPayment { source: SOURCE_SUCCESS.to_string() };
+    create_payment(order.id, source, &gateway, &connection).unwrap();
+    for transition in [OrderTransition::Pack, OrderTransition::Ship] {
+        transition_order(order.id, FormOrderTransition { transition, note: None }, &gateway, &connection).unwrap();
+    }
+    assert!(matches!(
+        create_return(order.id, form(vec![(boots_line, 4, ReturnReason::TooSmall)]), &connection),
+        Err(ApiError::Validation(_))
+    ));
+    assert!(matches!(
+        create_return(order.id, form(vec![(boots_line + 10, 1, ReturnReason::TooSmall)]), &connection),
+        Err(ApiError::Validation(_))
+    ));
+
+    let lines = vec![(boots_line, 2, ReturnReason::TooSmall), (laces_line, 1, ReturnReason::Defective)];
+    let request = create_return(order.id, form(lines), &connection).unwrap();
+    assert_eq!((request.status, request.refund), (ReturnStatus::Requested, Money::from_minor(4300)));
+    let rejected = create_return(order.id, form(vec![(boots_line, 1, ReturnReason::TooLarge)]), &connection).unwrap();
+    assert!(matches!(
+        create_return(order.id, form(vec![(boots_line, 1, ReturnReason::TooLarge)]), &connection),
+        Err(ApiError::Validation(_))
+    ));
+    assert_eq!(reject_return(rejected.id, &connection).unwrap().status, ReturnStatus::Rejected);
+
+    let receipt = FormReturnReceipt { location_id: DEFAULT_LOCATION_ID };
+    assert!(matches!(receive_return(request.id, receipt.clone(), &connection), Err(ApiError::Conflict(_))));
+    assert_eq!(approve_return(request.id, &connection).unwrap().status, ReturnStatus::Approved);
+    assert!(matches!(refund_return(request.id, &gateway, &connection), Err(ApiError::Conflict(_))));
+    assert_eq!(receive_return(request.id, receipt.clone(), &connection).unwrap().status, ReturnStatus::Received);
+    assert_eq!(show_sku(sku_id, &connection).unwrap().stock, 4);
+    assert_eq!(refund_return(request.id, &gateway, &connection).unwrap().status, ReturnStatus::Refunded);
+    let payment = &list_payments(order.id, &connection).unwrap()[0];
+    assert_eq!((payment.status, payment.refunded), (PaymentStatus::Captured, Money::from_minor(4300)));
+    assert_eq!(show_order(order.id, &connection).unwrap().status, OrderStatus::Shipped);
+
+    // Returning the last item refunds the order
+    let last = create_return(order.id, form(vec![(boots_line, 1, ReturnReason::TooLarge)]), &connection).unwrap();
+    approve_return(last.id, &connection).unwrap();
+    receive_return(last.id, receipt, &connection).unwrap();
+    refund_return(last.id, &gateway, &connection).unwrap();
+    let payment = &list_payments(order.id, &connection).unwrap()[0];
+    assert_eq!((payment.status, payment.refunded), (PaymentStatus::Refunded, Money::from_minor(6300)));
+    assert_eq!(show_order(order.id, &connection).unwrap().status, OrderStatus::Refunded);
+    assert_eq!(show_sku(sku_id, &connection).unwrap().stock, 5);
+    let statuses = list_returns(order.id, &connection).unwrap().iter().map(|request| request.status).collect::<Vec<_>>();
+    assert_eq!(statuses, vec![ReturnStatus::Refunded, ReturnStatus::Rejected, ReturnStatus::Refunded]);
+
+    // A line returned in several goes is refunded all that was paid for it
+    let socks_id = create_product(
+        NewCompleteProduct { product: product("socks", 1000), variants: vec![], sku_matrix: None },
+        &connection,
+    )
+    .unwrap();
+    let promo = FormPromoCode {
+        code: "SOCKS".to_string(),
+        percent_off: None,
+        amount_off: Some(Money::from_minor(100)),
+        currency: "EUR".to_string(),
+        min_cart_total: None,
+        usage_limit: None,
+        per_customer_limit: None,
+        starts_at: None,
+        ends_at: None,
+        active: true,
+        product_ids: vec![],
+        variant_rules: vec![],
+    };
+    create_promo_code(promo, &connection).unwrap();
+    let line = FormCartLine { product_id: socks_id, product_variant_ids: vec![], quantity: 3 };
+    add_cart_line(&mut cart, line, &connection).unwrap();
+    apply_promo_code(&mut cart, "SOCKS", "EUR", &connection).unwrap();
+    let order = checkout(&mut cart, "EUR", None, &connection).unwrap();
+    let source = FormPayment { source: SOURCE_SUCCESS.to_string() };
+    create_payment(order.id, source, &gateway, &connection).unwrap();
+    for transition in [OrderTransition::Pack, OrderTransition::Ship] {
+        transition_order(order.id, FormOrderTransition { transition, note: None }, &gateway, &connection).unwrap();
+    }
+    let mut refunds = Vec::new();
+    for _ in 0..3 {
+        let request = create_return(order.id, form(vec![(order.lines[0].id, 1, ReturnReason::TooSmall)]), &connection);
+        let id = request.unwrap().id;
+        approve_return(id, &connection).unwrap();
+        receive_return(id, FormReturnReceipt { location_id: DEFAULT_LOCATION_ID }, &connection).unwrap();
+        refunds.push(refund_return(id, &gateway, &connection).unwrap().refund.minor());
+    }
+    assert_eq!(refunds, vec![966, 967, 967]);
+    let payment = &list_payments(order.id, &connection).unwrap()[0];
+    assert_eq!((payment.status, payment.refunded), (PaymentStatus::Refunded, Money::from_minor(2900)));
+}
+
+#[test]
+fn promotions_test() {
+    use chrono::{Duration, Utc};
+    use dal::carts::{add_cart_line, apply_promo_code, price_cart};
+    use dal::orders::{checkout, transition_order};
+    use dal::promotions::{create_promo_code, delete_promo_code, show_promo_code, update_promo_code};
+    use dal::create_product;
+    use models::{
+        Cart, FormCartLine, FormOrderTransition, FormPromoCode, FormVariantRule, NewCompleteProduct, NewProduct,
+        NewVariant, NewVariantValue,
+    };
+    use shoe_store::db::order_status::{OrderStatus, OrderTransition};
+    use shoe_store::errors::ApiError;
+    use shoe_store::payments::mock::MockGateway;
+    use helpers::establish_connection_test;
+    let pool = establish_connection_test();
+    let connection = pool.get().expect("failed to get db connection");
+    let gateway = MockGateway::new("secret");
+
+    let product = |name: &str, cost: i64| NewProduct {
+        name: name.to_string(),
+        cost: Money::from_minor(cost),
+        currency: "EUR".to_string(),
+        active: true,
+        compare_at_cost: None,
+    };
+    let boots_id = create_product(
+        NewCompleteProduct {
+            product: product("boots", 3000),
+            variants: vec![NewVariantValue {
+                variant: NewVariant { name: "size".to_string() },
+                values: vec![Some("44".to_string()), Some("46".to_string()), Some("47.5".to_string())],
+            }],
+            sku_matrix: None,
+        },
+        &connection,
+    )
+    .unwrap();
+    let laces_id = create_product(
+        NewCompleteProduct { product: product("laces", 300), variants: vec![], sku_matrix: None },
+        &connection,
+    )
+    .unwrap();
+    let promo = |code: &str| FormPromoCode {
+        code: code.to_string(),
+        percent_off: None,
+        amount_off: None,
+        currency: "EUR".to_string(),
+        min_cart_total: None,
+        usage_limit: None,
+        per_customer_limit: None,
+        starts_at: None,
+        ends_at: None,
+        active: true,
+        product_ids: vec![],
+        variant_rules: vec![],
+    };
+    let big_sizes = create_promo_code(
+        FormPromoCode {
+            percent_off: Some(20),
+            min_cart_total: Some(Money::from_minor(5000)),
+            usage_limit: Some(2),
+            per_customer_limit: Some(1),
+            variant_rules: vec![FormVariantRule {
+                variant: "size".to_string(),
+                value: None,
+                min: Some(46.0),
+                max: None,
+            }],
+            ..promo(" big20 ")
+        },
+        &connection,
+    )
+    .unwrap();
+    assert_eq!(big_sizes.code, "BIG20");
+    let laces_only =
+        FormPromoCode { amount_off: Some(Money::from_minor(500)), product_ids: vec![laces_id], ..promo("LACES") };
+    create_promo_code(laces_only, &connection).unwrap();
+    let tomorrow = Utc::now().naive_utc() + Duration::days(1);
+    let later = FormPromoCode { percent_off: Some(10), starts_at: Some(tomorrow), ..promo("LATER") };
+    create_promo_code(later, &connection).unwrap();
+    let ten = FormPromoCode { amount_off: Some(Money::from_minor(1000)), ..promo("TEN") };
+    let ten_off = create_promo_code(ten, &connection).unwrap();
+
+    let cart_of = |lines: &[(i32, Vec<i32>)]| {
+        let mut cart = Cart::default();
+        for (product_id, product_variant_ids) in lines {
+            let product_variant_ids = product_variant_ids.clone();
+            let line = FormCartLine { product_id: *product_id, product_variant_ids, quantity: 1 };
+            add_cart_line(&mut cart, line, &connection).unwrap();
+        }
+        cart
+    };
+    let rejection = |cart: &mut Cart, code: &str| match apply_promo_code(cart, code, "EUR", &connection) {
+        Err(ApiError::Validation(reason)) => reason,
+        other => panic!("{} applied: {:?}", code, other),
+    };
+    let full = [(boots_id, vec![1]), (boots_id, vec![2]), (laces_id, vec![])];
+
+    let mut cart = cart_of(&full);
+    assert_eq!(rejection(&mut cart, "NOPE"), "promo code NOPE doesn't exist");
+    assert_eq!(rejection(&mut cart, "later"), format!("promo code LATER is only valid from {}", tomorrow));
+    assert!(cart.promo_code.is_none());
+    let priced = apply_promo_code(&mut cart, "big20", "EUR", &connection).unwrap();
+    assert_eq!(cart.promo_code.as_deref(), Some("BIG20"));
+    assert_eq!(priced.lines.iter().map(|line| line.discount.minor()).collect::<Vec<_>>(), vec![0, 600, 0]);
+    assert_eq!(
+        (priced.subtotal, priced.discount, priced.total),
+        (Money::from_minor(6300), Money::from_minor(600), Money::from_minor(5700))
+    );
+
+    // Per customer and overall limits are counted on orders, cancelled ones aside
+    assert!(matches!(checkout(&mut cart, "EUR", None, &connection), Err(ApiError::Validation(_))));
+    let order = checkout(&mut cart, "EUR", Some(" Ann@example.com"), &connection).unwrap();
+    assert_eq!((order.promo_code.as_deref(), order.discount), (Some("BIG20"), Money::from_minor(600)));
+    assert_eq!((order.total, order.customer_email.as_deref()), (Money::from_minor(5700), Some("ann@example.com")));
+    assert_eq!(order.lines[1].discount, Money::from_minor(600));
+    let mut cart = cart_of(&full);
+    apply_promo_code(&mut cart, "BIG20", "EUR", &connection).unwrap();
+    assert!(matches!(checkout(&mut cart, "EUR", Some("ANN@example.com "), &connection), Err(ApiError::Validation(_))));
+    checkout(&mut cart, "EUR", Some("bob@example.com"), &connection).unwrap();
+    let mut cart = cart_of(&full);
+    assert_eq!(rejection(&mut cart, "BIG20"), "promo code BIG20 has been used up");
+    let cancel = FormOrderTransition { transition: OrderTransition::Cancel, note: None };
+    transition_order(order.id, cancel, &gateway, &connection).unwrap();
+    assert_eq!(show_promo_code(big_sizes.id, &connection).unwrap().uses, 1);
+    apply_promo_code(&mut cart, "BIG20", "EUR", &connection).unwrap();
+
+    // A code that stops applying stays on the cart with the reason
+    let mut cart = cart_of(&[(boots_id, vec![3]), (laces_id, vec![])]);
+    assert_eq!(rejection(&mut cart, "BIG20"), "promo code BIG20 needs a cart of at least 50.00 EUR");
+    let mut cart = cart_of(&[(boots_id, vec![3]), (boots_id, vec![1])]);
+    apply_promo_code(&mut cart, "BIG20", "EUR", &connection).unwrap();
+    cart.lines.remove(1);
+    let priced = price_cart(&mut cart, "EUR", &connection).unwrap();
+    assert_eq!(priced.discount, Money::default());
+    assert!(priced.promo_rejection.unwrap().contains("needs a cart of at least"));
+    assert!(matches!(checkout(&mut cart, "EUR", Some("cy@example.com"), &connection), Err(ApiError::Validation(_))));
+
+    // Fixed amounts never exceed what they apply to, and are spread over lines
+    let mut cart = cart_of(&[(boots_id, vec![1]), (laces_id, vec![])]);
+    let priced = apply_promo_code(&mut cart, "laces", "EUR", &connection).unwrap();
+    assert_eq!(priced.lines.iter().map(|line| line.discount.minor()).collect::<Vec<_>>(), vec![0, 300]);
+    let priced = apply_promo_code(&mut cart, "ten", "EUR", &connection).unwrap();
+    assert_eq!(priced.lines.iter().map(|line| line.discount.minor()).collect::<Vec<_>>(), vec![910, 90]);
+    assert_eq!(priced.total, Money::from_minor(2300));
+
+    // An order left with nothing to pay is paid without a payment
+    let mut cart = cart_of(&[(laces_id, vec![])]);
+    apply_promo_code(&mut cart, "laces", "EUR", &connection).unwrap();
+    let order = checkout(&mut cart, "EUR", None, &connection).unwrap();
+    assert_eq!((order.total, order.status), (Money::default(), OrderStatus::Paid));
+
+    let inactive = FormPromoCode { active: false, amount_off: Some(Money::from_minor(1000)), ..promo("TEN") };
+    update_promo_code(ten_off.id, inactive, &connection).unwrap();
+    assert_eq!(rejection(&mut cart, "TEN"), "promo code TEN is no longer valid");
+    delete_promo_code(big_sizes.id, &connection).unwrap();
+    assert!(matches!(show_promo_code(big_sizes.id, &connection), Err(ApiError::NotFound(_))));
+}
@@ -1,3 +1,4 @@
+use diesel::{dsl::sql, sql_types::BigInt, sqlite::SqliteConnection, RunQueryDsl};
 use diesel_migrations::run_pending_migrations;
 use shoe_store::db::connect::{establish_connection, DbPool};
 
@@ -8,3 +9,24 @@ pub fn establish_connection_test() -> DbPool {
     run_pending_migrations(&conn).expect("failed to run migrations");
     pool
 }
+
+/// Checks that the stock of every SKU is the sum of its stock at each
+/// location, and that each of those is the balance of the last movement of
+/// the SKU there.
+pub fn assert_stock_consistent(conn: &SqliteConnection) {
+    let count = |query: &str| diesel::select(sql::<BigInt>(query)).get_result::<i64>(conn).unwrap();
+    let skus = count(
+        "(SELECT COUNT(*) FROM skus
+          WHERE stock <> (SELECT COALESCE(SUM(stock), 0) FROM location_stocks WHERE sku_id = skus.id))",
+    );
+    assert_eq!(skus, 0, "skus whose stock isn't the sum over locations");
+    let locations = count(
+        "(SELECT COUNT(*) FROM location_stocks
+          WHERE stock <> COALESCE(
+              (SELECT balance FROM inventory_movements
+               WHERE sku_id = location_stocks.sku_id AND location_id = location_stocks.location_id
+               ORDER BY id DESC LIMIT 1),
+              0))",
+    );
+    assert_eq!(locations, 0, "stocks at a location that aren't the last balance there");
+}